@@ -298,6 +298,99 @@ pub fn untgz_async<P: AsRef<Path> + ToOwned>(
         })
 }
 
+/// Decompresses gzip stream chunk by chunk, so memory use does not depend
+/// on the size of the archive.
+pub fn gunzip_stream<GzStream>(stream: GzStream) -> impl Stream<Item = bytes::Bytes, Error = String>
+where
+    GzStream: Stream<Item = bytes::Bytes, Error = String>,
+{
+    gunzip_stream_with(stream, gzip_error)
+}
+
+/// Compresses stream with gzip, chunk by chunk.
+pub fn gzip_stream<S>(stream: S) -> impl Stream<Item = bytes::Bytes, Error = String>
+where
+    S: Stream<Item = bytes::Bytes, Error = String>,
+{
+    gzip_stream_with(stream, gzip_error)
+}
+
+fn gzip_error(e: io::Error) -> String {
+    format!("gzip: {}", e)
+}
+
+/// Same as `gunzip_stream`, for any stream error; `io_error` converts
+/// decompression failures into it.
+pub fn gunzip_stream_with<S>(
+    stream: S,
+    io_error: fn(io::Error) -> S::Error,
+) -> impl Stream<Item = bytes::Bytes, Error = S::Error>
+where
+    S: Stream<Item = bytes::Bytes>,
+{
+    use flate2::write::GzDecoder;
+
+    flate_stream(
+        stream,
+        GzDecoder::new(Vec::new()),
+        |decoder| decoder.try_finish(),
+        |decoder| decoder.get_mut(),
+        io_error,
+    )
+}
+
+/// Same as `gzip_stream`, for any stream error; `io_error` converts
+/// compression failures into it.
+pub fn gzip_stream_with<S>(
+    stream: S,
+    io_error: fn(io::Error) -> S::Error,
+) -> impl Stream<Item = bytes::Bytes, Error = S::Error>
+where
+    S: Stream<Item = bytes::Bytes>,
+{
+    use flate2::{write::GzEncoder, Compression};
+
+    flate_stream(
+        stream,
+        GzEncoder::new(Vec::new(), Compression::default()),
+        |encoder| encoder.try_finish(),
+        |encoder| encoder.get_mut(),
+        io_error,
+    )
+}
+
+/// Pushes every chunk through `writer` and emits whatever it has produced so far.
+fn flate_stream<S, W>(
+    stream: S,
+    mut writer: W,
+    finish: fn(&mut W) -> io::Result<()>,
+    output: fn(&mut W) -> &mut Vec<u8>,
+    io_error: fn(io::Error) -> S::Error,
+) -> impl Stream<Item = bytes::Bytes, Error = S::Error>
+where
+    S: Stream<Item = bytes::Bytes>,
+    W: io::Write,
+{
+    use std::mem;
+
+    stream
+        .map(Some)
+        .chain(futures::stream::once(Ok(None)))
+        .and_then(move |chunk| {
+            match chunk {
+                Some(chunk) => io::Write::write_all(&mut writer, chunk.as_ref()),
+                None => finish(&mut writer),
+            }
+            .map_err(io_error)?;
+
+            Ok(bytes::Bytes::from(mem::replace(
+                output(&mut writer),
+                Vec::new(),
+            )))
+        })
+        .filter(|chunk| !chunk.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
     use actix::{Arbiter, System};
@@ -187,7 +187,7 @@ fn show_session(
                 hub_session.list_blobs(),
                 hub_session.config(),
             )
-            .and_then(move |(info, peers, blobs, config)| {
+            .and_then(move |(info, peers, blobs, (config, _version))| {
                 println!("id={}: name={}", session_id, info.name.unwrap_or_default());
                 println!("\nconfig:\n------");
                 println!("{}", serde_json::to_string_pretty(&config).unwrap());
@@ -26,7 +26,7 @@ fn main() {
                     println!("New hub session ready: {:?}.", hub_session);
                     future::ok(hub_session.clone()).join(hub_session.config())
                 })
-                .and_then(|(hub_session, mut config)| {
+                .and_then(|(hub_session, (mut config, _version))| {
                     println!("Session configuration: {:?}.", config);
                     config
                         .entry
@@ -55,8 +55,10 @@ fn main() {
                         bytes,
                         Bytes::from("test!"),
                     ]);
-                    future::ok(hub_session.clone())
-                        .join3(blob.upload_from_stream(stream), future::ok(blob.clone()))
+                    future::ok(hub_session.clone()).join3(
+                        blob.upload_from_stream(stream, Some("text/plain")),
+                        future::ok(blob.clone()),
+                    )
                 })
                 .and_then(|(hub_session, _, blob)| {
                     println!("Successfully uploaded blob.");
@@ -90,6 +92,7 @@ fn main() {
                                 tags: vec![],
                                 note: None,
                                 options: (),
+                                dry_run: false,
                             })
                         }),
                     )
@@ -97,12 +100,18 @@ fn main() {
                 .and_then(|(hub_session, peer_session)| {
                     println!("Peer session created: {:?}.", peer_session);
                     future::ok(hub_session.clone()).join(peer_session.update(vec![
-                        envman::Command::AddTags(vec!["my_tag_1".to_string()]),
+                        envman::Command::AddTags(
+                            vec!["my_tag_1".parse().unwrap()].into_iter().collect(),
+                        ),
                         envman::Command::Exec {
                             executable: "gu-factor".to_string(),
                             args: vec!["100".to_string()],
+                            timeout_secs: None,
+                            output_lines: false,
                         },
-                        envman::Command::AddTags(vec!["my_tag_2".to_string()]),
+                        envman::Command::AddTags(
+                            vec!["my_tag_2".parse().unwrap()].into_iter().collect(),
+                        ),
                     ]))
                 })
                 .and_then(|(_hub_session, update_results)| {
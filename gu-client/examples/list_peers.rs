@@ -1,28 +1,43 @@
 extern crate actix;
-extern crate actix_web;
 extern crate futures;
 extern crate gu_client;
 extern crate gu_net;
 
 use actix::Arbiter;
 use futures::{future, Future};
+use gu_client::error::{Error, TransportErrorKind};
 use gu_client::r#async::HubConnection;
+use std::process;
+use std::time::Duration;
+
+const HUB_TIMEOUT: Duration = Duration::from_secs(10);
 
 fn main() {
-    let hub_connection = HubConnection::from_addr("127.0.0.1:61622").expect("Invalid address.");
-    actix::System::run(move || {
-        Arbiter::spawn(
-            hub_connection
-                .list_peers()
-                .and_then(|peers| {
+    let hub_connection = HubConnection::from_addr("127.0.0.1:61622")
+        .expect("Invalid address.")
+        .with_timeout(HUB_TIMEOUT);
+    let exit_code = actix::System::run(move || {
+        Arbiter::spawn(hub_connection.list_peers().then(|result| {
+            let exit_code = match result {
+                Ok(peers) => {
                     peers.for_each(|peer| println!("peer_id={:#?}", peer.node_id));
-                    future::ok(())
-                })
-                .map_err(|e| {
-                    println!("Error while listing peers: {:#?}.", e);
-                    ()
-                })
-                .then(|_| future::ok(actix::System::current().stop())),
-        );
+                    0
+                }
+                Err(Error::Transport {
+                    kind: TransportErrorKind::Timeout,
+                    ..
+                }) => {
+                    eprintln!("Hub did not respond within {:?}.", HUB_TIMEOUT);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Error while listing peers: {}.", e);
+                    1
+                }
+            };
+            actix::System::current().stop_with_code(exit_code);
+            future::ok(())
+        }));
     });
+    process::exit(exit_code);
 }
@@ -1,21 +1,41 @@
+use crate::dir_tar;
 use crate::error::Error;
-use actix_web::{client, http, HttpMessage};
-use bytes::Bytes;
-use futures::{future, prelude::*};
+use actix::{Actor, Addr, Arbiter};
+use actix_web::{client, error::JsonPayloadError, http, ws, HttpMessage};
+use bytes::{Bytes, BytesMut};
+use futures::{
+    future::{self, Loop},
+    prelude::*,
+    stream,
+    sync::{mpsc, oneshot},
+};
 use gu_actix::release::{AsyncRelease, Handle};
-use gu_model::peers::PeerInfo;
+use gu_base::files::{gunzip_stream_with, gzip_stream_with};
+use gu_model::peers::{PeerDetails, PeerInfo, TagFilter};
 use gu_model::{
-    deployment::DeploymentInfo,
-    envman,
-    session::{self, BlobInfo, HubExistingSession, HubSessionSpec, Metadata},
+    deployment::{DeploymentFilter, DeploymentInfo, DeploymentStatus, PeerDeploymentInfo},
+    envman, hash,
+    paging::{Page, TOTAL_COUNT_HEADER},
+    session::{self, BlobInfo, HubExistingSession, HubSessionSpec, Metadata, SessionEvent},
 };
 use gu_net::rpc::peer::PeerSessionInfo;
 use gu_net::types::NodeId;
+use log::{debug, log_enabled, warn, Level};
+use semver::{Version, VersionReq};
 use serde::de::DeserializeOwned;
-use std::collections::VecDeque;
-use std::sync::Arc;
-use std::time::Duration;
-use std::{env, str};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{cmp, env, fmt, fs, str};
+use tokio_io::codec::{BytesCodec, FramedRead};
+use tokio_io::AsyncRead;
+use tokio_timer::Delay;
 use url::Url;
 
 /// Connection to a single hub.
@@ -24,112 +44,609 @@ pub struct HubConnection {
     hub_connection_inner: Arc<HubConnectionInner>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct HubConnectionInner {
     url: Url,
+    /// timeout of a single request
+    timeout: Duration,
+    /// timeout of a peer deployment creation, which may involve downloading an image
+    deployment_timeout: Duration,
+    /// connector using the client certificate, if one was given; shared by all requests
+    tls: Option<ClientTls>,
+    /// application name sent in `X-GU-APPNAME` header
+    app_name: Option<String>,
+    /// token sent as `Authorization: Bearer` header
+    token: Option<String>,
+    /// size of buffers upload streams are re-chunked into
+    upload_chunk_size: usize,
+    /// limit shared by all blob transfers of the connection
+    rate_limiter: Option<RateLimiter>,
+    /// whether blob uploads are gzip compressed and compressed downloads are asked for
+    transfer_compression: bool,
+    /// randomizes delays of polling helpers; shared by clones of the connection
+    jitter: JitterPolicy,
+}
+
+#[derive(Clone)]
+struct ClientTls(Addr<client::ClientConnector>);
+
+impl fmt::Debug for ClientTls {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ClientTls")
+    }
 }
 
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_DEPLOYMENT_TIMEOUT: Duration = Duration::from_secs(3600);
+/// max number of concurrent requests when fetching peers info one by one
+const PEERS_INFO_CONCURRENCY: usize = 8;
+/// max number of concurrent requests in `HubSession::delete_blobs`
+const BLOB_DELETE_CONCURRENCY: usize = 8;
+/// max number of concurrent requests in `HubConnection::delete_all_sessions`
+const SESSION_DELETE_CONCURRENCY: usize = 8;
+/// Number of items fetched per request when listing all peers or sessions.
+const LIST_PAGE_SIZE: usize = 100;
+/// Max size of an error response body read by `response_error`.
+const ERROR_BODY_LIMIT: usize = 64 * 1024;
+/// shortest delay between checks in `HubSession::wait_for_peer`
+const PEER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const PEER_POLL_MAX_INTERVAL: Duration = Duration::from_secs(2);
+/// shortest delay before reconnecting `HubSession::subscribe_events`
+const EVENTS_RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const EVENTS_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+const DEFAULT_UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+const MIN_UPLOAD_CHUNK_SIZE: usize = 4 * 1024;
+/// Size of chunks read by `Blob::upload_from_file`.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Variable with the `name=addr,name2=addr2` list of hubs read by `HubRegistry::from_env`.
+const HUBS_ENV_VAR: &str = "GU_HUBS";
+const DEFAULT_HUB_ADDR: &str = "127.0.0.1:61622";
+const APP_NAME_HEADER: &str = "X-GU-APPNAME";
+
 impl Default for HubConnection {
     fn default() -> Self {
-        match env::var("GU_HUB_ADDR") {
-            Ok(addr) => HubConnection::from_addr(addr).unwrap(),
-            Err(_) => HubConnection::from_addr("127.0.0.1:61622").unwrap(),
-        }
+        HubConnection::from_env_named("GU_HUB_ADDR").unwrap()
     }
 }
 
 impl HubConnection {
+    /// creates a hub connection from address:port stored in the given environment variable;
+    /// uses the default address when the variable is not set
+    pub fn from_env_named(var_name: &str) -> Result<HubConnection, Error> {
+        match env::var(var_name) {
+            Ok(addr) => HubConnection::from_addr(addr),
+            Err(_) => HubConnection::from_addr(DEFAULT_HUB_ADDR),
+        }
+    }
     /// creates a hub connection from a given address:port, e.g. 127.0.0.1:61621
     pub fn from_addr<T: Into<String>>(addr: T) -> Result<HubConnection, Error> {
         Url::parse(&format!("http://{}/", addr.into()))
             .map_err(Error::InvalidAddress)
-            .map(|url| HubConnection {
-                hub_connection_inner: Arc::new(HubConnectionInner { url: url }),
-            })
+            .map(HubConnection::from_parsed_url)
+    }
+    /// creates a hub connection from a full http or https URL, e.g. https://hub.example.com:8443;
+    /// the URL must not have a path other than `/`
+    pub fn from_url(url: &str) -> Result<HubConnection, Error> {
+        let url = Url::parse(url).map_err(Error::InvalidAddress)?;
+        let is_http = url.scheme() == "http" || url.scheme() == "https";
+        if !is_http || url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+            // url::ParseError has no variant for an unsupported scheme or path
+            return Err(Error::InvalidAddress(
+                url::ParseError::RelativeUrlWithoutBase,
+            ));
+        }
+        Ok(HubConnection::from_parsed_url(url))
+    }
+    fn from_parsed_url(url: Url) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                url,
+                timeout: DEFAULT_TIMEOUT,
+                deployment_timeout: DEFAULT_DEPLOYMENT_TIMEOUT,
+                tls: None,
+                app_name: None,
+                token: None,
+                upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+                rate_limiter: None,
+                transfer_compression: false,
+                jitter: JitterPolicy::default(),
+            }),
+        }
+    }
+    /// whether the hub is reached over https
+    pub fn secure(&self) -> bool {
+        self.hub_connection_inner.url.scheme() == "https"
+    }
+    /// sets timeout applied to every request sent to the hub
+    pub fn with_timeout(self, timeout: Duration) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                timeout,
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
+    /// sets timeout for creation of peer sessions (default is one hour)
+    pub fn with_deployment_timeout(self, deployment_timeout: Duration) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                deployment_timeout,
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
+    /// sets size of buffers blob uploads are sent in (default is 1 MiB);
+    /// values below 4 KiB are raised to 4 KiB
+    pub fn with_upload_chunk_size(self, bytes: usize) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                upload_chunk_size: cmp::max(bytes, MIN_UPLOAD_CHUNK_SIZE),
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
+    /// limits total speed of blob uploads and downloads made with this connection
+    /// and its clones; zero means unlimited
+    pub fn with_rate_limit(self, bytes_per_sec: u64) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                rate_limiter: match bytes_per_sec {
+                    0 => None,
+                    _ => Some(RateLimiter::new(bytes_per_sec)),
+                },
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
+    /// gzip compresses blob uploads and asks the hub for compressed downloads;
+    /// the hub stores blobs decompressed, so sizes and hashes are those of the content
+    pub fn with_transfer_compression(self, transfer_compression: bool) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                transfer_compression,
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
+    /// sets how delays between repeated requests of `HubSession::wait_for_peer`,
+    /// `HubSession::keep_alive` and `HubSession::subscribe_events` are randomized
+    pub fn with_jitter_policy(self, jitter: JitterPolicy) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                jitter,
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
+    /// authenticates to the hub with a client certificate (mutual TLS);
+    /// `pem` holds the certificate chain and `key` the PKCS#8 or RSA private key, both PEM encoded
+    ///
+    /// The connector used by all requests of the connection is started here,
+    /// so it has to be called within a running actix system.
+    pub fn with_client_cert(self, pem: Vec<u8>, key: Vec<u8>) -> Result<HubConnection, Error> {
+        use rustls::internal::pemfile;
+        use std::io::BufReader;
+
+        let certs = pemfile::certs(&mut BufReader::new(pem.as_slice()))
+            .map_err(|()| Error::TlsError("invalid client certificate".into()))?;
+        if certs.is_empty() {
+            return Err(Error::TlsError("no client certificate found".into()));
+        }
+        let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(key.as_slice()))
+            .map_err(|()| Error::TlsError("invalid private key".into()))?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut BufReader::new(key.as_slice()))
+                .map_err(|()| Error::TlsError("invalid private key".into()))?;
+        }
+        let key = match keys.into_iter().next() {
+            Some(key) => key,
+            None => return Err(Error::TlsError("no private key found".into())),
+        };
+
+        let mut config = rustls::ClientConfig::new();
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        config.set_single_client_cert(certs, key);
+
+        Ok(HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                tls: Some(ClientTls(
+                    client::ClientConnector::with_connector(config).start(),
+                )),
+                ..(*self.hub_connection_inner).clone()
+            }),
+        })
     }
     /// creates a new hub session
     pub fn new_session(
         &self,
         session_info: HubSessionSpec,
     ) -> impl Future<Item = Handle<HubSession>, Error = Error> {
+        self.create_session(session_info, None)
+    }
+    /// creates a new hub session, or returns the one created before with the same `key`
+    ///
+    /// The key must be generated by the client and unique per logical request;
+    /// reusing it on retry (e.g. after a timeout) prevents duplicate sessions.
+    pub fn new_session_idempotent<K: AsRef<str>>(
+        &self,
+        session_info: HubSessionSpec,
+        key: K,
+    ) -> impl Future<Item = Handle<HubSession>, Error = Error> {
+        self.create_session(session_info, Some(key.as_ref()))
+    }
+    fn create_session(
+        &self,
+        session_info: HubSessionSpec,
+        idempotency_key: Option<&str>,
+    ) -> impl Future<Item = Handle<HubSession>, Error = Error> {
+        if let Err(e) = session_info.validate() {
+            return future::Either::A(future::err(Error::InvalidSessionSpec(e)));
+        }
+        let timeout = self.timeout();
         let sessions_url = format!("{}sessions", self.hub_connection_inner.url);
-        let request = match client::ClientRequest::post(sessions_url).json(session_info) {
+        let mut builder = self.request(http::Method::POST, sessions_url);
+        if let Some(key) = idempotency_key {
+            builder.header(session::IDEMPOTENCY_KEY_HEADER, key);
+        }
+        let request = match builder.json(session_info) {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
         let hub_connection_for_session = self.clone();
         future::Either::B(
             request
-                .send()
-                .map_err(Error::CannotSendRequest)
+                .send_logged(timeout)
                 .and_then(|response| {
-                    if response.status() != http::StatusCode::CREATED {
-                        return future::Either::A(future::err(Error::CannotCreateHubSession(
-                            response.status(),
-                        )));
+                    // the hub answers 200 for a session found by the idempotency key
+                    let status = response.status();
+                    if status != http::StatusCode::CREATED && status != http::StatusCode::OK {
+                        return future::Either::A(response_error(
+                            response,
+                            Error::CannotCreateHubSession,
+                        ));
                     }
                     future::Either::B(response.body().map_err(Error::CannotGetResponseBody))
                 })
                 .and_then(|body| {
+                    let session_id = match str::from_utf8(&body.to_vec()) {
+                        Ok(str) => str.to_string(),
+                        Err(e) => return future::err(Error::CannotConvertToUTF8(e)),
+                    };
+                    debug!("created hub session {}", session_id);
                     future::ok(Handle::new(HubSession {
                         hub_connection: hub_connection_for_session,
-                        session_id: match str::from_utf8(&body.to_vec()) {
-                            Ok(str) => str.to_string(),
-                            Err(e) => return future::err(Error::CannotConvertToUTF8(e)),
-                        },
+                        session_id,
+                        keep_alives: Default::default(),
                     }))
                 }),
         )
     }
-    pub fn auth_app<T: Into<String>, U: Into<String>>(&self, _app_name: T, _token: Option<U>) {}
+    /// identifies the application to the hub; every request carries the app name
+    /// and, when given, the token as a bearer authorization
+    pub fn auth_app<T: Into<String>, U: Into<String>>(
+        self,
+        app_name: T,
+        token: Option<U>,
+    ) -> HubConnection {
+        HubConnection {
+            hub_connection_inner: Arc::new(HubConnectionInner {
+                app_name: Some(app_name.into()),
+                token: token.map(Into::into),
+                ..(*self.hub_connection_inner).clone()
+            }),
+        }
+    }
     /// returns all peers connected to the hub
     pub fn list_peers(&self) -> impl Future<Item = impl Iterator<Item = PeerInfo>, Error = Error> {
-        let url = format!("{}peers", self.hub_connection_inner.url);
-        match client::ClientRequest::get(url).finish() {
-            Ok(r) => future::Either::A(
-                r.send()
-                    .map_err(Error::CannotSendRequest)
-                    .and_then(|response| match response.status() {
-                        http::StatusCode::OK => {
-                            future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
-                        }
-                        status => future::Either::B(future::err(Error::CannotListHubPeers(status))),
-                    })
-                    .and_then(|answer_json: Vec<PeerInfo>| future::ok(answer_json.into_iter())),
-            ),
-            Err(e) => future::Either::B(future::err(Error::CannotCreateRequest(e))),
+        self.fetch_all_pages("peers", String::new(), Error::CannotListHubPeers)
+            .map(|peers: Vec<PeerInfo>| peers.into_iter())
+    }
+    /// returns peers carrying all (`match_all`) or any of the given tags
+    pub fn list_peers_with_tags(
+        &self,
+        tags: &[String],
+        match_all: bool,
+    ) -> impl Future<Item = impl Iterator<Item = PeerInfo>, Error = Error> {
+        if let Some(tag) = tags.iter().find(|tag| tag.contains(',')) {
+            return future::Either::A(future::err(Error::InvalidTag(tag.clone())));
+        }
+        let filter = TagFilter::new(tags, match_all);
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(ref tags) = filter.tags {
+            query.append_pair("tags", tags);
         }
+        query.append_pair("matchAll", &filter.match_all.to_string());
+
+        future::Either::B(
+            self.fetch_all_pages("peers", query.finish(), Error::CannotListHubPeers)
+                .map(|peers: Vec<PeerInfo>| peers.into_iter()),
+        )
+    }
+    /// returns a page of peers connected to the hub, together with the number of all peers
+    pub fn list_peers_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> impl Future<Item = (Vec<PeerInfo>, usize), Error = Error> {
+        self.fetch_page(
+            "peers",
+            "",
+            Page::new(offset, limit),
+            Error::CannotListHubPeers,
+        )
     }
     /// returns information about all hub sessions
     pub fn list_sessions(
         &self,
     ) -> impl Future<Item = impl Iterator<Item = HubExistingSession>, Error = Error> {
-        let url = format!("{}sessions", self.hub_connection_inner.url);
-        match client::ClientRequest::get(url).finish() {
-            Ok(r) => future::Either::A(
-                r.send()
-                    .map_err(Error::CannotSendRequest)
-                    .and_then(|response| match response.status() {
-                        http::StatusCode::OK => {
-                            future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
-                        }
-                        status => {
-                            future::Either::B(future::err(Error::CannotListHubSessions(status)))
-                        }
-                    })
-                    .and_then(|answer_json: Vec<_>| future::ok(answer_json.into_iter())),
-            ),
-            Err(e) => future::Either::B(future::err(Error::CannotCreateRequest(e))),
+        self.fetch_all_pages("sessions", String::new(), Error::CannotListHubSessions)
+            .map(|sessions: Vec<HubExistingSession>| sessions.into_iter())
+    }
+    /// deletes all hub sessions, a few at a time, e.g. in test teardown
+    ///
+    /// Fails with `Error::DeleteNotConfirmed` unless `confirm` is set. A session
+    /// which is already gone counts as deleted.
+    pub fn delete_all_sessions(
+        &self,
+        confirm: bool,
+    ) -> impl Future<Item = DeleteSummary, Error = Error> {
+        if !confirm {
+            return future::Either::A(future::err(Error::DeleteNotConfirmed));
         }
+        let connection = self.clone();
+
+        future::Either::B(self.list_sessions().and_then(move |sessions| {
+            stream::iter_ok(sessions.map(|session| session.id.to_string()))
+                .map(move |session_id| {
+                    connection
+                        .hub_session(session_id.clone())
+                        .delete()
+                        .then(|r| match r {
+                            Err(Error::ResourceNotFound) => Ok(()),
+                            r => r,
+                        })
+                        .then(move |r| Ok::<_, Error>((session_id, r)))
+                })
+                .buffer_unordered(SESSION_DELETE_CONCURRENCY)
+                .fold(DeleteSummary::default(), |mut summary, (session_id, r)| {
+                    match r {
+                        Ok(()) => summary.deleted.push(session_id),
+                        Err(e) => summary.failed.push((session_id, e)),
+                    }
+                    Ok::<_, Error>(summary)
+                })
+        }))
+    }
+    /// returns a page of hub sessions, together with the number of all sessions
+    pub fn list_sessions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> impl Future<Item = (Vec<HubExistingSession>, usize), Error = Error> {
+        self.fetch_page(
+            "sessions",
+            "",
+            Page::new(offset, limit),
+            Error::CannotListHubSessions,
+        )
+    }
+    /// checks whether the hub responds, and how many peers and sessions it has
+    ///
+    /// Never fails: an unreachable (or misbehaving) hub is reported with `reachable: false`.
+    pub fn health(&self) -> impl Future<Item = HubHealth, Error = Error> {
+        let start = Instant::now();
+
+        self.list_peers()
+            .join(self.list_sessions())
+            .then(move |result| {
+                let latency = start.elapsed();
+                Ok(match result {
+                    Ok((peers, sessions)) => HubHealth {
+                        reachable: true,
+                        peer_count: peers.count(),
+                        session_count: sessions.count(),
+                        latency,
+                    },
+                    Err(_) => HubHealth {
+                        reachable: false,
+                        peer_count: 0,
+                        session_count: 0,
+                        latency,
+                    },
+                })
+            })
+    }
+    /// returns version of the hub
+    pub fn server_version(&self) -> impl Future<Item = Version, Error = Error> {
+        let timeout = self.timeout();
+        let url = format!("{}version", self.hub_connection_inner.url);
+        self.request(http::Method::GET, url)
+            .finish()
+            .into_future()
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |r| r.send_logged(timeout))
+            .and_then(|response| match response.status() {
+                http::StatusCode::OK => future::Either::A(future::ok(response)),
+                _ => future::Either::B(response_error(response, Error::CannotGetServerVersion)),
+            })
+            .and_then(|response| response.json().map_err(Error::InvalidJSONResponse))
+            .and_then(|version: String| {
+                Version::parse(&version).map_err(Error::InvalidServerVersion)
+            })
+    }
+    /// fails with `Error::IncompatibleServer` unless hub version matches `req`
+    pub fn ensure_compatible(&self, req: VersionReq) -> impl Future<Item = Version, Error = Error> {
+        self.server_version().and_then(move |server| {
+            if req.matches(&server) {
+                Ok(server)
+            } else {
+                Err(Error::IncompatibleServer {
+                    server,
+                    required: req,
+                })
+            }
+        })
+    }
+    /// returns information about given peers, fetched in a single request
+    ///
+    /// Hubs without the bulk endpoint are asked about each peer separately.
+    /// Peers unknown to the hub are left out of the result.
+    pub fn peers_info<Ids: IntoIterator<Item = NodeId>>(
+        &self,
+        ids: Ids,
+    ) -> impl Future<Item = HashMap<NodeId, PeerInfo>, Error = Error> {
+        let ids: Vec<NodeId> = ids.into_iter().collect();
+        let timeout = self.timeout();
+        let url = format!("{}peers/info", self.url());
+        let connection = self.clone();
+
+        self.request(http::Method::POST, url)
+            .json(&ids)
+            .into_future()
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |r| r.send_logged(timeout))
+            .and_then(move |response| match response.status() {
+                http::StatusCode::OK => future::Either::A(
+                    response
+                        .json()
+                        .map_err(Error::InvalidJSONResponse)
+                        .and_then(|peers: Vec<PeerInfo>| {
+                            Ok(peers.into_iter().map(|peer| (peer.node_id, peer)).collect())
+                        }),
+                ),
+                http::StatusCode::NOT_FOUND | http::StatusCode::METHOD_NOT_ALLOWED => {
+                    future::Either::B(future::Either::A(connection.peers_info_one_by_one(ids)))
+                }
+                _ => future::Either::B(future::Either::B(response_error(
+                    response,
+                    Error::CannotGetPeerInfo,
+                ))),
+            })
+    }
+    /// creates a hub session with the `count` best peers and deploys to each of them
+    ///
+    /// Peers for which `selector` returns `None` are skipped, the rest is ranked
+    /// by the returned key, highest first. Result of the deployment is reported
+    /// separately for every chosen peer.
+    pub fn deploy_to_best<F, K>(
+        &self,
+        session_spec: HubSessionSpec,
+        deployment: envman::CreateSession,
+        count: usize,
+        selector: F,
+    ) -> impl Future<
+        Item = (
+            Handle<HubSession>,
+            Vec<(NodeId, Result<PeerSession, Error>)>,
+        ),
+        Error = Error,
+    >
+    where
+        F: Fn(&PeerInfo) -> Option<K> + 'static,
+        K: Ord,
+    {
+        let connection = self.clone();
+
+        self.list_peers()
+            .and_then(move |peers| {
+                let mut ranked: Vec<(K, NodeId)> = peers
+                    .filter_map(|peer| selector(&peer).map(|key| (key, peer.node_id)))
+                    .collect();
+                ranked.sort_by(|a, b| b.0.cmp(&a.0));
+                let node_ids: Vec<NodeId> = ranked
+                    .into_iter()
+                    .take(count)
+                    .map(|(_, node_id)| node_id)
+                    .collect();
+                if node_ids.is_empty() {
+                    return future::Either::A(future::err(Error::NoMatchingPeers));
+                }
+                future::Either::B(
+                    connection
+                        .new_session(session_spec)
+                        .map(move |session| (session, node_ids)),
+                )
+            })
+            .and_then(|(session, node_ids)| {
+                session
+                    .add_peers(node_ids.iter().map(NodeId::to_string))
+                    .map(move |_| (session, node_ids))
+            })
+            .and_then(move |(session, node_ids)| {
+                future::join_all(
+                    node_ids
+                        .into_iter()
+                        .map(|node_id| {
+                            session
+                                .peer(node_id)
+                                .new_session(deployment.clone())
+                                .then(move |r| Ok((node_id, r)))
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .map(move |results| (session, results))
+            })
+    }
+    /// returns deployments of all peers connected to the hub
+    ///
+    /// Hubs without the aggregate endpoint are asked about each peer separately.
+    /// Peers which disconnect meanwhile are left out of the result.
+    pub fn all_deployments(
+        &self,
+    ) -> impl Future<Item = Vec<(NodeId, DeploymentInfo)>, Error = Error> {
+        let timeout = self.timeout();
+        let url = format!("{}peers/deployments", self.url());
+        let connection = self.clone();
+
+        self.request(http::Method::GET, url)
+            .finish()
+            .into_future()
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |r| r.send_logged(timeout))
+            .and_then(move |response| match response.status() {
+                http::StatusCode::OK => future::Either::A(
+                    response
+                        .json()
+                        .map_err(Error::InvalidJSONResponse)
+                        .and_then(|deployments: Vec<PeerDeploymentInfo>| {
+                            Ok(deployments
+                                .into_iter()
+                                .map(|d| (d.node_id, d.deployment))
+                                .collect())
+                        }),
+                ),
+                http::StatusCode::NOT_FOUND | http::StatusCode::METHOD_NOT_ALLOWED => {
+                    future::Either::B(future::Either::A(connection.all_deployments_one_by_one()))
+                }
+                _ => future::Either::B(future::Either::B(response_error(
+                    response,
+                    Error::CannotListPeerSessions,
+                ))),
+            })
     }
     /// returns hub session object
     pub fn hub_session<T: Into<String>>(&self, session_id: T) -> HubSession {
         HubSession {
             hub_connection: self.clone(),
             session_id: session_id.into(),
+            keep_alives: Default::default(),
         }
     }
 
+    /// parses node id given as a string; all client methods taking string ids use it
+    pub fn parse_node_id(node_id: &str) -> Result<NodeId, Error> {
+        node_id
+            .parse()
+            .map_err(|e: gu_net::types::ParseError| Error::InvalidPeer {
+                input: node_id.to_string(),
+                reason: e.to_string(),
+            })
+    }
+
     pub fn peer<T: Into<NodeId>>(&self, node_id: T) -> ProviderRef {
         let connection = self.clone();
         let node_id = node_id.into();
@@ -144,49 +661,303 @@ impl HubConnection {
         self.hub_connection_inner.url.as_ref()
     }
 
+    /// starts a request to the hub; with a client certificate set it goes through its connector
+    fn request<U: AsRef<str>>(&self, method: http::Method, url: U) -> client::ClientRequestBuilder {
+        let inner = &self.hub_connection_inner;
+        let mut builder = client::ClientRequest::build();
+        builder.method(method).uri(url);
+        if let Some(app_name) = &inner.app_name {
+            builder.header(APP_NAME_HEADER, app_name.as_str());
+        }
+        if let Some(token) = &inner.token {
+            builder.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        if let Some(ClientTls(connector)) = &inner.tls {
+            builder.with_connector(connector.clone());
+        }
+        builder
+    }
+
+    /// starts a WebSocket handshake with the hub, with the same headers as `request`
+    fn ws_client<U: AsRef<str>>(&self, url: U) -> ws::Client {
+        let inner = &self.hub_connection_inner;
+        let mut client = match &inner.tls {
+            Some(ClientTls(connector)) => ws::Client::with_connector(url, connector.clone()),
+            None => ws::Client::new(url),
+        };
+        if let Some(app_name) = &inner.app_name {
+            client = client.header(APP_NAME_HEADER, app_name.as_str());
+        }
+        if let Some(token) = &inner.token {
+            client = client.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        client
+    }
+
+    fn timeout(&self) -> Duration {
+        self.hub_connection_inner.timeout
+    }
+
+    fn jitter(&self) -> &JitterPolicy {
+        &self.hub_connection_inner.jitter
+    }
+
+    fn deployment_timeout(&self) -> Duration {
+        self.hub_connection_inner.deployment_timeout
+    }
+
+    fn upload_chunk_size(&self) -> usize {
+        self.hub_connection_inner.upload_chunk_size
+    }
+
+    fn rate_limiter(&self) -> Option<RateLimiter> {
+        self.hub_connection_inner.rate_limiter.clone()
+    }
+
+    fn transfer_compression(&self) -> bool {
+        self.hub_connection_inner.transfer_compression
+    }
+
+    /// GET request for blob content, negotiating its encoding
+    fn blob_request(&self, url: String) -> Result<client::ClientRequest, actix_web::Error> {
+        self.request(http::Method::GET, url)
+            .header(
+                http::header::ACCEPT_ENCODING,
+                if self.transfer_compression() {
+                    "gzip"
+                } else {
+                    "identity"
+                },
+            )
+            .finish()
+    }
+
+    fn peers_info_one_by_one(
+        &self,
+        ids: Vec<NodeId>,
+    ) -> impl Future<Item = HashMap<NodeId, PeerInfo>, Error = Error> {
+        let connection = self.clone();
+
+        stream::iter_ok(ids)
+            .map(move |node_id| {
+                connection.peer(node_id).info().then(|r| match r {
+                    Ok(info) => Ok(Some(info)),
+                    Err(ref e) if e.status() == Some(http::StatusCode::NOT_FOUND) => Ok(None),
+                    Err(e) => Err(e),
+                })
+            })
+            .buffer_unordered(PEERS_INFO_CONCURRENCY)
+            .filter_map(|info| info)
+            .fold(HashMap::new(), |mut peers, info: PeerInfo| {
+                peers.insert(info.node_id, info);
+                Ok::<_, Error>(peers)
+            })
+    }
+
+    fn all_deployments_one_by_one(
+        &self,
+    ) -> impl Future<Item = Vec<(NodeId, DeploymentInfo)>, Error = Error> {
+        let connection = self.clone();
+
+        self.list_peers().and_then(move |peers| {
+            stream::iter_ok(peers.map(|peer| peer.node_id).collect::<Vec<_>>())
+                .map(move |node_id| {
+                    let url = format!("{}peers/{:?}/deployments", connection.url(), node_id);
+                    connection.fetch_json(&url).then(move |r| match r {
+                        Ok(deployments) => Ok(Some((node_id, deployments))),
+                        Err(ref e) if e.status() == Some(http::StatusCode::NOT_FOUND) => Ok(None),
+                        Err(e) => Err(e),
+                    })
+                })
+                .buffer_unordered(PEERS_INFO_CONCURRENCY)
+                .filter_map(|deployments| deployments)
+                .fold(
+                    Vec::new(),
+                    |mut all, (node_id, deployments): (NodeId, Vec<DeploymentInfo>)| {
+                        all.extend(deployments.into_iter().map(|d| (node_id, d)));
+                        Ok::<_, Error>(all)
+                    },
+                )
+        })
+    }
+
+    fn fetch_page<T: DeserializeOwned + 'static>(
+        &self,
+        path: &str,
+        query: &str,
+        page: Page,
+        error: fn(http::StatusCode) -> Error,
+    ) -> impl Future<Item = (Vec<T>, usize), Error = Error> {
+        let timeout = self.timeout();
+        let mut url = format!("{}{}?offset={}", self.url(), path, page.offset);
+        if let Some(limit) = page.limit {
+            url.push_str(&format!("&limit={}", limit));
+        }
+        if !query.is_empty() {
+            url.push('&');
+            url.push_str(query);
+        }
+        self.request(http::Method::GET, &url)
+            .finish()
+            .into_future()
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |r| r.send_logged(timeout))
+            .and_then(move |response| match response.status() {
+                http::StatusCode::OK => {
+                    let total: Option<usize> = response
+                        .headers()
+                        .get(TOTAL_COUNT_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok());
+                    future::Either::A(response.json().map_err(Error::InvalidJSONResponse).map(
+                        move |items: Vec<T>| match total {
+                            Some(total) => (items, total),
+                            // hub without paging support sends everything
+                            None => page.apply(items),
+                        },
+                    ))
+                }
+                _ => future::Either::B(response_error(response, error)),
+            })
+    }
+
+    fn fetch_all_pages<T: DeserializeOwned + 'static>(
+        &self,
+        path: &'static str,
+        query: String,
+        error: fn(http::StatusCode) -> Error,
+    ) -> impl Future<Item = Vec<T>, Error = Error> {
+        let connection = self.clone();
+        future::loop_fn(Vec::new(), move |mut items: Vec<T>| {
+            connection
+                .fetch_page(path, &query, Page::new(items.len(), LIST_PAGE_SIZE), error)
+                .map(move |(page, total)| {
+                    let done = page.is_empty() || items.len() + page.len() >= total;
+                    items.extend(page);
+                    if done {
+                        future::Loop::Break(items)
+                    } else {
+                        future::Loop::Continue(items)
+                    }
+                })
+        })
+    }
+
     fn fetch_json<T: DeserializeOwned + 'static>(
         &self,
         url: &str,
     ) -> impl Future<Item = T, Error = Error> {
-        client::ClientRequest::get(&url)
+        let timeout = self.timeout();
+        self.request(http::Method::GET, &url)
             .finish()
             .into_future()
             .map_err(Error::CannotCreateRequest)
-            .and_then(|r| r.send().map_err(Error::CannotSendRequest))
+            .and_then(move |r| r.send_logged(timeout))
             .and_then(|response| match response.status() {
-                http::StatusCode::OK => Ok(response),
-                status => Err(Error::CannotGetPeerInfo(status)),
+                http::StatusCode::OK => future::Either::A(future::ok(response)),
+                _ => future::Either::B(response_error(response, Error::CannotGetPeerInfo)),
             })
             .and_then(|response| response.json().map_err(Error::InvalidJSONResponse))
     }
 
     fn delete_resource(&self, url: &str) -> impl Future<Item = (), Error = Error> {
-        client::ClientRequest::delete(&url)
+        let timeout = self.timeout();
+        self.request(http::Method::DELETE, &url)
             .finish()
             .into_future()
             .map_err(Error::CannotCreateRequest)
-            .and_then(|r| r.send().map_err(Error::CannotSendRequest))
+            .and_then(move |r| r.send_logged(timeout))
             .and_then(|response| match response.status() {
                 http::StatusCode::NO_CONTENT => future::Either::A(future::ok(())),
-                http::StatusCode::OK => future::Either::B(
+                http::StatusCode::OK => future::Either::B(future::Either::A(
                     response
                         .json()
                         .map_err(Error::InvalidJSONResponse)
-                        .and_then(|j: serde_json::Value| Ok(eprintln!("{}", j))),
-                ),
+                        .and_then(|j: serde_json::Value| Ok(debug!("deleted: {}", j))),
+                )),
                 http::StatusCode::NOT_FOUND => {
                     future::Either::A(future::err(Error::ResourceNotFound))
                 }
-                status => future::Either::A(future::err(Error::CannotGetPeerInfo(status))),
+                _ => future::Either::B(future::Either::B(response_error(
+                    response,
+                    Error::CannotGetPeerInfo,
+                ))),
             })
     }
 }
 
+/// Named hub connections.
+#[derive(Clone, Debug, Default)]
+pub struct HubRegistry {
+    hubs: BTreeMap<String, HubConnection>,
+}
+
+impl HubRegistry {
+    /// loads hubs from the `GU_HUBS` variable; empty when the variable is not set
+    pub fn from_env() -> Result<HubRegistry, Error> {
+        match env::var(HUBS_ENV_VAR) {
+            Ok(spec) => HubRegistry::parse(&spec),
+            Err(_) => Ok(HubRegistry::default()),
+        }
+    }
+
+    /// parses a `name=addr,name2=addr2` list of hubs; addr may also be a full URL
+    pub fn parse(spec: &str) -> Result<HubRegistry, Error> {
+        let mut hubs = BTreeMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim();
+            let addr = parts.next().unwrap_or_default().trim();
+            if name.is_empty() || addr.is_empty() {
+                return Err(Error::InvalidAddress(url::ParseError::EmptyHost));
+            }
+            let connection = if addr.contains("://") {
+                HubConnection::from_url(addr)?
+            } else {
+                HubConnection::from_addr(addr)?
+            };
+            hubs.insert(name.to_string(), connection);
+        }
+        Ok(HubRegistry { hubs })
+    }
+
+    /// returns connection to the hub registered under the given name
+    pub fn get(&self, name: &str) -> Option<HubConnection> {
+        self.hubs.get(name).cloned()
+    }
+
+    /// returns names of all registered hubs
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.hubs.keys().map(String::as_str)
+    }
+}
+
+/// Hub status returned by `HubConnection::health`.
+#[derive(Clone, Debug)]
+pub struct HubHealth {
+    pub reachable: bool,
+    pub peer_count: usize,
+    pub session_count: usize,
+    /// time taken to get the answer (or to fail)
+    pub latency: Duration,
+}
+
+/// Outcome of `HubConnection::delete_all_sessions`.
+#[derive(Debug, Default)]
+pub struct DeleteSummary {
+    /// ids of deleted sessions, including ones already gone
+    pub deleted: Vec<String>,
+    /// ids of sessions which could not be deleted, with the reason
+    pub failed: Vec<(String, Error)>,
+}
+
 /// Hub session.
 #[derive(Clone, Debug)]
 pub struct HubSession {
     hub_connection: HubConnection,
     session_id: String,
+    /// stop handles of tasks started by `keep_alive`, shared by clones of the session
+    keep_alives: Arc<Mutex<Vec<oneshot::Sender<()>>>>,
 }
 
 impl HubSession {
@@ -196,53 +967,88 @@ impl HubSession {
         T: IntoIterator<Item = U>,
         U: AsRef<str>,
     {
+        let timeout = self.hub_connection.timeout();
         let add_url = format!(
             "{}sessions/{}/peers",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        let peer_vec: Vec<String> = peers.into_iter().map(|peer| peer.as_ref().into()).collect();
-        let request = match client::ClientRequest::post(add_url).json(peer_vec) {
+        let peer_vec = match peers
+            .into_iter()
+            .map(|peer| HubConnection::parse_node_id(peer.as_ref()))
+            .collect::<Result<Vec<NodeId>, Error>>()
+        {
+            Ok(peer_vec) => peer_vec,
+            Err(e) => return future::Either::A(future::err(e)),
+        };
+        let request = match self
+            .hub_connection
+            .request(http::Method::POST, add_url)
+            .json(peer_vec)
+        {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
         let session_id_copy = self.session_id.clone();
-        future::Either::B(
-            request
-                .send()
-                .map_err(Error::CannotSendRequest)
-                .and_then(|response| match response.status() {
-                    http::StatusCode::NOT_FOUND => {
-                        future::Either::A(future::err(Error::SessionNotFound(session_id_copy)))
-                    }
-                    http::StatusCode::INTERNAL_SERVER_ERROR => future::Either::A(future::err(
-                        Error::CannotAddPeersToSession(response.status()),
-                    )),
-                    _ => future::Either::B(
-                        response.json().map_err(|e| Error::InvalidJSONResponse(e)),
-                    ),
-                }),
-        )
+        future::Either::B(request.send_logged(timeout).and_then(
+            |response| match response.status() {
+                http::StatusCode::NOT_FOUND => {
+                    future::Either::A(future::err(Error::SessionNotFound(session_id_copy)))
+                }
+                http::StatusCode::INTERNAL_SERVER_ERROR => future::Either::B(future::Either::A(
+                    response_error(response, Error::CannotAddPeersToSession),
+                )),
+                _ => future::Either::B(future::Either::B(
+                    response.json().map_err(|e| Error::InvalidJSONResponse(e)),
+                )),
+            },
+        ))
+    }
+    /// creates a new blob with a tar archive of a local directory
+    ///
+    /// Entries matching patterns of the `.gu-ignore` file in the directory are left out.
+    /// Symbolic links are stored as links, or left out with `skip_symlinks`.
+    pub fn upload_dir_as_blob<P: AsRef<Path>>(
+        &self,
+        path: P,
+        skip_symlinks: bool,
+    ) -> impl Future<Item = Blob, Error = Error> {
+        let root = path.as_ref().to_path_buf();
+        if let Err(e) = fs::read_dir(&root) {
+            return future::Either::A(future::err(Error::IoError(e)));
+        }
+
+        future::Either::B(self.new_blob().and_then(move |blob| {
+            blob.upload_from_stream_info(
+                dir_tar::stream_dir_tar(root, skip_symlinks),
+                Some("application/x-tar"),
+            )
+            .map(move |_| blob)
+        }))
     }
     /// creates a new blob
     pub fn new_blob(&self) -> impl Future<Item = Blob, Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let new_blob_url = format!(
             "{}sessions/{}/blobs",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        let request = match client::ClientRequest::post(new_blob_url).finish() {
+        let request = match self
+            .hub_connection
+            .request(http::Method::POST, new_blob_url)
+            .finish()
+        {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
         let hub_session_copy = self.clone();
         future::Either::B(
             request
-                .send()
-                .map_err(Error::CannotSendRequest)
+                .send_logged(timeout)
                 .and_then(|response| match response.status() {
                     http::StatusCode::CREATED => {
                         future::Either::A(response.body().map_err(Error::CannotGetResponseBody))
                     }
-                    status => future::Either::B(future::err(Error::CannotCreateBlob(status))),
+                    _ => future::Either::B(response_error(response, Error::CannotCreateBlob)),
                 })
                 .and_then(|body| {
                     future::ok(Blob {
@@ -255,6 +1061,32 @@ impl HubSession {
                 }),
         )
     }
+    /// creates a peer session on each of the given peers and sends it the same commands
+    ///
+    /// At most `concurrency` peers are handled at once. Results are given per peer
+    /// (in completion order), so a failure on one peer does not hide the others.
+    pub fn broadcast<Peers: IntoIterator<Item = NodeId>>(
+        &self,
+        session_info: envman::CreateSession,
+        commands: Vec<envman::Command>,
+        peers: Peers,
+        concurrency: usize,
+    ) -> impl Future<Item = Vec<(NodeId, Result<Vec<String>, Error>)>, Error = Error> {
+        let hub_session = self.clone();
+        let peers: Vec<NodeId> = peers.into_iter().collect();
+
+        stream::iter_ok::<_, Error>(peers)
+            .map(move |node_id| {
+                let commands = commands.clone();
+                hub_session
+                    .peer(node_id)
+                    .new_session(session_info.clone())
+                    .and_then(move |peer_session| peer_session.update(commands))
+                    .then(move |result| Ok((node_id, result)))
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+    }
     /// gets single peer by its id
     pub fn peer(&self, node_id: NodeId) -> Peer {
         Peer {
@@ -262,35 +1094,98 @@ impl HubSession {
             hub_session: self.clone(),
         }
     }
+    /// waits until the provider of `node_id` is connected to the hub
+    ///
+    /// Hub is asked again after `PEER_POLL_INTERVAL` up to `PEER_POLL_MAX_INTERVAL`,
+    /// as set by the connection's `JitterPolicy`; fails with `Error::PeerTimeout`
+    /// when the peer does not show up within `timeout`.
+    pub fn wait_for_peer(
+        &self,
+        node_id: NodeId,
+        timeout: Duration,
+    ) -> impl Future<Item = Peer, Error = Error> {
+        let deadline = Instant::now() + timeout;
+        let peer = self.peer(node_id);
+        let jitter = self.hub_connection.jitter().clone();
+
+        future::loop_fn((peer, PEER_POLL_INTERVAL), move |(peer, previous)| {
+            let jitter = jitter.clone();
+            peer.info().then(move |r| match r {
+                Ok(_) => future::Either::A(future::ok(Loop::Break(peer))),
+                Err(ref e) if e.status() == Some(http::StatusCode::NOT_FOUND) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return future::Either::A(future::err(Error::PeerTimeout(node_id)));
+                    }
+                    let delay =
+                        jitter.next_delay(PEER_POLL_INTERVAL, previous, PEER_POLL_MAX_INTERVAL);
+                    future::Either::B(
+                        Delay::new(cmp::min(now + delay, deadline))
+                            .map_err(move |_| Error::PeerTimeout(node_id))
+                            .map(move |()| Loop::Continue((peer, delay))),
+                    )
+                }
+                Err(e) => future::Either::A(future::err(e)),
+            })
+        })
+    }
     /// gets single peer by its id given as a string
     pub fn peer_from_str<T: AsRef<str>>(&self, node_id: T) -> Result<Peer, Error> {
-        Ok(self.peer(
-            node_id
-                .as_ref()
-                .parse()
-                .map_err(|_| Error::InvalidPeer(node_id.as_ref().to_string()))?,
-        ))
+        Ok(self.peer(HubConnection::parse_node_id(node_id.as_ref())?))
     }
 
-    /// returns all session peers
-    pub fn list_peers(&self) -> impl Future<Item = impl Iterator<Item = PeerInfo>, Error = Error> {
+    /// returns deployments of the session on all its peers, filtered by the hub
+    ///
+    /// Allows to find and reuse existing workers, e.g. docker deployments of a given image.
+    pub fn find_deployments(
+        &self,
+        filter: DeploymentFilter,
+    ) -> impl Future<Item = Vec<(NodeId, DeploymentInfo)>, Error = Error> {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(ref env_type) = filter.env_type {
+            query.append_pair("envType", env_type);
+        }
+        if let Some(ref image_url) = filter.image_url {
+            query.append_pair("imageUrl", image_url);
+        }
+        if let Some(ref tags) = filter.tags {
+            query.append_pair("tags", tags);
+        }
+        let url = format!(
+            "{}sessions/{}/deployments?{}",
+            self.hub_connection.hub_connection_inner.url,
+            self.session_id,
+            query.finish()
+        );
+
+        self.hub_connection
+            .fetch_json(&url)
+            .map(|deployments: Vec<PeerDeploymentInfo>| {
+                deployments
+                    .into_iter()
+                    .map(|info| (info.node_id, info.deployment))
+                    .collect()
+            })
+    }
+    /// returns all session peers
+    pub fn list_peers(&self) -> impl Future<Item = impl Iterator<Item = PeerInfo>, Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let url = format!(
             "{}sessions/{}/peers",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        let request = match client::ClientRequest::get(url).finish() {
+        let request = match self.hub_connection.request(http::Method::GET, url).finish() {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
         future::Either::B(
             request
-                .send()
-                .map_err(Error::CannotSendRequest)
+                .send_logged(timeout)
                 .and_then(|response| match response.status() {
                     http::StatusCode::OK => {
                         future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
                     }
-                    status => future::Either::B(future::err(Error::CannotListSessionPeers(status))),
+                    _ => future::Either::B(response_error(response, Error::CannotListSessionPeers)),
                 })
                 .and_then(|answer_json: Vec<PeerInfo>| future::ok(answer_json.into_iter())),
         )
@@ -302,98 +1197,338 @@ impl HubSession {
             hub_session: self.clone(),
         }
     }
+    /// deletes given blobs, a few at a time; results are in the order of `ids`
+    ///
+    /// A blob which is already gone counts as deleted.
+    pub fn delete_blobs<I>(
+        &self,
+        ids: I,
+    ) -> impl Future<Item = Vec<(String, Result<(), Error>)>, Error = Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let hub_session = self.clone();
+
+        stream::iter_ok(ids.into_iter().collect::<Vec<_>>())
+            .map(move |blob_id| {
+                hub_session
+                    .blob(blob_id.clone())
+                    .delete()
+                    .then(|r| match r {
+                        Err(ref e) if e.status() == Some(http::StatusCode::NOT_FOUND) => Ok(()),
+                        r => r,
+                    })
+                    .then(move |r| Ok::<_, Error>((blob_id, r)))
+            })
+            .buffered(BLOB_DELETE_CONCURRENCY)
+            .collect()
+    }
     /// returns all session blobs
     pub fn list_blobs(&self) -> impl Future<Item = impl Iterator<Item = BlobInfo>, Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let url = format!(
             "{}sessions/{}/blobs",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        let request = match client::ClientRequest::get(url).finish() {
+        let request = match self.hub_connection.request(http::Method::GET, url).finish() {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
         future::Either::B(
             request
-                .send()
-                .map_err(Error::CannotSendRequest)
+                .send_logged(timeout)
                 .and_then(|response| match response.status() {
                     http::StatusCode::OK => {
                         future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
                     }
-                    status => future::Either::B(future::err(Error::CannotListSessionBlobs(status))),
+                    _ => future::Either::B(response_error(response, Error::CannotListSessionBlobs)),
                 })
                 .and_then(|answer_json: Vec<BlobInfo>| future::ok(answer_json.into_iter())),
         )
     }
     /// gets information about hub session
     pub fn info(&self) -> impl Future<Item = HubSessionSpec, Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let url = format!(
             "{}sessions/{}",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        match client::ClientRequest::get(url).finish() {
-            Ok(r) => future::Either::A(r.send().map_err(Error::CannotSendRequest).and_then(
-                |response| match response.status() {
-                    http::StatusCode::OK => {
-                        future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
-                    }
-                    status => future::Either::B(future::err(Error::CannotGetHubSession(status))),
-                },
-            )),
+        match self.hub_connection.request(http::Method::GET, url).finish() {
+            Ok(r) => {
+                future::Either::A(r.send_logged(timeout).and_then(
+                    |response| match response.status() {
+                        http::StatusCode::OK => {
+                            future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
+                        }
+                        _ => {
+                            future::Either::B(response_error(response, Error::CannotGetHubSession))
+                        }
+                    },
+                ))
+            }
             Err(e) => future::Either::B(future::err(Error::CannotCreateRequest(e))),
         }
     }
-    /// sets hub session config
-    pub fn set_config(&self, config: Metadata) -> impl Future<Item = (), Error = Error> {
+    /// checks whether the hub session still exists
+    pub fn exists(&self) -> impl Future<Item = bool, Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let url = format!(
-            "{}sessions/{}/config",
+            "{}sessions/{}",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        future::result(client::ClientRequest::put(url).json(config))
+        future::result(self.hub_connection.request(http::Method::GET, url).finish())
             .map_err(Error::CannotCreateRequest)
-            .and_then(|request| request.send().map_err(Error::CannotSendRequest))
+            .and_then(move |request| request.send_logged(timeout))
             .and_then(|response| match response.status() {
-                http::StatusCode::OK => future::ok(()),
-                status => future::err(Error::CannotSetHubSessionConfig(status)),
+                http::StatusCode::OK => future::Either::A(future::ok(true)),
+                http::StatusCode::NOT_FOUND => future::Either::A(future::ok(false)),
+                _ => future::Either::B(response_error(response, Error::CannotGetHubSession)),
             })
     }
-    /// gets hub session config
-    pub fn config(&self) -> impl Future<Item = Metadata, Error = Error> {
+    /// sets hub session config
+    pub fn set_config(&self, config: Metadata) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/config",
+            self.hub_connection.hub_connection_inner.url, self.session_id
+        );
+        future::result(
+            self.hub_connection
+                .request(http::Method::PUT, url)
+                .json(config),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => future::Either::A(future::ok(())),
+            _ => future::Either::B(response_error(response, Error::CannotSetHubSessionConfig)),
+        })
+    }
+    /// updates hub session config with JSON merge patch (RFC 7386); `null` values remove keys
+    pub fn patch_config(&self, patch: serde_json::Value) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/config",
+            self.hub_connection.hub_connection_inner.url, self.session_id
+        );
+        future::result(
+            self.hub_connection
+                .request(http::Method::PATCH, url)
+                .json(patch),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => future::Either::A(future::ok(())),
+            _ => future::Either::B(response_error(response, Error::CannotSetHubSessionConfig)),
+        })
+    }
+    /// sets hub session config, provided it was not changed since `version` was read with `config`
+    pub fn set_config_if_version(
+        &self,
+        config: Metadata,
+        version: u64,
+    ) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/config",
+            self.hub_connection.hub_connection_inner.url, self.session_id
+        );
+        future::result(
+            self.hub_connection
+                .request(http::Method::PUT, url)
+                .header(http::header::IF_MATCH, format!("\"{}\"", version))
+                .json(config),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => future::Either::A(future::ok(())),
+            http::StatusCode::PRECONDITION_FAILED => {
+                future::Either::A(future::err(Error::ConfigVersionConflict))
+            }
+            _ => future::Either::B(response_error(response, Error::CannotSetHubSessionConfig)),
+        })
+    }
+    /// gets hub session config together with its version
+    pub fn config(&self) -> impl Future<Item = (Metadata, u64), Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let url = format!(
             "{}sessions/{}/config",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
-        future::result(client::ClientRequest::get(url).finish())
+        future::result(self.hub_connection.request(http::Method::GET, url).finish())
             .map_err(Error::CannotCreateRequest)
-            .and_then(|request| request.send().map_err(Error::CannotSendRequest))
+            .and_then(move |request| request.send_logged(timeout))
             .and_then(|response| match response.status() {
                 http::StatusCode::OK => {
-                    future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
+                    let etag_version: Option<u64> = response
+                        .headers()
+                        .get(http::header::ETAG)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.trim_matches('"').parse().ok());
+                    future::Either::A(response.json().map_err(Error::InvalidJSONResponse).map(
+                        move |config: Metadata| {
+                            let version = etag_version.unwrap_or(config.version);
+                            (config, version)
+                        },
+                    ))
                 }
-                status => future::Either::B(future::err(Error::CannotGetHubSessionConfig(status))),
+                _ => future::Either::B(response_error(response, Error::CannotGetHubSessionConfig)),
             })
     }
     /// updates hub session
     pub fn update(&self, command: session::Command) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.hub_connection.timeout();
         let url = format!(
             "{}sessions/{}",
             self.hub_connection.hub_connection_inner.url, self.session_id
         );
         future::result(
-            client::ClientRequest::build()
-                .method(actix_web::http::Method::PATCH)
-                .uri(url)
+            self.hub_connection
+                .request(http::Method::PATCH, url)
                 .json(command),
         )
         .map_err(Error::CannotCreateRequest)
-        .and_then(|request| request.send().map_err(Error::CannotSendRequest))
+        .and_then(move |request| request.send_logged(timeout))
         .and_then(|response| match response.status() {
-            http::StatusCode::OK => future::ok(()),
-            status => future::err(Error::CannotUpdateHubSession(status)),
+            http::StatusCode::OK => future::Either::A(future::ok(())),
+            _ => future::Either::B(response_error(response, Error::CannotUpdateHubSession)),
+        })
+    }
+    /// keeps hub session alive by pinging it at most every `interval`
+    ///
+    /// Pings are sent between half of `interval` and `interval` apart, as set
+    /// by the connection's `JitterPolicy`. Pinging stops when the returned guard
+    /// is dropped or released, when the session is deleted or released, or when
+    /// the session no longer exists on the hub.
+    pub fn keep_alive(&self, interval: Duration) -> KeepAlive {
+        let (tx, rx) = oneshot::channel();
+        let (release_tx, release_rx) = oneshot::channel();
+        {
+            let mut keep_alives = self.keep_alives.lock().unwrap();
+            keep_alives.retain(|stop| !stop.is_canceled());
+            keep_alives.push(release_tx);
+        }
+        let session = self.clone();
+        let jitter = self.hub_connection.jitter().clone();
+
+        Arbiter::spawn(
+            future::loop_fn(interval, move |previous| {
+                let jitter = jitter.clone();
+                session.info().then(move |r| match r {
+                    Err(ref e) if e.status() == Some(http::StatusCode::NOT_FOUND) => {
+                        future::Either::A(future::ok(Loop::Break(())))
+                    }
+                    _ => {
+                        let delay = jitter.next_delay(interval / 2, previous, interval);
+                        future::Either::B(
+                            Delay::new(Instant::now() + delay)
+                                .map_err(|_| ())
+                                .map(move |()| Loop::Continue(delay)),
+                        )
+                    }
+                })
+            })
+            .select(rx.select2(release_rx).then(|_| Ok(())))
+            .then(|_| Ok(())),
+        );
+
+        KeepAlive { _stop: tx }
+    }
+
+    /// returns stream of changes of this session pushed by the hub over WebSocket
+    ///
+    /// A lost connection is reestablished with backoff (randomized by the
+    /// connection's `JitterPolicy`) until the stream
+    /// is dropped. The stream ends with `Error::SessionNotFound` when the session is gone.
+    pub fn subscribe_events(&self) -> impl Stream<Item = SessionEvent, Error = Error> {
+        let mut url = self.hub_connection.hub_connection_inner.url.clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        let _ = url.set_scheme(scheme);
+        let url = format!("{}sessions/{}/events", url, self.session_id);
+        let connection = self.hub_connection.clone();
+        let session_id = self.session_id.clone();
+        let jitter = self.hub_connection.jitter().clone();
+        let (tx, rx) = mpsc::unbounded();
+
+        Arbiter::spawn(future::loop_fn(EVENTS_RECONNECT_MIN_DELAY, move |delay| {
+            if tx.is_closed() {
+                return future::Either::A(future::ok(Loop::Break(())));
+            }
+            let events_tx = tx.clone();
+            let not_found_tx = tx.clone();
+            let closed_tx = tx.clone();
+            let session_id = session_id.clone();
+            let jitter = jitter.clone();
+
+            future::Either::B(
+                connection
+                    .ws_client(&url)
+                    .connect()
+                    .then(move |r| match r {
+                        Ok((reader, writer)) => future::Either::A(
+                            reader
+                                .map_err(|e| debug!("session events connection lost: {}", e))
+                                .for_each(move |message| match message {
+                                    ws::Message::Text(text) => match serde_json::from_str(&text) {
+                                        Ok(event) => {
+                                            events_tx.unbounded_send(Ok(event)).map_err(|_| ())
+                                        }
+                                        Err(e) => {
+                                            warn!("invalid session event: {}", e);
+                                            Ok(())
+                                        }
+                                    },
+                                    _ => Ok(()),
+                                })
+                                .then(move |_| {
+                                    drop(writer);
+                                    Ok(EVENTS_RECONNECT_MIN_DELAY)
+                                }),
+                        ),
+                        Err(ws::ClientError::InvalidResponseStatus(
+                            http::StatusCode::NOT_FOUND,
+                        )) => {
+                            let _ = not_found_tx
+                                .unbounded_send(Err(Error::SessionNotFound(session_id)));
+                            future::Either::B(future::err(()))
+                        }
+                        Err(e) => {
+                            debug!("cannot connect to session events: {}", e);
+                            future::Either::B(future::ok(jitter.next_delay(
+                                EVENTS_RECONNECT_MIN_DELAY,
+                                delay,
+                                EVENTS_RECONNECT_MAX_DELAY,
+                            )))
+                        }
+                    })
+                    // backoff starts over once a connection was established
+                    .and_then(|wait| {
+                        Delay::new(Instant::now() + wait)
+                            .map_err(|_| ())
+                            .map(move |_| wait)
+                    })
+                    .map(move |wait| {
+                        if closed_tx.is_closed() {
+                            Loop::Break(())
+                        } else {
+                            Loop::Continue(wait)
+                        }
+                    }),
+            )
+        }));
+
+        rx.then(|r| match r {
+            Ok(event) => event,
+            Err(()) => unreachable!("unbounded receiver never fails"),
         })
     }
-    /// deletes hub session
+    /// deletes hub session, stopping its keep-alive tasks
     pub fn delete(self) -> impl Future<Item = (), Error = Error> {
+        for stop in self.keep_alives.lock().unwrap().drain(..) {
+            let _ = stop.send(());
+        }
         let url = format!(
             "{}sessions/{}",
             self.hub_connection.hub_connection_inner.url, self.session_id
@@ -409,6 +1544,21 @@ impl AsyncRelease for HubSession {
     }
 }
 
+/// Guard of the hub session keep-alive task started by `HubSession::keep_alive`.
+///
+/// Dropping or releasing the guard stops the pinging, as does releasing the session.
+#[derive(Debug)]
+pub struct KeepAlive {
+    _stop: oneshot::Sender<()>,
+}
+
+impl AsyncRelease for KeepAlive {
+    type Result = future::FutureResult<(), Error>;
+    fn release(self) -> Self::Result {
+        future::ok(())
+    }
+}
+
 /// Large binary object.
 #[derive(Clone, Debug)]
 pub struct Blob {
@@ -417,135 +1567,776 @@ pub struct Blob {
 }
 
 impl Blob {
+    /// address the blob is uploaded to and downloaded from
+    fn url(&self) -> String {
+        format!(
+            "{}sessions/{}/blobs/{}",
+            self.hub_session.hub_connection.hub_connection_inner.url,
+            self.hub_session.session_id,
+            self.blob_id
+        )
+    }
+
     /// uploads blob represented by a stream
-    pub fn upload_from_stream<S, T>(&self, stream: S) -> impl Future<Item = (), Error = Error>
+    ///
+    /// The content type is stored by the hub and sent back on download;
+    /// `application/octet-stream` is used when none is given.
+    pub fn upload_from_stream<S, T>(
+        &self,
+        stream: S,
+        content_type: Option<&str>,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        S: Stream<Item = Bytes, Error = T> + 'static,
+        T: Into<actix_web::Error>,
+    {
+        self.upload_from_stream_info(stream, content_type)
+            .map(|_| ())
+    }
+    /// uploads blob represented by a stream; returns size and hash computed by the hub
+    pub fn upload_from_stream_info<S, T>(
+        &self,
+        stream: S,
+        content_type: Option<&str>,
+    ) -> impl Future<Item = BlobInfo, Error = Error>
+    where
+        S: Stream<Item = Bytes, Error = T> + 'static,
+        T: Into<actix_web::Error>,
+    {
+        self.upload(stream, content_type, None)
+    }
+    /// uploads content of a local file
+    pub fn upload_from_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> impl Future<Item = (), Error = Error> {
+        let (file, size) = match fs::File::open(path)
+            .and_then(|file| file.metadata().map(|metadata| (file, metadata.len())))
+        {
+            Ok(v) => v,
+            Err(e) => return future::Either::A(future::err(Error::IoError(e))),
+        };
+        future::Either::B(self.upload(file_chunks(file), None, Some(size)).map(|_| ()))
+    }
+
+    /// uploads everything read from `reader`, e.g. a tokio file or socket
+    ///
+    /// The content is sent in chunks of the connection's upload chunk size.
+    /// The upload fails with `Error::IoError` when reading fails.
+    pub fn upload_from_reader<R>(&self, reader: R) -> impl Future<Item = (), Error = Error>
+    where
+        R: AsyncRead + 'static,
+    {
+        let read_error = Rc::new(RefCell::new(None));
+        let failed_read = read_error.clone();
+        let stream = FramedRead::new(reader, BytesCodec::new())
+            .map(BytesMut::freeze)
+            .map_err(move |e: io::Error| {
+                let upload_error = io::Error::new(e.kind(), e.to_string());
+                *failed_read.borrow_mut() = Some(e);
+                upload_error
+            });
+
+        self.upload(stream, None, None)
+            .then(move |r| match read_error.borrow_mut().take() {
+                Some(e) => Err(Error::IoError(e)),
+                None => r.map(|_| ()),
+            })
+    }
+
+    fn upload<S, T>(
+        &self,
+        stream: S,
+        content_type: Option<&str>,
+        content_length: Option<u64>,
+    ) -> impl Future<Item = BlobInfo, Error = Error>
     where
         S: Stream<Item = Bytes, Error = T> + 'static,
         T: Into<actix_web::Error>,
     {
+        let timeout = self.hub_session.hub_connection.timeout();
         let url = format!(
             "{}sessions/{}/blobs/{}",
             self.hub_session.hub_connection.hub_connection_inner.url,
             self.hub_session.session_id,
             self.blob_id
         );
-        let request = match client::ClientRequest::put(url).streaming(stream) {
+        let mut builder = self
+            .hub_session
+            .hub_connection
+            .request(http::Method::PUT, url);
+        builder.header(
+            http::header::CONTENT_TYPE,
+            content_type.unwrap_or("application/octet-stream"),
+        );
+        let chunk_size = self.hub_session.hub_connection.upload_chunk_size();
+        let limiter = self.hub_session.hub_connection.rate_limiter();
+        let stream = stream.map_err(Into::<actix_web::Error>::into);
+        let body: Box<Stream<Item = Bytes, Error = actix_web::Error>> =
+            if self.hub_session.hub_connection.transfer_compression() {
+                // compressed length is not known up front
+                builder.header(http::header::CONTENT_ENCODING, "gzip");
+                Box::new(gzip_stream_with(stream, actix_web::Error::from))
+            } else {
+                if let Some(length) = content_length {
+                    builder.header(http::header::CONTENT_LENGTH, length.to_string());
+                }
+                Box::new(stream)
+            };
+        let request = match builder.streaming(throttle(rechunk(body, chunk_size), limiter)) {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
-        future::Either::B(
-            request
-                .send()
-                .map_err(Error::CannotSendRequest)
-                .and_then(|response| match response.status() {
-                    http::StatusCode::OK => future::ok(()),
-                    status => future::err(Error::CannotUploadBlobFromStream(status)),
-                }),
+        future::Either::B(request.send_logged(timeout).and_then(
+            |response| match response.status() {
+                http::StatusCode::OK => {
+                    future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
+                }
+                _ => future::Either::B(response_error(response, Error::CannotUploadBlobFromStream)),
+            },
+        ))
+    }
+    /// uploads blob represented by a stream, starting at given offset
+    ///
+    /// Content stored by the hub past `offset` is replaced. Together with `uploaded_size`
+    /// it allows to resume an interrupted upload.
+    pub fn upload_from_stream_resumable<S, T>(
+        &self,
+        stream: S,
+        offset: u64,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        S: Stream<Item = Bytes, Error = T> + 'static,
+        T: Into<actix_web::Error>,
+    {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/blobs/{}",
+            self.hub_session.hub_connection.hub_connection_inner.url,
+            self.hub_session.session_id,
+            self.blob_id
+        );
+        let request = match self
+            .hub_session
+            .hub_connection
+            .request(http::Method::PUT, url)
+            .header(http::header::CONTENT_RANGE, format!("bytes {}-*/*", offset))
+            .streaming(throttle(
+                rechunk(stream, self.hub_session.hub_connection.upload_chunk_size()),
+                self.hub_session.hub_connection.rate_limiter(),
+            )) {
+            Ok(r) => r,
+            Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
+        };
+        future::Either::B(request.send_logged(timeout).and_then(
+            |response| match response.status() {
+                status if status.is_success() => future::Either::A(future::ok(())),
+                _ => future::Either::B(response_error(response, Error::CannotUploadBlobFromStream)),
+            },
+        ))
+    }
+    /// returns number of bytes of the blob already stored by the hub
+    pub fn uploaded_size(&self) -> impl Future<Item = u64, Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/blobs/{}",
+            self.hub_session.hub_connection.hub_connection_inner.url,
+            self.hub_session.session_id,
+            self.blob_id
+        );
+        future::result(
+            self.hub_session
+                .hub_connection
+                .request(http::Method::HEAD, url)
+                .finish(),
         )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => {
+                future::Either::A(future::ok(content_length(&response).unwrap_or(0)))
+            }
+            _ => future::Either::B(response_error(response, Error::CannotGetBlobSize)),
+        })
     }
     /// downloads blob
     pub fn download(&self) -> impl Stream<Item = Bytes, Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let limiter = self.hub_session.hub_connection.rate_limiter();
         let url = format!(
             "{}sessions/{}/blobs/{}",
             self.hub_session.hub_connection.hub_connection_inner.url,
             self.hub_session.session_id,
             self.blob_id
         );
-        future::result(client::ClientRequest::get(url).finish())
+        future::result(self.hub_session.hub_connection.blob_request(url))
             .map_err(Error::CannotCreateRequest)
-            .and_then(|request| request.send().map_err(Error::CannotSendRequest))
-            .and_then(|response| match response.status() {
+            .and_then(move |request| request.send_logged(timeout))
+            .and_then(move |response| match response.status() {
                 http::StatusCode::OK => {
-                    future::ok(response.payload().map_err(Error::CannotReceiveBlobBody))
+                    future::Either::A(future::ok(blob_body(&response, limiter)))
                 }
-                status => future::err(Error::CannotReceiveBlob(status)),
+                _ => future::Either::B(response_error(response, Error::CannotReceiveBlob)),
             })
             .flatten_stream()
     }
-    /// deletes blob
-    pub fn delete(self) -> impl Future<Item = (), Error = Error> {
-        let remove_url = format!(
+    /// downloads part of the blob, from `start` to `end` inclusive, or to the end of the blob
+    /// when `end` is not given
+    pub fn download_range(
+        &self,
+        start: u64,
+        end: Option<u64>,
+    ) -> impl Stream<Item = Bytes, Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let limiter = self.hub_session.hub_connection.rate_limiter();
+        let range = match end {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => format!("bytes={}-", start),
+        };
+        future::result(
+            self.hub_session
+                .hub_connection
+                .request(http::Method::GET, self.url())
+                .header(http::header::RANGE, range)
+                .finish(),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(move |response| match response.status() {
+            // the hub answers with 200 when the range covers whole content
+            http::StatusCode::PARTIAL_CONTENT | http::StatusCode::OK => {
+                future::Either::A(future::ok(throttle(
+                    response.payload().map_err(Error::CannotReceiveBlobBody),
+                    limiter,
+                )))
+            }
+            http::StatusCode::RANGE_NOT_SATISFIABLE => {
+                future::Either::B(future::Either::A(future::err(Error::RangeNotSatisfiable)))
+            }
+            _ => future::Either::B(future::Either::B(response_error(
+                response,
+                Error::CannotReceiveBlob,
+            ))),
+        })
+        .flatten_stream()
+    }
+    /// downloads blob, reporting progress after each received chunk
+    ///
+    /// The callback gets the number of bytes received so far and the total blob size
+    /// taken from the `Content-Length` header (`None` when the hub sends a chunked response).
+    pub fn download_with_progress<F>(
+        &self,
+        mut progress: F,
+    ) -> impl Stream<Item = Bytes, Error = Error>
+    where
+        F: FnMut(u64, Option<u64>) + 'static,
+    {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let limiter = self.hub_session.hub_connection.rate_limiter();
+        let url = format!(
             "{}sessions/{}/blobs/{}",
             self.hub_session.hub_connection.hub_connection_inner.url,
             self.hub_session.session_id,
             self.blob_id
         );
-        let request = match client::ClientRequest::delete(remove_url).finish() {
-            Ok(r) => r,
-            Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
+        future::result(self.hub_session.hub_connection.blob_request(url))
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |request| request.send_logged(timeout))
+            .and_then(move |response| match response.status() {
+                http::StatusCode::OK => {
+                    let total = content_length(&response);
+                    let mut received = 0u64;
+                    future::Either::A(future::ok(blob_body(&response, limiter).map(
+                        move |chunk| {
+                            received += chunk.len() as u64;
+                            progress(received, total);
+                            chunk
+                        },
+                    )))
+                }
+                _ => future::Either::B(response_error(response, Error::CannotReceiveBlob)),
+            })
+            .flatten_stream()
+    }
+    /// downloads blob, checking its content against `expected_hash`
+    ///
+    /// The hash is given as `<algorithm>:<hex value>`, e.g. `sha256:...`. The digest is
+    /// computed while bytes flow; the stream fails with `Error::ChecksumMismatch` at its
+    /// end if the content does not match.
+    pub fn download_verified(
+        &self,
+        expected_hash: &str,
+    ) -> impl Stream<Item = Bytes, Error = Error> {
+        let expected = expected_hash.to_string();
+        let download = self.download();
+
+        future::result(
+            hash::ParsedHash::from_hash_bytes(expected_hash.as_bytes())
+                .and_then(|parsed| Ok((parsed.algo_name()?.to_string(), parsed.digest()?)))
+                .map_err(Error::InvalidHash),
+        )
+        .map(move |(algo_name, digest)| {
+            let digest = Rc::new(RefCell::new(Some(digest)));
+            let end_digest = digest.clone();
+
+            let verify = future::lazy(move || {
+                let digest = end_digest.borrow_mut().take().unwrap();
+                let actual = format!("{}:{}", algo_name, to_hex_str(&digest.result()));
+                if actual.eq_ignore_ascii_case(&expected) {
+                    Ok(())
+                } else {
+                    Err(Error::ChecksumMismatch { expected, actual })
+                }
+            })
+            .into_stream()
+            .filter_map(|()| None);
+
+            download
+                .inspect(move |chunk| digest.borrow_mut().as_mut().unwrap().input(chunk))
+                .chain(verify)
+        })
+        .flatten_stream()
+    }
+    /// downloads blob into a local file; returns the number of bytes written
+    ///
+    /// Content goes to a temporary file next to `path` and is renamed into place
+    /// once complete, so a failed download leaves no partially written file.
+    pub fn download_to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> impl Future<Item = u64, Error = Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(path.file_name().unwrap_or_default());
+        tmp_name.push(".download");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let file = match fs::File::create(&tmp_path) {
+            Ok(file) => file,
+            Err(e) => return future::Either::A(future::err(Error::IoError(e))),
         };
+        let cleanup_path = tmp_path.clone();
+
         future::Either::B(
-            request
-                .send()
-                .map_err(Error::CannotSendRequest)
-                .and_then(|response| match response.status() {
-                    http::StatusCode::OK => future::ok(()),
-                    status_code => future::err(Error::CannotDeleteBlob(status_code)),
+            self.download()
+                .fold((file, 0u64), |(mut file, written), chunk| {
+                    match file.write_all(&chunk) {
+                        Ok(()) => Ok((file, written + chunk.len() as u64)),
+                        Err(e) => Err(Error::IoError(e)),
+                    }
+                })
+                .and_then(move |(file, written)| {
+                    file.sync_all()
+                        .and_then(|()| {
+                            drop(file);
+                            fs::rename(&tmp_path, &path)
+                        })
+                        .map(|()| written)
+                        .map_err(Error::IoError)
+                })
+                .map_err(move |e| {
+                    let _ = fs::remove_file(&cleanup_path);
+                    e
                 }),
         )
     }
-}
-
-/// Peer node.
-#[derive(Clone, Debug)]
-pub struct Peer {
-    hub_session: HubSession,
-    pub node_id: NodeId,
-}
+    /// copies the blob into other session of the same hub; returns the new blob
+    ///
+    /// Nothing is transferred by the client: the hub shares the stored data between
+    /// both blobs when it can, and copies it otherwise.
+    pub fn copy_to(&self, dest_session: &HubSession) -> impl Future<Item = Blob, Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let url = format!("{}/copy?toSession={}", self.url(), dest_session.session_id);
+        let blob_id = self.blob_id.clone();
+        let dest_session = dest_session.clone();
 
-impl Peer {
-    /// creates new peer session
-    pub fn new_session(
-        &self,
-        session_info: envman::CreateSession,
-    ) -> impl Future<Item = PeerSession, Error = Error> {
-        let url = format!(
-            "{}sessions/{}/peers/{}/deployments",
+        future::result(
+            self.hub_session
+                .hub_connection
+                .request(http::Method::POST, url)
+                .finish(),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(move |response| match response.status() {
+            http::StatusCode::CREATED => {
+                future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
+            }
+            http::StatusCode::NOT_FOUND => {
+                future::Either::B(future::Either::A(future::err(Error::BlobNotFound(blob_id))))
+            }
+            _ => future::Either::B(future::Either::B(response_error(
+                response,
+                Error::CannotCopyBlob,
+            ))),
+        })
+        .map(move |info: BlobInfo| dest_session.blob(info.id))
+    }
+    /// deletes blob
+    pub fn delete(self) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let remove_url = format!(
+            "{}sessions/{}/blobs/{}",
             self.hub_session.hub_connection.hub_connection_inner.url,
             self.hub_session.session_id,
-            self.node_id.to_string()
+            self.blob_id
         );
-        let request = match client::ClientRequest::post(url).json(session_info) {
+        let request = match self
+            .hub_session
+            .hub_connection
+            .request(http::Method::DELETE, remove_url)
+            .finish()
+        {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
-        let peer_copy = self.clone();
-        future::Either::B(
-            request
-                .send()
-                .timeout(Duration::from_secs(3600))
-                .map_err(Error::CannotSendRequest)
-                .and_then(|response| {
-                    if response.status() != http::StatusCode::CREATED {
-                        return future::Either::A(future::err(Error::CannotCreatePeerSession(
-                            response.status(),
-                        )));
-                    }
-                    future::Either::B(response.json().map_err(Error::InvalidJSONResponse))
-                })
-                .and_then(|answer_json: String| {
-                    future::ok(PeerSession {
-                        peer: peer_copy,
-                        session_id: answer_json,
-                    })
-                }),
-        )
+        future::Either::B(request.send_logged(timeout).and_then(
+            |response| match response.status() {
+                http::StatusCode::OK => future::Either::A(future::ok(())),
+                _ => future::Either::B(response_error(response, Error::CannotDeleteBlob)),
+            },
+        ))
+    }
+}
+
+/// fails with the message of an unexpected response, read from its `{"error": "..."}`
+/// JSON body or, as the hub mostly replies, its plain text body; `fallback` builds
+/// the error when the body carries no message
+fn response_error<T, F>(
+    response: client::ClientResponse,
+    fallback: F,
+) -> impl Future<Item = T, Error = Error>
+where
+    F: FnOnce(http::StatusCode) -> Error,
+{
+    let status = response.status();
+    response.body().limit(ERROR_BODY_LIMIT).then(move |body| {
+        let message = body.ok().and_then(|body| error_message(&body));
+        Err(match message {
+            Some(message) => Error::Server { status, message },
+            None => fallback(status),
+        })
+    })
+}
+
+/// message of an error response body; JSON bodies carry it in the `error` field
+fn error_message(body: &[u8]) -> Option<String> {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(body) => body["error"].as_str().map(str::to_string),
+        Err(_) => str::from_utf8(body)
+            .ok()
+            .map(str::trim)
+            .filter(|message| !message.is_empty())
+            .map(str::to_string),
+    }
+}
+
+/// parses newline delimited JSON body of the response, value by value
+fn json_lines<T: DeserializeOwned>(
+    response: client::ClientResponse,
+) -> impl Stream<Item = T, Error = Error> {
+    let mut pending = BytesMut::new();
+
+    response
+        .payload()
+        .map_err(Error::CannotGetResponseBody)
+        .map(move |chunk| {
+            pending.extend_from_slice(&chunk);
+            let mut values = Vec::new();
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line = pending.split_to(end + 1);
+                values.push(
+                    serde_json::from_slice(&line[..end])
+                        .map_err(|e| Error::InvalidJSONResponse(JsonPayloadError::Deserialize(e))),
+                );
+            }
+            stream::iter_result(values)
+        })
+        .flatten()
+}
+
+fn content_length(response: &client::ClientResponse) -> Option<u64> {
+    response
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// blob content of the response, decompressed when the hub sent it gzip encoded
+fn blob_body(
+    response: &client::ClientResponse,
+    limiter: Option<RateLimiter>,
+) -> Box<Stream<Item = Bytes, Error = Error>> {
+    let gzip_encoded = response
+        .headers()
+        .get(http::header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().eq_ignore_ascii_case("gzip"))
+        .unwrap_or(false);
+    let body = throttle(
+        response.payload().map_err(Error::CannotReceiveBlobBody),
+        limiter,
+    );
+    if gzip_encoded {
+        Box::new(gunzip_stream_with(body, Error::IoError))
+    } else {
+        Box::new(body)
+    }
+}
+
+/// joins small chunks and splits big ones into `chunk_size` buffers; only the last one may be shorter
+fn rechunk<S>(stream: S, chunk_size: usize) -> impl Stream<Item = Bytes, Error = S::Error>
+where
+    S: Stream<Item = Bytes>,
+{
+    let mut buf = BytesMut::new();
+
+    stream
+        .map(Some)
+        .chain(stream::once(Ok(None)))
+        .map(move |chunk| {
+            let mut chunks = Vec::new();
+            match chunk {
+                Some(chunk) => {
+                    buf.extend_from_slice(&chunk);
+                    while buf.len() >= chunk_size {
+                        chunks.push(buf.split_to(chunk_size).freeze());
+                    }
+                }
+                None if !buf.is_empty() => chunks.push(buf.take().freeze()),
+                None => (),
+            }
+            stream::iter_ok(chunks)
+        })
+        .flatten()
+}
+
+/// Randomizes delays between repeated requests of polling helpers, so that
+/// clients started together do not hit the hub at the same moments.
+///
+/// Delays follow "decorrelated jitter": each is drawn between the minimal one
+/// and three times the previous delay, capped. Clones share the generator.
+#[derive(Clone, Debug)]
+pub struct JitterPolicy(Arc<Mutex<u64>>);
+
+impl Default for JitterPolicy {
+    fn default() -> Self {
+        static INSTANCES: AtomicUsize = AtomicUsize::new(0);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let instance = INSTANCES.fetch_add(1, Ordering::Relaxed) as u64;
+        JitterPolicy::seeded(now.as_secs() ^ (u64::from(now.subsec_nanos()) << 32) ^ instance)
+    }
+}
+
+impl JitterPolicy {
+    /// jitter repeating the same sequence of delays for a given seed, e.g. in tests
+    pub fn seeded(seed: u64) -> Self {
+        JitterPolicy(Arc::new(Mutex::new(seed)))
+    }
+
+    /// delay to wait after `previous` one, between `min` and `max`
+    pub fn next_delay(&self, min: Duration, previous: Duration, max: Duration) -> Duration {
+        let upper = cmp::max(min, cmp::min(max, previous * 3));
+        let span = duration_nanos(upper - min);
+        min + Duration::from_nanos(self.next_u64() % (span + 1))
+    }
+
+    /// splitmix64 step
+    fn next_u64(&self) -> u64 {
+        let mut state = self.0.lock().unwrap();
+        *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+fn duration_nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+/// Token bucket shared by blob transfers of a connection.
+///
+/// Transfers may run into debt, so every chunk is delayed by the time needed
+/// to earn the bytes sent before it.
+#[derive(Clone, Debug)]
+struct RateLimiter(Arc<Mutex<TokenBucket>>);
+
+#[derive(Debug)]
+struct TokenBucket {
+    bytes_per_sec: u64,
+    /// bytes which can be sent right away; negative when in debt
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter(Arc::new(Mutex::new(TokenBucket {
+            bytes_per_sec,
+            available: bytes_per_sec as f64,
+            updated: Instant::now(),
+        })))
+    }
+
+    /// takes `bytes` from the bucket; returns the time they may be sent at
+    fn reserve(&self, bytes: usize) -> Instant {
+        let mut bucket = self.0.lock().unwrap();
+        let now = Instant::now();
+        let rate = bucket.bytes_per_sec as f64;
+        let elapsed = now.duration_since(bucket.updated);
+        let refill = (elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9) * rate;
+
+        // at most one second of transfer is saved up
+        bucket.available = (bucket.available + refill).min(rate) - bytes as f64;
+        bucket.updated = now;
+        if bucket.available >= 0.0 {
+            now
+        } else {
+            now + Duration::from_nanos((-bucket.available / rate * 1e9) as u64)
+        }
+    }
+}
+
+/// delays chunks of the stream to keep the limit
+fn throttle<S>(
+    stream: S,
+    limiter: Option<RateLimiter>,
+) -> impl Stream<Item = Bytes, Error = S::Error>
+where
+    S: Stream<Item = Bytes>,
+{
+    stream.and_then(move |chunk| match limiter {
+        None => future::Either::A(future::ok(chunk)),
+        // timer errors only stop the throttling
+        Some(ref limiter) => {
+            future::Either::B(Delay::new(limiter.reserve(chunk.len())).then(move |_| Ok(chunk)))
+        }
+    })
+}
+
+/// reads the file in `FILE_CHUNK_SIZE` chunks
+fn file_chunks(file: fs::File) -> impl Stream<Item = Bytes, Error = io::Error> {
+    stream::unfold(file, |mut file| {
+        let mut buf = vec![0; FILE_CHUNK_SIZE];
+        match file.read(&mut buf) {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some(Ok((Bytes::from(buf), file)))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    })
+}
+
+fn to_hex_str(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Peer node.
+#[derive(Clone, Debug)]
+pub struct Peer {
+    hub_session: HubSession,
+    pub node_id: NodeId,
+}
+
+impl Peer {
+    /// creates new peer session
+    pub fn new_session(
+        &self,
+        session_info: envman::CreateSession,
+    ) -> impl Future<Item = PeerSession, Error = Error> {
+        let deployment_timeout = self.hub_session.hub_connection.deployment_timeout();
+        let url = format!(
+            "{}sessions/{}/peers/{}/deployments",
+            self.hub_session.hub_connection.hub_connection_inner.url,
+            self.hub_session.session_id,
+            self.node_id.to_string()
+        );
+        let request = match self
+            .hub_session
+            .hub_connection
+            .request(http::Method::POST, url)
+            .json(session_info)
+        {
+            Ok(r) => r,
+            Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
+        };
+        let peer_copy = self.clone();
+        future::Either::B(
+            request
+                .send_logged(deployment_timeout)
+                .and_then(|response| {
+                    if response.status() != http::StatusCode::CREATED {
+                        return future::Either::A(response_error(
+                            response,
+                            Error::CannotCreatePeerSession,
+                        ));
+                    }
+                    future::Either::B(response.json().map_err(Error::InvalidJSONResponse))
+                })
+                .and_then(|answer_json: String| {
+                    future::ok(PeerSession {
+                        peer: peer_copy,
+                        session_id: answer_json,
+                    })
+                }),
+        )
+    }
+    /// returns all peer sessions created within the hub session
+    pub fn list_sessions(
+        &self,
+    ) -> impl Future<Item = impl Iterator<Item = PeerSessionInfo>, Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/peers/{}/deployments",
+            self.hub_session.hub_connection.hub_connection_inner.url,
+            self.hub_session.session_id,
+            self.node_id.to_string()
+        );
+        future::result(
+            self.hub_session
+                .hub_connection
+                .request(http::Method::GET, &url)
+                .finish(),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => {
+                future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
+            }
+            _ => future::Either::B(response_error(response, Error::CannotListPeerSessions)),
+        })
+        .and_then(|answer_json: Vec<PeerSessionInfo>| future::ok(answer_json.into_iter()))
     }
     /// gets peer information
     pub fn info(&self) -> impl Future<Item = PeerInfo, Error = Error> {
+        let timeout = self.hub_session.hub_connection.timeout();
         let url = format!(
             "{}peers/{:?}",
             self.hub_session.hub_connection.hub_connection_inner.url, self.node_id
         );
-        future::result(client::ClientRequest::get(&url).finish())
-            .map_err(Error::CannotCreateRequest)
-            .and_then(|request| request.send().map_err(Error::CannotSendRequest))
-            .and_then(|response| match response.status() {
-                http::StatusCode::OK => {
-                    future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
-                }
-                status => future::Either::B(future::err(Error::CannotGetPeerInfo(status))),
-            })
+        future::result(
+            self.hub_session
+                .hub_connection
+                .request(http::Method::GET, &url)
+                .finish(),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => {
+                future::Either::A(response.json().map_err(Error::InvalidJSONResponse))
+            }
+            _ => future::Either::B(response_error(response, Error::CannotGetPeerInfo)),
+        })
     }
 }
 
@@ -562,8 +2353,27 @@ impl PeerSession {
         &self,
         commands: Vec<envman::Command>,
     ) -> impl Future<Item = Vec<String>, Error = Error> {
+        self.send_update(commands, false)
+    }
+
+    /// like `update`, but commands following a failed one are still run
+    ///
+    /// Returns result of every command; failed ones are reported as `command <index> failed: <error>`.
+    pub fn update_best_effort(
+        &self,
+        commands: Vec<envman::Command>,
+    ) -> impl Future<Item = Vec<String>, Error = Error> {
+        self.send_update(commands, true)
+    }
+
+    fn send_update(
+        &self,
+        commands: Vec<envman::Command>,
+        continue_on_error: bool,
+    ) -> impl Future<Item = Vec<String>, Error = Error> {
+        let timeout = self.peer.hub_session.hub_connection.timeout();
         let url = format!(
-            "{}sessions/{}/peers/{}/deployments/{}",
+            "{}sessions/{}/peers/{}/deployments/{}?continueOnError={}",
             self.peer
                 .hub_session
                 .hub_connection
@@ -572,24 +2382,126 @@ impl PeerSession {
             self.peer.hub_session.session_id,
             self.peer.node_id.to_string(),
             self.session_id,
+            continue_on_error,
         );
         future::result(
-            client::ClientRequest::build()
-                .method(actix_web::http::Method::PATCH)
-                .uri(url)
+            self.peer
+                .hub_session
+                .hub_connection
+                .request(http::Method::PATCH, url)
                 .json(commands),
         )
         .map_err(Error::CannotCreateRequest)
-        .and_then(|request| request.send().map_err(Error::CannotSendRequest))
+        .and_then(move |request| request.send_logged(timeout))
         .and_then(|response| match response.status() {
             http::StatusCode::OK => {
                 future::Either::A(response.json().map_err(|e| Error::InvalidJSONResponse(e)))
             }
-            status => future::Either::B(future::err(Error::CannotUpdateDeployment(status))),
+            _ => future::Either::B(response_error(response, Error::CannotUpdateDeployment)),
+        })
+    }
+    /// cancels updates of this peer session which are still in progress
+    ///
+    /// Pending `update` calls fail; commands already started on the peer are not undone.
+    pub fn cancel_update(&self) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.peer.hub_session.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/peers/{}/deployments/{}/cancel",
+            self.peer
+                .hub_session
+                .hub_connection
+                .hub_connection_inner
+                .url,
+            self.peer.hub_session.session_id,
+            self.peer.node_id.to_string(),
+            self.session_id,
+        );
+        future::result(
+            self.peer
+                .hub_session
+                .hub_connection
+                .request(http::Method::POST, url)
+                .finish(),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => future::Either::A(future::ok(())),
+            _ => future::Either::B(response_error(response, Error::CannotCancelUpdate)),
+        })
+    }
+    /// returns output of this peer session, line by line
+    ///
+    /// With `follow` the stream does not end after the current output, but waits for new lines.
+    pub fn logs(&self, follow: bool) -> impl Stream<Item = envman::LogLine, Error = Error> {
+        let timeout = self.peer.hub_session.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/peers/{}/deployments/{}/logs?follow={}",
+            self.peer
+                .hub_session
+                .hub_connection
+                .hub_connection_inner
+                .url,
+            self.peer.hub_session.session_id,
+            self.peer.node_id.to_string(),
+            self.session_id,
+            follow,
+        );
+
+        future::result(
+            self.peer
+                .hub_session
+                .hub_connection
+                .request(http::Method::GET, url)
+                .finish(),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => future::Either::A(future::ok(json_lines(response))),
+            _ => future::Either::B(response_error(response, Error::CannotGetLogs)),
+        })
+        .flatten_stream()
+    }
+    /// updates deployment session, yielding index and outcome of each command as soon as it completes
+    ///
+    /// Commands are run one by one; the stream ends after the first failed command.
+    pub fn update_streaming(
+        &self,
+        commands: Vec<envman::Command>,
+    ) -> impl Stream<Item = (usize, Result<String, String>), Error = Error> {
+        let timeout = self.peer.hub_session.hub_connection.timeout();
+        let url = format!(
+            "{}sessions/{}/peers/{}/deployments/{}/stream",
+            self.peer
+                .hub_session
+                .hub_connection
+                .hub_connection_inner
+                .url,
+            self.peer.hub_session.session_id,
+            self.peer.node_id.to_string(),
+            self.session_id,
+        );
+
+        future::result(
+            self.peer
+                .hub_session
+                .hub_connection
+                .request(http::Method::POST, url)
+                .json(commands),
+        )
+        .map_err(Error::CannotCreateRequest)
+        .and_then(move |request| request.send_logged(timeout))
+        .and_then(|response| match response.status() {
+            http::StatusCode::OK => future::Either::A(future::ok(json_lines(response))),
+            _ => future::Either::B(response_error(response, Error::CannotUpdateDeployment)),
         })
+        .flatten_stream()
+        .map(|result: envman::CommandResult| (result.index, result.into_result()))
     }
     /// deletes peer session
     pub fn delete(self) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.peer.hub_session.hub_connection.timeout();
         let remove_url = format!(
             "{}sessions/{}/peers/{}/deployments/{}",
             self.peer
@@ -601,19 +2513,22 @@ impl PeerSession {
             self.peer.node_id.to_string(),
             self.session_id,
         );
-        let request = match client::ClientRequest::delete(remove_url).finish() {
+        let request = match self
+            .peer
+            .hub_session
+            .hub_connection
+            .request(http::Method::DELETE, remove_url)
+            .finish()
+        {
             Ok(r) => r,
             Err(e) => return future::Either::A(future::err(Error::CannotCreateRequest(e))),
         };
-        future::Either::B(
-            request
-                .send()
-                .map_err(Error::CannotSendRequest)
-                .and_then(|response| match response.status() {
-                    http::StatusCode::OK => future::ok(()),
-                    status_code => future::err(Error::CannotDeletePeerSession(status_code)),
-                }),
-        )
+        future::Either::B(request.send_logged(timeout).and_then(
+            |response| match response.status() {
+                http::StatusCode::OK => future::Either::A(future::ok(())),
+                _ => future::Either::B(response_error(response, Error::CannotDeletePeerSession)),
+            },
+        ))
     }
 }
 
@@ -652,21 +2567,157 @@ impl DeploymentRef {
         self.info.note.as_ref().map(AsRef::as_ref)
     }
 
+    /// status of the deployment at the time this ref was fetched (see `refresh`)
+    pub fn status(&self) -> DeploymentStatus {
+        self.info.status
+    }
+
+    /// updates deployment by sending multiple peer commands
+    pub fn update(
+        &self,
+        commands: Vec<envman::Command>,
+    ) -> impl Future<Item = Vec<String>, Error = Error> {
+        Self::send_commands(&self.connection, self.url(), commands)
+    }
+
+    /// uploads file from the deployment workspace into a new blob of `hub_session`
+    ///
+    /// The blob is removed when the peer fails to upload the file.
+    pub fn fetch_output<P: Into<String>>(
+        &self,
+        path: P,
+        hub_session: &HubSession,
+    ) -> impl Future<Item = Blob, Error = Error> {
+        let connection = self.connection.clone();
+        let url = self.url();
+        let file_path = path.into();
+        hub_session.new_blob().and_then(move |blob| {
+            let command = envman::Command::UploadFile {
+                uri: blob.url(),
+                file_path,
+                format: envman::ResourceFormat::Raw,
+            };
+            Self::send_commands(&connection, url, vec![command]).then(move |r| match r {
+                Ok(_) => future::Either::A(future::ok(blob)),
+                Err(e) => future::Either::B(blob.delete().then(move |_| Err(e))),
+            })
+        })
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}peers/{:?}/deployments/{}",
+            self.connection.url(),
+            &self.node_id,
+            &self.info.id
+        )
+    }
+
+    fn send_commands(
+        connection: &HubConnection,
+        url: String,
+        commands: Vec<envman::Command>,
+    ) -> impl Future<Item = Vec<String>, Error = Error> {
+        let timeout = connection.timeout();
+        future::result(connection.request(http::Method::PATCH, url).json(commands))
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |request| request.send_logged(timeout))
+            .and_then(|response| match response.status() {
+                http::StatusCode::OK => {
+                    future::Either::A(response.json().map_err(|e| Error::InvalidJSONResponse(e)))
+                }
+                _ => future::Either::B(response_error(response, Error::CannotUpdateDeployment)),
+            })
+    }
+
+    /// re-fetches deployment info, e.g. to see tags and note changed by `update`
+    pub fn refresh(self) -> impl Future<Item = DeploymentRef, Error = Error> {
+        let url = format!(
+            "{}peers/{:?}/deployments/{}",
+            self.connection.url(),
+            &self.node_id,
+            &self.info.id
+        );
+        let DeploymentRef {
+            connection,
+            node_id,
+            ..
+        } = self;
+        connection
+            .fetch_json(&url)
+            .and_then(move |info: DeploymentInfo| {
+                Ok(DeploymentRef {
+                    connection,
+                    node_id,
+                    info,
+                })
+            })
+    }
+
+    /// renames the deployment in place, without recreating it
+    pub fn set_name<S: Into<String>>(
+        self,
+        name: S,
+    ) -> impl Future<Item = DeploymentRef, Error = Error> {
+        self.set_metadata(serde_json::json!({ "name": name.into() }))
+    }
+
+    /// replaces all tags of the deployment in place, without recreating it
+    pub fn set_tags<T, I>(self, tags: I) -> impl Future<Item = DeploymentRef, Error = Error>
+    where
+        T: Into<String>,
+        I: IntoIterator<Item = T>,
+    {
+        let tags: Vec<String> = tags.into_iter().map(Into::into).collect();
+        self.set_metadata(serde_json::json!({ "tags": tags }))
+    }
+
+    fn set_metadata(
+        self,
+        metadata: serde_json::Value,
+    ) -> impl Future<Item = DeploymentRef, Error = Error> {
+        let timeout = self.connection.timeout();
+        let url = format!("{}/metadata", self.url());
+        let DeploymentRef {
+            connection,
+            node_id,
+            ..
+        } = self;
+        future::result(connection.request(http::Method::PATCH, url).json(metadata))
+            .map_err(Error::CannotCreateRequest)
+            .and_then(move |request| request.send_logged(timeout))
+            .and_then(|response| match response.status() {
+                http::StatusCode::OK => {
+                    future::Either::A(response.json().map_err(|e| Error::InvalidJSONResponse(e)))
+                }
+                _ => future::Either::B(response_error(response, Error::CannotUpdateDeployment)),
+            })
+            .and_then(move |info: DeploymentInfo| {
+                Ok(DeploymentRef {
+                    connection,
+                    node_id,
+                    info,
+                })
+            })
+    }
+
     pub fn delete(self) -> impl Future<Item = (), Error = Error> {
+        let timeout = self.connection.timeout();
         let url = format!(
             "{}peers/{:?}/deployments/{}",
             self.connection.url(),
             &self.node_id,
             &self.info.id
         );
-        client::delete(url)
+        self.connection
+            .request(http::Method::DELETE, url)
             .finish()
             .into_future()
             .map_err(Error::CannotCreateRequest)
-            .and_then(|r| r.send().map_err(Error::CannotSendRequest))
+            .and_then(move |r| r.send_logged(timeout))
             .and_then(|response| match response.status() {
-                http::StatusCode::NO_CONTENT => future::ok(()),
-                status_code => future::err(Error::CannotDeletePeerSession(status_code)),
+                http::StatusCode::NO_CONTENT => future::Either::A(future::ok(())),
+                _ => future::Either::B(response_error(response, Error::CannotDeletePeerSession)),
             })
     }
 }
@@ -677,6 +2728,36 @@ impl ProviderRef {
         self.connection.fetch_json(&url)
     }
 
+    /// peer information along with environment types, capacity and free resources
+    /// of the provider
+    pub fn details(&self) -> impl Future<Item = PeerDetails, Error = Error> {
+        let url = format!("{}peers/{:?}", self.connection.url(), self.node_id);
+        self.connection.fetch_json(&url)
+    }
+
+    /// environment types the provider can create deployments in, e.g. `hd`, `docker`
+    pub fn capabilities(&self) -> impl Future<Item = Vec<String>, Error = Error> {
+        self.details().map(|details| details.capabilities)
+    }
+
+    /// resources of the provider free for new sessions; `None` for providers
+    /// not reporting them
+    pub fn resources(
+        &self,
+    ) -> impl Future<Item = Option<envman::ResourceAvailability>, Error = Error> {
+        self.details().map(|details| details.resources)
+    }
+
+    /// environment types accepted as `env_type` of deployments created on the provider
+    pub fn env_types(&self) -> impl Future<Item = Vec<String>, Error = Error> {
+        let url = format!(
+            "{}peers/{:?}/env-types",
+            self.connection.url(),
+            self.node_id
+        );
+        self.connection.fetch_json(&url)
+    }
+
     pub fn deployments(
         &self,
     ) -> impl Future<Item = impl IntoIterator<Item = DeploymentRef>, Error = Error> {
@@ -722,3 +2803,454 @@ impl ProviderRef {
             })
     }
 }
+
+/// Sends requests with their outcome logged.
+trait SendLogged {
+    fn send_logged(self, timeout: Duration) -> LoggedRequest;
+}
+
+impl SendLogged for client::ClientRequest {
+    fn send_logged(self, timeout: Duration) -> LoggedRequest {
+        // nothing is captured when the log would be dropped anyway
+        let context = if log_enabled!(Level::Warn) {
+            Some((self.method().clone(), self.uri().clone(), Instant::now()))
+        } else {
+            None
+        };
+        LoggedRequest {
+            inner: self.send().timeout(timeout),
+            context,
+        }
+    }
+}
+
+/// Request in flight; logs method, url, status and elapsed time once it completes.
+struct LoggedRequest {
+    inner: client::SendRequest,
+    context: Option<(http::Method, http::Uri, Instant)>,
+}
+
+impl Future for LoggedRequest {
+    type Item = client::ClientResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.inner.poll();
+        if let Some((method, uri, started)) = &self.context {
+            match &result {
+                Ok(Async::NotReady) => (),
+                Ok(Async::Ready(response))
+                    if response.status().is_client_error()
+                        || response.status().is_server_error() =>
+                {
+                    warn!(
+                        "{} {} -> {} in {:?}",
+                        method,
+                        uri,
+                        response.status(),
+                        started.elapsed()
+                    )
+                }
+                Ok(Async::Ready(response)) => debug!(
+                    "{} {} -> {} in {:?}",
+                    method,
+                    uri,
+                    response.status(),
+                    started.elapsed()
+                ),
+                Err(e) => warn!(
+                    "{} {} failed after {:?}: {}",
+                    method,
+                    uri,
+                    started.elapsed(),
+                    e
+                ),
+            }
+        }
+        if let Ok(Async::Ready(response)) = &result {
+            if response.status() == http::StatusCode::UNAUTHORIZED {
+                return Err(Error::Unauthorized);
+            }
+        }
+        result.map_err(|e| match e {
+            client::SendRequestError::Connector(client::ClientConnectorError::SslError(e)) => {
+                Error::TlsError(e.to_string())
+            }
+            e => Error::transport(e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::TransportErrorKind;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_hub_registry() {
+        let registry = HubRegistry::parse(
+            "local=127.0.0.1:61622, lab = 10.0.0.2:61622,tls=https://hub.example.com",
+        )
+        .unwrap();
+
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec!["lab", "local", "tls"]
+        );
+        assert_eq!(registry.get("lab").unwrap().url(), "http://10.0.0.2:61622/");
+        assert!(registry.get("tls").unwrap().secure());
+        assert!(registry.get("other").is_none());
+
+        for spec in &["local", "=127.0.0.1:61622", "local=127.0.0.1:port"] {
+            match HubRegistry::parse(spec) {
+                Err(Error::InvalidAddress(_)) => (),
+                r => panic!("unexpected result for {:?}: {:?}", spec, r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_url() {
+        let connection = HubConnection::from_url("https://hub.example.com:8443").unwrap();
+        assert_eq!(connection.url(), "https://hub.example.com:8443/");
+        assert!(connection.secure());
+
+        let connection = HubConnection::from_url("http://127.0.0.1:61622/").unwrap();
+        assert_eq!(connection.url(), "http://127.0.0.1:61622/");
+        assert!(!connection.secure());
+
+        for url in &[
+            "https://hub.example.com/api",
+            "https://hub.example.com/?a=b",
+            "ftp://hub.example.com/",
+            "127.0.0.1:61622",
+        ] {
+            match HubConnection::from_url(url) {
+                Err(Error::InvalidAddress(_)) => (),
+                r => panic!("unexpected result for {:?}: {:?}", url, r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rechunk() {
+        let input = stream::iter_ok::<_, ()>(vec![
+            Bytes::from(vec![1u8; 3]),
+            Bytes::from(vec![2u8; 6]),
+            Bytes::from(vec![3u8; 1]),
+        ]);
+        let sizes: Vec<usize> = rechunk(input, 4)
+            .map(|chunk| chunk.len())
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(sizes, vec![4, 4, 2]);
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let input = stream::iter_ok::<_, io::Error>(
+            content.chunks(7_000).map(Bytes::from).collect::<Vec<_>>(),
+        );
+        let output: Vec<u8> = gunzip_stream_with(gzip_stream_with(input, |e| e), |e| e)
+            .concat2()
+            .wait()
+            .unwrap()
+            .to_vec();
+
+        assert_eq!(output, content);
+    }
+
+    #[test]
+    fn test_delete_all_sessions_not_confirmed() {
+        let connection = HubConnection::from_addr("127.0.0.1:1").unwrap();
+        match connection.delete_all_sessions(false).wait() {
+            Err(Error::DeleteNotConfirmed) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_release_stops_keep_alive() {
+        use std::io::{BufRead, BufReader, Write};
+
+        // hub which accepts every request, counting session pings
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let pings = Arc::new(AtomicUsize::new(0));
+        let hub_pings = pings.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let pings = hub_pings.clone();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut request_line = String::new();
+                        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        while !reader.by_ref().lines().next().unwrap().unwrap().is_empty() {}
+                        let response: &[u8] = if request_line.starts_with("GET") {
+                            pings.fetch_add(1, Ordering::SeqCst);
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}"
+                        } else {
+                            b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n"
+                        };
+                        stream.write_all(response).unwrap();
+                    }
+                });
+            }
+        });
+
+        let session = HubConnection::from_addr(addr.to_string())
+            .unwrap()
+            .hub_session("s1");
+        let after_release = Arc::new(Mutex::new((0, 0)));
+        let counted = after_release.clone();
+
+        actix::System::run(move || {
+            let interval = Duration::from_millis(20);
+            let keep_alive = session.keep_alive(interval);
+            let wait = |millis| Delay::new(Instant::now() + Duration::from_millis(millis));
+
+            Arbiter::spawn(
+                wait(100)
+                    .map_err(|e| panic!("timer: {}", e))
+                    .and_then(move |()| session.release().map_err(|e| panic!("release: {:?}", e)))
+                    // lets pings sent before the release arrive
+                    .and_then(move |()| wait(50).map_err(|e| panic!("timer: {}", e)))
+                    .and_then(move |()| {
+                        let released = pings.load(Ordering::SeqCst);
+                        wait(200)
+                            .map_err(|e| panic!("timer: {}", e))
+                            .map(move |()| (pings, released))
+                    })
+                    .then(move |r: Result<_, ()>| {
+                        let (pings, released) = r.unwrap();
+                        *counted.lock().unwrap() = (released, pings.load(Ordering::SeqCst));
+                        drop(keep_alive);
+                        actix::System::current().stop();
+                        Ok(())
+                    }),
+            )
+        });
+
+        let (released, total) = *after_release.lock().unwrap();
+        assert!(released > 0);
+        assert_eq!(released, total);
+    }
+
+    #[test]
+    fn test_jitter_policy() {
+        let min = Duration::from_millis(500);
+        let max = Duration::from_secs(2);
+        let delays = |jitter: JitterPolicy| {
+            let mut previous = min;
+            (0..20)
+                .map(|_| {
+                    previous = jitter.next_delay(min, previous, max);
+                    previous
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = delays(JitterPolicy::seeded(7));
+        assert_eq!(first, delays(JitterPolicy::seeded(7)));
+        assert_ne!(first, delays(JitterPolicy::seeded(8)));
+        assert!(first.iter().all(|delay| *delay >= min && *delay <= max));
+        assert!(first.windows(2).any(|w| w[0] != w[1]));
+
+        assert_eq!(JitterPolicy::seeded(7).next_delay(max, min, min), max);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+
+        assert!(limiter.reserve(1000) < start + Duration::from_millis(100));
+        assert!(limiter.reserve(500) >= start + Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_parse_node_id() {
+        let node_id = "0x1234567890123456789012345678901234567890";
+        assert_eq!(
+            HubConnection::parse_node_id(node_id).unwrap(),
+            node_id.parse::<NodeId>().unwrap()
+        );
+
+        match HubConnection::default()
+            .hub_session("1")
+            .add_peers(vec![node_id, "0xinvalid"])
+            .wait()
+        {
+            Err(Error::InvalidPeer { ref input, .. }) if input == "0xinvalid" => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_missing_local_file() {
+        let blob = HubConnection::default().hub_session("1").blob("1");
+
+        match blob
+            .upload_from_file("/nonexistent/gu-client-upload")
+            .wait()
+        {
+            Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::NotFound => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match blob
+            .download_to_file("/nonexistent/gu-client-download")
+            .wait()
+        {
+            Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::NotFound => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        // hub which accepts connections, but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let _connections: Vec<_> = listener.incoming().collect();
+        });
+
+        let connection = HubConnection::from_addr(addr.to_string())
+            .unwrap()
+            .with_timeout(Duration::from_millis(200));
+
+        actix::System::run(move || {
+            Arbiter::spawn(connection.list_sessions().then(|r| {
+                match r {
+                    Err(Error::Transport {
+                        kind: TransportErrorKind::Timeout,
+                        ..
+                    }) => (),
+                    Err(e) => panic!("unexpected error: {:?}", e),
+                    Ok(_) => panic!("response not expected"),
+                }
+                actix::System::current().stop();
+                Ok(())
+            }))
+        });
+    }
+
+    #[test]
+    fn test_server_error_message() {
+        use std::io::{BufRead, BufReader, Write};
+
+        // hub which fails every request, explaining why in the body
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let _headers: Vec<String> = BufReader::new(stream.try_clone().unwrap())
+                .lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .collect();
+            let body = r#"{"error": "disk full"}"#;
+            write!(
+                stream,
+                "HTTP/1.1 500 Internal Server Error\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let connection = HubConnection::from_addr(addr.to_string()).unwrap();
+
+        actix::System::run(move || {
+            Arbiter::spawn(connection.server_version().then(|r| {
+                match r {
+                    Err(Error::Server { status, message }) => {
+                        assert_eq!(status, http::StatusCode::INTERNAL_SERVER_ERROR);
+                        assert_eq!(message, "disk full");
+                    }
+                    r => panic!("unexpected result: {:?}", r),
+                }
+                actix::System::current().stop();
+                Ok(())
+            }))
+        });
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message(br#"{"error": "disk full"}"#),
+            Some("disk full".to_string())
+        );
+        assert_eq!(
+            error_message(b"Session not found\n"),
+            Some("Session not found".to_string())
+        );
+        assert_eq!(error_message(br#"{"code": 1}"#), None);
+        assert_eq!(error_message(b" "), None);
+        assert_eq!(error_message(&[0xff, 0xfe]), None);
+
+        let error = Error::Server {
+            status: http::StatusCode::NOT_FOUND,
+            message: "Session not found".into(),
+        };
+        assert_eq!(error.status(), Some(http::StatusCode::NOT_FOUND));
+        assert_eq!(
+            Error::CannotGetPeerInfo(http::StatusCode::NOT_FOUND).status(),
+            Some(http::StatusCode::NOT_FOUND)
+        );
+        assert_eq!(Error::ResourceNotFound.status(), None);
+    }
+
+    #[test]
+    fn test_unauthorized() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::sync::mpsc;
+
+        // hub which rejects every request, reporting the headers it got
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let headers: Vec<String> = BufReader::new(stream.try_clone().unwrap())
+                .lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .map(|line| line.to_lowercase())
+                .collect();
+            stream
+                .write_all(b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            tx.send(headers).unwrap();
+        });
+
+        let connection = HubConnection::from_addr(addr.to_string())
+            .unwrap()
+            .auth_app("test-app", Some("secret"));
+
+        actix::System::run(move || {
+            Arbiter::spawn(connection.server_version().then(|r| {
+                match r {
+                    Err(Error::Unauthorized) => (),
+                    r => panic!("unexpected result: {:?}", r),
+                }
+                actix::System::current().stop();
+                Ok(())
+            }))
+        });
+
+        let headers = rx.recv().unwrap();
+        assert!(headers.contains(&"authorization: bearer secret".to_string()));
+        assert!(headers.contains(&"x-gu-appname: test-app".to_string()));
+    }
+}
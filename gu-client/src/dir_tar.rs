@@ -0,0 +1,149 @@
+//! Packing of local directories for `HubSession::upload_dir_as_blob`.
+
+use bytes::Bytes;
+use futures::prelude::*;
+use gu_actix::pipe;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Name of the file with exclusion patterns, read from the packed directory.
+pub const IGNORE_FILE: &str = ".gu-ignore";
+
+/// tars the directory in a background thread; the archive is streamed as it is built
+pub fn stream_dir_tar(
+    root: PathBuf,
+    skip_symlinks: bool,
+) -> impl Stream<Item = Bytes, Error = io::Error> {
+    let (mut tx, rx) = pipe::sync_to_async(5);
+
+    thread::spawn(move || {
+        let result = IgnoreRules::load(&root).and_then(|ignore| {
+            let mut builder = tar::Builder::new(&mut tx);
+            builder.follow_symlinks(false);
+            append_dir(&mut builder, &root, Path::new(""), &ignore, skip_symlinks)?;
+            builder.finish()
+        });
+        if let Err(e) = result {
+            let _ = tx.send(Err(e));
+        }
+    });
+
+    rx
+}
+
+fn append_dir<W: Write>(
+    builder: &mut tar::Builder<W>,
+    root: &Path,
+    dir: &Path,
+    ignore: &IgnoreRules,
+    skip_symlinks: bool,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(dir))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = dir.join(entry.file_name());
+        if ignore.is_ignored(&name) {
+            continue;
+        }
+        // does not follow symlinks
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            if !skip_symlinks {
+                builder.append_path_with_name(entry.path(), &name)?;
+            }
+        } else if file_type.is_dir() {
+            builder.append_dir(&name, entry.path())?;
+            append_dir(builder, root, &name, ignore, skip_symlinks)?;
+        } else {
+            builder.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Patterns of `.gu-ignore`, one per line; empty lines and lines starting with `#` are skipped.
+///
+/// Patterns may use `*` and `?` wildcards. A pattern containing `/` is matched against
+/// the path relative to the packed directory, other ones against the entry name.
+/// Contents of ignored directories are left out as well.
+#[derive(Default)]
+struct IgnoreRules {
+    patterns: Vec<String>,
+}
+
+impl IgnoreRules {
+    fn load(root: &Path) -> io::Result<Self> {
+        match fs::File::open(root.join(IGNORE_FILE)) {
+            Ok(file) => Self::parse(BufReader::new(file)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(IgnoreRules::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn parse<R: BufRead>(input: R) -> io::Result<Self> {
+        let mut patterns = Vec::new();
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            patterns.push(line.trim_matches('/').to_string());
+        }
+        Ok(IgnoreRules { patterns })
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        let relative = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+
+        self.patterns.iter().any(|pattern| {
+            let text = if pattern.contains('/') {
+                relative.as_str()
+            } else {
+                &*name
+            };
+            wildcard_match(pattern.as_bytes(), text.as_bytes())
+        })
+    }
+}
+
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, _) => text.is_empty(),
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, text) || (!text.is_empty() && wildcard_match(pattern, &text[1..]))
+        }
+        (Some(_), None) => false,
+        (Some((b'?', rest)), Some((_, text_rest))) => wildcard_match(rest, text_rest),
+        (Some((p, rest)), Some((t, text_rest))) => p == t && wildcard_match(rest, text_rest),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::parse(&b"# comment\n\n*.log\ntarget/\nsrc/gen?.rs\n"[..]).unwrap();
+
+        assert!(rules.is_ignored(Path::new("run.log")));
+        assert!(rules.is_ignored(Path::new("out/run.log")));
+        assert!(rules.is_ignored(Path::new("target")));
+        assert!(rules.is_ignored(Path::new("src/gen1.rs")));
+        assert!(!rules.is_ignored(Path::new("lib/gen1.rs")));
+        assert!(!rules.is_ignored(Path::new("run.log.txt")));
+        assert!(!rules.is_ignored(Path::new("# comment")));
+    }
+}
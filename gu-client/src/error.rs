@@ -1,9 +1,13 @@
-use std::{error, fmt};
+use actix_web::client::{ClientConnectorError, SendRequestError};
+use std::{error, fmt, io};
 
 /// Errors returned by Rust API for Golem Unlimited
 #[derive(Debug)]
 pub enum Error {
+    BlobNotFound(String),
     CannotAddPeersToSession(actix_web::http::StatusCode),
+    CannotCancelUpdate(actix_web::http::StatusCode),
+    CannotCopyBlob(actix_web::http::StatusCode),
     CannotCreateBlob(actix_web::http::StatusCode),
     CannotConvertToUTF8(std::str::Utf8Error),
     CannotCreateRequest(actix_web::Error),
@@ -12,33 +16,180 @@ pub enum Error {
     CannotDeleteBlob(actix_web::http::StatusCode),
     CannotDeleteHubSession(actix_web::http::StatusCode),
     CannotDeletePeerSession(actix_web::http::StatusCode),
+    CannotGetBlobSize(actix_web::http::StatusCode),
     CannotGetHubSession(actix_web::http::StatusCode),
     CannotGetHubSessionConfig(actix_web::http::StatusCode),
+    CannotGetLogs(actix_web::http::StatusCode),
     CannotGetPeerInfo(actix_web::http::StatusCode),
+    CannotGetServerVersion(actix_web::http::StatusCode),
     CannotGetResponseBody(actix_web::error::PayloadError),
     CannotListHubSessions(actix_web::http::StatusCode),
     CannotListHubPeers(actix_web::http::StatusCode),
+    CannotListPeerSessions(actix_web::http::StatusCode),
     CannotListSessionBlobs(actix_web::http::StatusCode),
     CannotListSessionPeers(actix_web::http::StatusCode),
     CannotReceiveBlob(actix_web::http::StatusCode),
     CannotReceiveBlobBody(actix_web::error::PayloadError),
+    #[deprecated(
+        note = "never returned; transport failures are reported as `Error::Transport`, \
+                so `match` arms on this variant no longer match"
+    )]
     CannotSendRequest(actix_web::client::SendRequestError),
     CannotSetHubSessionConfig(actix_web::http::StatusCode),
     CannotUploadBlobFromStream(actix_web::http::StatusCode),
     CannotUpdateDeployment(actix_web::http::StatusCode),
     CannotUpdateHubSession(actix_web::http::StatusCode),
+    ConfigVersionConflict,
+    /// destructive call made without setting its `confirm` parameter
+    DeleteNotConfirmed,
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
     InvalidAddress(url::ParseError),
+    InvalidHash(gu_model::hash::Error),
     InvalidJSONResponse(actix_web::error::JsonPayloadError),
-    InvalidPeer(String),
+    InvalidPeer {
+        input: String,
+        reason: String,
+    },
+    InvalidServerVersion(semver::SemVerError),
+    InvalidSessionSpec(String),
+    /// tag which cannot be put in a comma separated filter
+    InvalidTag(String),
+    /// no peer of the hub was accepted by the selector
+    NoMatchingPeers,
+    IoError(std::io::Error),
+    IncompatibleServer {
+        server: semver::Version,
+        required: semver::VersionReq,
+    },
+    PeerTimeout(gu_net::types::NodeId),
+    /// requested range lies past the end of the blob
+    RangeNotSatisfiable,
+    /// failure explained by the hub in the `{"error": "..."}` response body
+    Server {
+        status: actix_web::http::StatusCode,
+        message: String,
+    },
     SessionNotFound(String),
+    TlsError(String),
+    /// request did not reach the hub, or its response did not come back
+    Transport {
+        kind: TransportErrorKind,
+        source: actix_web::client::SendRequestError,
+    },
+    Unauthorized,
     ResourceNotFound,
 }
 
 impl fmt::Display for Error {
     // TODO @filipgolem please implement real Display for Error
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::InvalidPeer { input, reason } => {
+                write!(f, "invalid peer id {:?}: {}", input, reason)
+            }
+            Error::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "checksum mismatch: expected {}, got {}",
+                    expected, actual
+                )
+            }
+            Error::Server { status, message } => write!(f, "hub error {}: {}", status, message),
+            Error::Transport { kind, source } => write!(f, "{:?}: {}", kind, source),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
 impl error::Error for Error {}
+
+impl Error {
+    /// status of the unexpected hub response the error was built from, whether
+    /// the hub explained the failure or not
+    pub fn status(&self) -> Option<actix_web::http::StatusCode> {
+        match self {
+            Error::CannotAddPeersToSession(status)
+            | Error::CannotCancelUpdate(status)
+            | Error::CannotCopyBlob(status)
+            | Error::CannotCreateBlob(status)
+            | Error::CannotCreateHubSession(status)
+            | Error::CannotCreatePeerSession(status)
+            | Error::CannotDeleteBlob(status)
+            | Error::CannotDeleteHubSession(status)
+            | Error::CannotDeletePeerSession(status)
+            | Error::CannotGetBlobSize(status)
+            | Error::CannotGetHubSession(status)
+            | Error::CannotGetHubSessionConfig(status)
+            | Error::CannotGetLogs(status)
+            | Error::CannotGetPeerInfo(status)
+            | Error::CannotGetServerVersion(status)
+            | Error::CannotListHubSessions(status)
+            | Error::CannotListHubPeers(status)
+            | Error::CannotListPeerSessions(status)
+            | Error::CannotListSessionBlobs(status)
+            | Error::CannotListSessionPeers(status)
+            | Error::CannotReceiveBlob(status)
+            | Error::CannotSetHubSessionConfig(status)
+            | Error::CannotUploadBlobFromStream(status)
+            | Error::CannotUpdateDeployment(status)
+            | Error::CannotUpdateHubSession(status)
+            | Error::Server { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn transport(source: SendRequestError) -> Error {
+        Error::Transport {
+            kind: TransportErrorKind::of(&source),
+            source,
+        }
+    }
+}
+
+/// Cause of a failed request, telling hub being down apart from slow network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportErrorKind {
+    ConnectionRefused,
+    Timeout,
+    /// hub address could not be resolved
+    DnsFailure,
+    Other,
+}
+
+impl TransportErrorKind {
+    fn of(e: &SendRequestError) -> TransportErrorKind {
+        use actix::actors::resolver::ResolverError;
+
+        match e {
+            SendRequestError::Timeout => TransportErrorKind::Timeout,
+            SendRequestError::Io(e) => TransportErrorKind::of_io(e),
+            SendRequestError::Connector(e) => match e {
+                ClientConnectorError::Timeout
+                | ClientConnectorError::Resolver(ResolverError::Timeout) => {
+                    TransportErrorKind::Timeout
+                }
+                ClientConnectorError::NoHost
+                | ClientConnectorError::Resolver(ResolverError::Resolver(_)) => {
+                    TransportErrorKind::DnsFailure
+                }
+                ClientConnectorError::IoError(e)
+                | ClientConnectorError::Resolver(ResolverError::IoError(e)) => {
+                    TransportErrorKind::of_io(e)
+                }
+                _ => TransportErrorKind::Other,
+            },
+            _ => TransportErrorKind::Other,
+        }
+    }
+
+    fn of_io(e: &io::Error) -> TransportErrorKind {
+        match e.kind() {
+            io::ErrorKind::ConnectionRefused => TransportErrorKind::ConnectionRefused,
+            io::ErrorKind::TimedOut => TransportErrorKind::Timeout,
+            _ => TransportErrorKind::Other,
+        }
+    }
+}
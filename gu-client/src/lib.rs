@@ -6,12 +6,19 @@ extern crate futures;
 extern crate gu_actix;
 extern crate gu_model;
 extern crate gu_net;
+extern crate log;
+extern crate semver;
 extern crate serde;
 extern crate serde_json;
+extern crate tar;
+extern crate tokio_io;
+extern crate tokio_timer;
 extern crate url;
 
 /// Asynchronous Rust API for Golem Unlimited
 pub mod r#async;
+mod dir_tar;
 /// Errors returned by Rust API for Golem Unlimited
 pub mod error;
+/// Blocking wrappers for Rust API for Golem Unlimited
 pub mod sync;
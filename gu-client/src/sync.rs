@@ -1,8 +1,13 @@
+use crate::error::Error;
+use crate::r#async::{Blob, HubConnection, HubSession};
 use actix::prelude::*;
 use futures::prelude::*;
 use futures::sync::oneshot;
+use gu_model::peers::PeerInfo;
+use gu_model::session::{HubExistingSession, HubSessionSpec};
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 pub struct SystemHandle(Addr<Runner>);
 
 struct Runner;
@@ -76,3 +81,79 @@ where
         )
     }
 }
+
+/// Blocking connection to a single hub.
+///
+/// Runs its own single-threaded actix system, so it can be used without
+/// setting up one in the calling code.
+pub struct SyncHubConnection {
+    system: SystemHandle,
+    connection: HubConnection,
+}
+
+impl SyncHubConnection {
+    /// creates a hub connection from a given address:port, e.g. 127.0.0.1:61621
+    pub fn from_addr<T: Into<String>>(addr: T) -> Result<SyncHubConnection, Error> {
+        let connection = HubConnection::from_addr(addr)?;
+
+        Ok(SyncHubConnection {
+            system: start(),
+            connection,
+        })
+    }
+
+    /// sets timeout applied to every request sent to the hub
+    ///
+    /// Calls which do not get a response in time fail instead of blocking.
+    pub fn with_timeout(self, timeout: Duration) -> SyncHubConnection {
+        SyncHubConnection {
+            connection: self.connection.with_timeout(timeout),
+            ..self
+        }
+    }
+
+    /// returns underlying asynchronous connection
+    pub fn connection(&self) -> &HubConnection {
+        &self.connection
+    }
+
+    /// returns all peers connected to the hub
+    pub fn list_peers(&self) -> Result<Vec<PeerInfo>, Error> {
+        let connection = self.connection.clone();
+        self.system
+            .wait(move || connection.list_peers().map(|peers| peers.collect()))
+    }
+
+    /// returns information about all hub sessions
+    pub fn list_sessions(&self) -> Result<Vec<HubExistingSession>, Error> {
+        let connection = self.connection.clone();
+        self.system.wait(move || {
+            connection
+                .list_sessions()
+                .map(|sessions| sessions.collect())
+        })
+    }
+
+    /// creates a new hub session
+    ///
+    /// The session is not removed automatically, call `HubSession::delete` when done.
+    pub fn new_session(&self, session_info: HubSessionSpec) -> Result<HubSession, Error> {
+        let connection = self.connection.clone();
+        self.system.wait(move || {
+            connection
+                .new_session(session_info)
+                .map(|handle| handle.into_inner().unwrap())
+        })
+    }
+
+    /// downloads whole blob into memory
+    pub fn download(&self, blob: &Blob) -> Result<Vec<u8>, Error> {
+        let blob = blob.clone();
+        self.system.wait(move || {
+            blob.download().fold(Vec::new(), |mut content, chunk| {
+                content.extend_from_slice(chunk.as_ref());
+                Ok::<_, Error>(content)
+            })
+        })
+    }
+}
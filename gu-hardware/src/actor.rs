@@ -51,6 +51,18 @@ pub struct Hardware {
     num_cores: usize,
 }
 
+impl Hardware {
+    pub fn num_cores(&self) -> usize {
+        self.num_cores
+    }
+
+    /// free memory in bytes; `None` when it could not be read
+    pub fn free_memory_bytes(&self) -> Option<u64> {
+        // sysinfo reports memory in KiB
+        self.ram.as_ref().map(|ram| ram.free() * 1024)
+    }
+}
+
 impl Message for HardwareQuery {
     type Result = Result<Hardware, String>;
 }
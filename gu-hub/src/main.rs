@@ -30,9 +30,11 @@ extern crate prettytable;
 
 extern crate bytes;
 extern crate clap;
+extern crate libc;
 extern crate mdns;
 extern crate semver;
 extern crate sha1;
+extern crate tokio_timer;
 extern crate zip;
 
 use gu_base::*;
@@ -4,10 +4,10 @@ use actix::prelude::*;
 use actix_web::http::Method;
 use actix_web::http::StatusCode;
 use actix_web::{
-    self, http, AsyncResponder, FromRequest, HttpRequest, HttpResponse, Json, Path, Responder,
-    Scope,
+    self, http, AsyncResponder, FromRequest, HttpRequest, HttpResponse, Json, Path, Query,
+    Responder, Scope,
 };
-use futures::prelude::*;
+use futures::{future, prelude::*};
 use gu_actix::prelude::*;
 use gu_base::{cli, App, AppSettings, ArgMatches, Decorator, Module, SubCommand};
 use gu_model::peers as peers_api;
@@ -17,6 +17,11 @@ use gu_net::{
 };
 use serde_json::Value as JsonValue;
 use server::HubClient;
+use std::time::Duration;
+use tokio_timer::Timeout;
+
+/// time peer details wait for each answer of the provider
+const PEER_DETAILS_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct PeerModule {
     inner: State,
@@ -79,7 +84,10 @@ impl Module for PeerModule {
 pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
     scope
         .route("", http::Method::GET, list_peers)
+        .route("/info", http::Method::POST, peers_info)
+        .route("/deployments", http::Method::GET, all_deployments)
         .resource("/{nodeId}", |r| r.get().with(fetch_peer))
+        .resource("/{nodeId}/env-types", |r| r.get().with(fetch_env_types))
         .resource("/{nodeId}/deployments", |r| {
             r.get().with(fetch_deployments);
             r.post().with(new_deployment)
@@ -87,13 +95,19 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
         .resource("/{nodeId}/deployments/{deploymentId}", |r| {
             use gu_model::envman::{Command, DestroySession, SessionUpdate};
             use gu_net::rpc::{peer, reply::SendError, ReplyRouter};
+            r.get().with(fetch_deployment);
             r.method(Method::PATCH).with_async(
-                |(path, commands): (Path<DeploymentPath>, Json<Vec<Command>>)| {
+                |(path, query, commands): (
+                    Path<DeploymentPath>,
+                    Query<UpdateQuery>,
+                    Json<Vec<Command>>,
+                )| {
                     peer(path.node_id)
                         .into_endpoint()
                         .send(SessionUpdate {
                             session_id: path.into_inner().deployment_id,
                             commands: commands.into_inner(),
+                            continue_on_error: query.continue_on_error,
                         })
                         .map_err(|e| match e {
                             SendError::NoDestination => {
@@ -106,7 +120,9 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
                         })
                         .and_then(|update_result| match update_result {
                             Ok(update_result) => Ok(HttpResponse::Ok().json(update_result)),
-                            Err(_) => Err(actix_web::error::ErrorInternalServerError("err")),
+                            Err(update_result) => {
+                                Ok(HttpResponse::InternalServerError().json(update_result))
+                            }
                         })
                 },
             );
@@ -132,6 +148,9 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
                     })
             })
         })
+        .resource("/{nodeId}/deployments/{deploymentId}/metadata", |r| {
+            r.method(Method::PATCH).with(set_deployment_metadata)
+        })
         .route("/send-to", http::Method::POST, peer_send)
         .route(
             "/send-to/{nodeId}/{destinationId}",
@@ -140,15 +159,96 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
         )
 }
 
-fn list_peers<S>(_r: HttpRequest<S>) -> impl Responder {
+fn list_peers<S>(r: HttpRequest<S>) -> impl Responder {
+    use gu_model::paging::{Page, TOTAL_COUNT_HEADER};
+    use gu_model::peers::TagFilter;
     use gu_net::rpc::peer::*;
 
+    let page = match Query::<Page>::extract(&r) {
+        Ok(page) => page.into_inner(),
+        Err(e) => return future::err::<HttpResponse, _>(e).responder(),
+    };
+    let filter = match Query::<TagFilter>::extract(&r) {
+        Ok(filter) => filter.into_inner(),
+        Err(e) => return future::err::<HttpResponse, _>(e).responder(),
+    };
+
     PeerManager::from_registry()
         .send(ListPeers)
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("err: {}", e)))
-        .and_then(|res| {
+        .and_then(move |mut res| {
             //debug!("res={:?}", res);
-            Ok(HttpResponse::Ok().json(res))
+            res.retain(|peer| filter.matches(&peer.tags));
+            res.sort_by_key(|peer| peer.node_id.to_string());
+            let (res, total) = page.apply(res);
+            Ok(HttpResponse::Ok()
+                .header(TOTAL_COUNT_HEADER, total.to_string())
+                .json(res))
+        })
+        .responder()
+}
+
+fn peers_info(ids: Json<Vec<NodeId>>) -> impl Responder {
+    use gu_net::rpc::peer::*;
+    use std::collections::HashSet;
+
+    let ids: HashSet<NodeId> = ids.into_inner().into_iter().collect();
+
+    PeerManager::from_registry()
+        .send(ListPeers)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("err: {}", e)))
+        .and_then(move |res| {
+            Ok(HttpResponse::Ok().json(
+                res.into_iter()
+                    .filter(|peer| ids.contains(&peer.node_id))
+                    .collect::<Vec<_>>(),
+            ))
+        })
+        .responder()
+}
+
+/// Deployments of all connected peers; peers which fail to answer are left out.
+fn all_deployments<S>(_r: HttpRequest<S>) -> impl Responder {
+    use gu_model::deployment::PeerDeploymentInfo;
+    use gu_model::envman::GetSessions;
+    use gu_net::rpc::peer::*;
+    use gu_net::rpc::{peer, ReplyRouter};
+
+    PeerManager::from_registry()
+        .send(ListPeers)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("err: {}", e)))
+        .and_then(|peers| {
+            future::join_all(
+                peers
+                    .into_iter()
+                    .map(|info| {
+                        let node_id = info.node_id;
+                        peer(node_id)
+                            .into_endpoint()
+                            .send(GetSessions::default())
+                            .then(move |r| match r {
+                                Ok(Ok(sessions)) => Ok(sessions
+                                    .into_iter()
+                                    .map(|s| PeerDeploymentInfo {
+                                        node_id,
+                                        deployment: s.into(),
+                                    })
+                                    .collect()),
+                                Ok(Err(e)) => {
+                                    warn!("cannot list deployments of {:?}: {:?}", node_id, e);
+                                    Ok(Vec::new())
+                                }
+                                Err(e) => {
+                                    warn!("cannot list deployments of {:?}: {}", node_id, e);
+                                    Ok(Vec::new())
+                                }
+                            })
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .and_then(|deployments: Vec<Vec<PeerDeploymentInfo>>| {
+            Ok(HttpResponse::Ok().json(deployments.into_iter().flatten().collect::<Vec<_>>()))
         })
         .responder()
 }
@@ -166,21 +266,107 @@ struct DeploymentPath {
     deployment_id: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeploymentMetadata {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    tags: Option<gu_model::envman::TagSet>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateQuery {
+    #[serde(default)]
+    continue_on_error: bool,
+}
+
 fn fetch_peer(info: Path<PeerPath>) -> impl Responder {
+    use gu_model::envman::{GetCapabilities, GetCapacity, GetResources};
     use gu_net::rpc::peer::*;
+    use gu_net::rpc::{peer, ReplyRouter};
+    use std::collections::BTreeMap;
 
     PeerManager::from_registry()
         .send(GetPeer(info.node_id))
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("err: {}", e)))
         .and_then(|res| match res {
-            None => Ok(HttpResponse::build(StatusCode::NOT_FOUND).body("Peer not found")),
-            Some(info) => Ok(HttpResponse::Ok().json(peers_api::PeerDetails {
-                node_id: info.node_id,
-                node_name: Some(info.node_name),
-                peer_addr: info.peer_addr.unwrap_or_else(|| "Error".into()),
-                tags: info.tags.into_iter().collect(),
-                sessions: Vec::new(),
-            })),
+            None => future::Either::A(future::ok(
+                HttpResponse::build(StatusCode::NOT_FOUND).body("Peer not found"),
+            )),
+            Some(info) => future::Either::B(
+                Timeout::new(
+                    peer(info.node_id)
+                        .into_endpoint()
+                        .send(GetCapabilities::default()),
+                    PEER_DETAILS_TIMEOUT,
+                )
+                .then(|r| {
+                    // providers without the message, or not answering in time,
+                    // report no capabilities
+                    Ok(match r {
+                        Ok(Ok(capabilities)) => capabilities,
+                        _ => Vec::new(),
+                    })
+                })
+                .join3(
+                    Timeout::new(
+                        peer(info.node_id)
+                            .into_endpoint()
+                            .send(GetCapacity::default()),
+                        PEER_DETAILS_TIMEOUT,
+                    )
+                    .then(|r| {
+                        // and no capacity
+                        Ok(match r {
+                            Ok(Ok(capacity)) => capacity,
+                            _ => BTreeMap::new(),
+                        })
+                    }),
+                    Timeout::new(
+                        peer(info.node_id)
+                            .into_endpoint()
+                            .send(GetResources::default()),
+                        PEER_DETAILS_TIMEOUT,
+                    )
+                    .then(|r| Ok(r.ok().and_then(Result::ok))),
+                )
+                .and_then(move |(capabilities, capacity, resources)| {
+                    Ok(HttpResponse::Ok().json(peers_api::PeerDetails {
+                        node_id: info.node_id,
+                        node_name: Some(info.node_name),
+                        peer_addr: info.peer_addr.unwrap_or_else(|| "Error".into()),
+                        tags: info.tags.into_iter().collect(),
+                        sessions: Vec::new(),
+                        capabilities,
+                        capacity,
+                        resources,
+                    }))
+                }),
+            ),
+        })
+        .responder()
+}
+
+/// Environment types the peer can create deployments in.
+fn fetch_env_types(info: Path<PeerPath>) -> impl Responder {
+    use gu_model::envman::GetCapabilities;
+    use gu_net::rpc::{peer, reply::SendError, ReplyRouter};
+
+    peer(info.node_id)
+        .into_endpoint()
+        .send(GetCapabilities::default())
+        .map_err(|e| match e {
+            SendError::NoDestination => actix_web::error::ErrorNotFound("peer not found"),
+            SendError::NotConnected(node_id) => {
+                actix_web::error::ErrorNotFound(format!("Peer not found {:?}", node_id))
+            }
+            _ => actix_web::error::ErrorInternalServerError(format!("{}", e)),
+        })
+        .and_then(|result| match result {
+            Ok(env_types) => Ok(HttpResponse::Ok().json(env_types)),
+            Err(_) => Err(actix_web::error::ErrorInternalServerError("err")),
         })
         .responder()
 }
@@ -212,6 +398,68 @@ fn fetch_deployments(info: Path<PeerPath>) -> impl Responder {
         .responder()
 }
 
+fn fetch_deployment(path: Path<DeploymentPath>) -> impl Responder {
+    use gu_model::deployment::DeploymentInfo;
+    use gu_model::envman::GetSessions;
+    use gu_net::rpc::{peer, reply::SendError, ReplyRouter};
+
+    let deployment_id = path.deployment_id.clone();
+    peer(path.node_id)
+        .into_endpoint()
+        .send(GetSessions::default())
+        .map_err(|e| match e {
+            SendError::NoDestination => actix_web::error::ErrorNotFound("peer not found"),
+            SendError::NotConnected(node_id) => {
+                actix_web::error::ErrorNotFound(format!("Peer not found {:?}", node_id))
+            }
+            _ => actix_web::error::ErrorInternalServerError(format!("{}", e)),
+        })
+        .and_then(move |session_result| match session_result {
+            Ok(sessions) => match sessions.into_iter().find(|s| s.id == deployment_id) {
+                Some(session) => Ok(HttpResponse::Ok().json(DeploymentInfo::from(session))),
+                None => Err(actix_web::error::ErrorNotFound("deployment not found")),
+            },
+            Err(_) => Err(actix_web::error::ErrorInternalServerError("err")),
+        })
+        .responder()
+}
+
+/// Changes name and tags of a deployment; fields missing in the body are kept.
+fn set_deployment_metadata(
+    path: Path<DeploymentPath>,
+    body: Json<DeploymentMetadata>,
+) -> impl Responder {
+    use gu_model::deployment::DeploymentInfo;
+    use gu_model::envman::{Error, SetSessionMetadata};
+    use gu_net::rpc::{peer, reply::SendError, ReplyRouter};
+
+    let path = path.into_inner();
+    let body = body.into_inner();
+    peer(path.node_id)
+        .into_endpoint()
+        .send(SetSessionMetadata {
+            session_id: path.deployment_id,
+            name: body.name,
+            tags: body.tags,
+        })
+        .map_err(|e| match e {
+            SendError::NoDestination => actix_web::error::ErrorNotFound("peer not found"),
+            SendError::NotConnected(node_id) => {
+                actix_web::error::ErrorNotFound(format!("Peer not found {:?}", node_id))
+            }
+            _ => actix_web::error::ErrorInternalServerError(format!("{}", e)),
+        })
+        .and_then(|result| match result {
+            Ok(session) => Ok(HttpResponse::Ok().json(DeploymentInfo::from(session))),
+            Err(Error::NoSuchSession(id)) => Err(actix_web::error::ErrorNotFound(format!(
+                "deployment not found {}",
+                id
+            ))),
+            Err(e) => Err(actix_web::error::ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
 fn new_deployment(
     info: Path<PeerPath>,
     body: Json<gu_model::envman::CreateSession>,
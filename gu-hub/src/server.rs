@@ -134,6 +134,10 @@ fn chat_route(
     rpc::ws::route(req, req.state().clone())
 }
 
+fn version_route(_req: &actix_web::HttpRequest<NodeId>) -> actix_web::HttpResponse {
+    actix_web::HttpResponse::Ok().json(env!("CARGO_PKG_VERSION"))
+}
+
 pub(crate) struct ServerConfigurer<D: Decorator> {
     decorator: D,
     path: Option<String>,
@@ -170,7 +174,8 @@ impl<D: Decorator + 'static + Sync + Send> ServerConfigurer<D> {
                             .expect("cannot provide static files"),
                     )
                     .scope("/m", mock::scope)
-                    .resource("/ws/", |r| r.route().f(chat_route)),
+                    .resource("/ws/", |r| r.route().f(chat_route))
+                    .resource("/version", |r| r.get().f(version_route)),
             )
         });
         let _ = server.bind(c.p2p_addr()).unwrap().start();
@@ -10,17 +10,22 @@ use futures::{
     sync::oneshot::{self, Sender},
     Future, Stream,
 };
-use gu_base::files::{read_async, write_async};
+use gu_base::files::read_async;
+use gu_model::session::BlobInfo;
 use sha1::Sha1;
 use std::fmt::Debug;
 use std::{
     collections::BTreeMap,
     fs::{self, File},
-    io,
+    io::{self, Write},
     ops::Deref,
     path::{Path, PathBuf},
+    sync::{Arc, RwLock},
 };
 
+/// Content type reported for blobs uploaded without one.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
 struct FileLockActor {
     to_notify: Vec<Sender<()>>,
     readers: usize,
@@ -242,25 +247,153 @@ fn write_dag_future(
         .map_err(|_| SessionErr::FileError("Lock on writer???!!!".to_string()))
 }
 
+fn truncate_file(path: &Path, offset: u64) -> Result<(), SessionErr> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| SessionErr::FileError(e.to_string()))?;
+    let len = file
+        .metadata()
+        .map_err(|e| SessionErr::FileError(e.to_string()))?
+        .len();
+
+    if offset > len {
+        return Err(SessionErr::RangeNotSatisfiable);
+    }
+    file.set_len(offset)
+        .map_err(|e| SessionErr::FileError(e.to_string()))
+}
+
+/// Content type of the blob is kept next to it, in a hidden `.{blob_id}.content-type` file.
+fn content_type_path(path: &Path) -> PathBuf {
+    hidden_path(path, "content-type")
+}
+
+/// Appends payload to the blob file, which is expected to be `offset` bytes long.
+fn write_blob<Payload, Error>(
+    payload: Payload,
+    path: PathBuf,
+    offset: u64,
+) -> impl Future<Item = (), Error = SessionErr>
+where
+    Payload: Stream<Item = bytes::Bytes, Error = Error>,
+    Error: Debug,
+{
+    future::result(fs::OpenOptions::new().create(true).append(true).open(&path))
+        .map_err(|e| SessionErr::FileError(e.to_string()))
+        .and_then(move |file| {
+            payload
+                .map_err(|e| SessionErr::FileError(format!("stream err: {:?}", e)))
+                .fold((file, offset), move |(mut file, pos), chunk| {
+                    let attempted = pos + chunk.len() as u64;
+                    match file.write_all(chunk.as_ref()) {
+                        Ok(()) => Ok((file, attempted)),
+                        Err(ref e) if is_out_of_space(e) => {
+                            error!(
+                                "no space left for blob {}: {} bytes attempted",
+                                path.display(),
+                                attempted
+                            );
+                            Err(SessionErr::InsufficientStorage)
+                        }
+                        Err(e) => Err(SessionErr::FileError(e.to_string())),
+                    }
+                })
+                .and_then(|_| Ok(()))
+        })
+}
+
+#[cfg(unix)]
+fn is_out_of_space(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(::libc::ENOSPC)
+}
+
+#[cfg(not(unix))]
+fn is_out_of_space(_e: &io::Error) -> bool {
+    false
+}
+
+/// Empties the blob file, so it can be written from scratch; data shared with
+/// other blobs is left to them.
+fn reset_file(path: &Path) -> Result<(), SessionErr> {
+    remove_if_exists(path)
+        .and_then(|()| File::create(path))
+        .map(|_| ())
+        .map_err(|e| SessionErr::FileError(e.to_string()))
+}
+
+/// Gives the blob its own copy of data shared with other blobs, so it can be modified.
+fn unshare_file(path: &Path) -> Result<(), SessionErr> {
+    let shared = fs::metadata(path)
+        .map(|metadata| is_hard_linked(&metadata))
+        .map_err(|e| SessionErr::FileError(e.to_string()))?;
+    if !shared {
+        return Ok(());
+    }
+
+    let tmp_path = hidden_path(path, "unshare");
+    fs::copy(path, &tmp_path)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(|e| {
+            let _ = remove_if_exists(&tmp_path);
+            SessionErr::FileError(e.to_string())
+        })
+}
+
+#[cfg(unix)]
+fn is_hard_linked(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() > 1
+}
+
+// link count is not available, so data is always copied
+#[cfg(not(unix))]
+fn is_hard_linked(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+/// File kept next to the blob, as `.{blob_id}.{suffix}`.
+fn hidden_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
 #[derive(Clone)]
 pub struct Blob {
     path: PathBuf,
+    content_type: Arc<RwLock<Option<String>>>,
     lock: Addr<FileLockActor>,
 }
 
 impl Blob {
     pub fn new(path: PathBuf) -> io::Result<Blob> {
         File::create(&path)?;
+        remove_if_exists(&content_type_path(&path))?;
 
         Ok(Blob {
             path: path.clone(),
+            content_type: Arc::new(RwLock::new(None)),
             lock: FileLockActor::new(path, true).start(),
         })
     }
 
     pub fn from_existing(path: PathBuf) -> Blob {
+        let content_type = fs::read_to_string(content_type_path(&path)).ok();
+
         Blob {
             path: path.clone(),
+            content_type: Arc::new(RwLock::new(content_type)),
             lock: FileLockActor::new(path, false).start(),
         }
     }
@@ -269,8 +402,31 @@ impl Blob {
         self.path.as_ref()
     }
 
+    /// Content type given on upload, `application/octet-stream` when none was given.
+    pub fn content_type(&self) -> String {
+        self.content_type
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string())
+    }
+
+    fn set_content_type(&self, content_type: Option<String>) -> Result<(), SessionErr> {
+        let path = content_type_path(&self.path);
+        let stored = match content_type {
+            Some(ref content_type) => fs::write(path, content_type),
+            None => remove_if_exists(&path),
+        };
+        stored.map_err(|e| SessionErr::FileError(e.to_string()))?;
+
+        *self.content_type.write().unwrap() = content_type;
+        Ok(())
+    }
+
+    /// Replaces blob content and its content type.
     pub fn write<Payload, Error>(
         self,
+        content_type: Option<String>,
         fut: Payload,
     ) -> impl Future<Item = SessionOk, Error = SessionErr>
     where
@@ -280,12 +436,156 @@ impl Blob {
         self.lock
             .send(WriteAccessRequest)
             .flatten_fut()
-            .and_then(move |_access: WriteAccess| {
-                write_async(fut, self.path.clone()).map_err(|e| SessionErr::FileError(e))
+            .and_then(move |access: WriteAccess| {
+                let path = self.path.clone();
+                future::result(reset_file(&path))
+                    .and_then(move |_| write_blob(fut, path, 0))
+                    .and_then(move |_| self.set_content_type(content_type))
+                    .and_then(move |_| {
+                        drop(access);
+                        Ok(())
+                    })
             })
             .and_then(|_a| Ok(SessionOk::Ok))
     }
 
+    /// Size, checksum and content type of the stored content; fails with `BlobLockedError`
+    /// while the blob is being written.
+    pub fn info(self, id: String) -> impl Future<Item = BlobInfo, Error = SessionErr> {
+        self.lock
+            .send(ReadAccessRequest)
+            .flatten_fut()
+            .and_then(move |access: ReadAccess| {
+                fs::metadata(&self.path)
+                    .map_err(|e| SessionErr::FileError(e.to_string()))
+                    .map(|metadata| BlobInfo {
+                        id,
+                        content_type: Some(self.content_type()),
+                        size: Some(metadata.len()),
+                        hash: Some(format!("sha1:{}", access.sha1.digest())),
+                    })
+            })
+    }
+
+    /// Writes payload starting at `offset`, replacing everything stored past it.
+    ///
+    /// Content type is kept unless a new one is given.
+    pub fn write_at<Payload, Error>(
+        self,
+        offset: u64,
+        content_type: Option<String>,
+        fut: Payload,
+    ) -> impl Future<Item = SessionOk, Error = SessionErr>
+    where
+        Payload: Stream<Item = bytes::Bytes, Error = Error>,
+        Error: Debug,
+    {
+        self.lock
+            .send(WriteAccessRequest)
+            .flatten_fut()
+            .and_then(move |access: WriteAccess| {
+                let prepared =
+                    unshare_file(&self.path).and_then(|_| truncate_file(&self.path, offset));
+                future::result(prepared)
+                    .and_then(move |_| {
+                        write_blob(fut, self.path.clone(), offset).and_then(move |_| {
+                            match content_type {
+                                Some(_) => self.set_content_type(content_type),
+                                None => Ok(()),
+                            }
+                        })
+                    })
+                    .and_then(move |_| {
+                        drop(access);
+                        Ok(SessionOk::Ok)
+                    })
+            })
+    }
+
+    /// Replaces the blob file with a hard link to content of `other`.
+    ///
+    /// Data is removed from disk when the last blob referring to it is deleted;
+    /// a write to any of them makes its own copy first.
+    pub fn link_to(&self, other: &Blob) -> io::Result<()> {
+        let tmp_path = hidden_path(&self.path, "link");
+        remove_if_exists(&tmp_path)?;
+        fs::hard_link(&other.path, &tmp_path)?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| {
+            let _ = remove_if_exists(&tmp_path);
+            e
+        })
+    }
+
+    /// Replaces the blob file with a hard link to content of `other`, provided both
+    /// still hold content with the given `hash`; returns whether it was linked.
+    ///
+    /// Holds read access to `other` and write access to the blob meanwhile, so
+    /// fails with `BlobLockedError` while `other` is being written.
+    pub fn dedup_with(
+        self,
+        other: Blob,
+        hash: String,
+    ) -> impl Future<Item = bool, Error = SessionErr> {
+        other
+            .lock
+            .send(ReadAccessRequest)
+            .flatten_fut()
+            .join(self.lock.send(WriteAccessRequest).flatten_fut())
+            .and_then(
+                move |(read_access, write_access): (ReadAccess, WriteAccess)| {
+                    recalculate_sha1(self.path.clone()).then(move |sha1| {
+                        let same = |sha1: &Sha1| format!("sha1:{}", sha1.digest()) == hash;
+                        let linked = match sha1 {
+                            Ok(ref sha1) if same(sha1) && same(&read_access.sha1) => self
+                                .link_to(&other)
+                                .map(|_| true)
+                                .map_err(|e| SessionErr::FileError(e.to_string())),
+                            Ok(_) => Ok(false),
+                            Err(e) => Err(e),
+                        };
+                        drop(write_access);
+                        drop(read_access);
+                        linked
+                    })
+                },
+            )
+    }
+
+    /// Replaces blob content and content type with those of `source`.
+    ///
+    /// Data is shared by a hard link when the file system allows it, otherwise
+    /// it is copied.
+    pub fn copy_from(self, source: Blob) -> impl Future<Item = SessionOk, Error = SessionErr> {
+        source
+            .lock
+            .send(ReadAccessRequest)
+            .flatten_fut()
+            .join(self.lock.send(WriteAccessRequest).flatten_fut())
+            .and_then(
+                move |(read_access, write_access): (ReadAccess, WriteAccess)| {
+                    let copied = self
+                        .link_to(&source)
+                        .or_else(|e| {
+                            debug!("cannot link blob {}: {}", self.path.display(), e);
+                            let tmp_path = hidden_path(&self.path, "copy");
+                            fs::copy(&source.path, &tmp_path)
+                                .and_then(|_| fs::rename(&tmp_path, &self.path))
+                                .map_err(|e| {
+                                    let _ = remove_if_exists(&tmp_path);
+                                    e
+                                })
+                        })
+                        .map_err(|e| SessionErr::FileError(e.to_string()))
+                        .and_then(|_| {
+                            self.set_content_type(source.content_type.read().unwrap().clone())
+                        });
+                    drop(write_access);
+                    drop(read_access);
+                    copied.map(|_| SessionOk::Ok)
+                },
+            )
+    }
+
     pub fn read(self) -> impl Future<Item = (NamedFile, HeaderValue), Error = SessionErr> {
         self.lock
             .send(ReadAccessRequest)
@@ -304,9 +604,52 @@ impl Blob {
 
     // TODO: Async?
     pub fn clean_file(&self) -> io::Result<()> {
+        remove_if_exists(&content_type_path(&self.path))?;
         match (&self.path).exists() {
             true => fs::remove_file(&self.path),
             false => Ok(()),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix::{Arbiter, System};
+    use futures::stream;
+
+    fn payload(data: &'static str) -> impl Stream<Item = bytes::Bytes, Error = ()> {
+        stream::once(Ok(bytes::Bytes::from(data)))
+    }
+
+    #[test]
+    fn test_write_replaces_content() {
+        let dir = std::env::temp_dir().join("gu-hub-blob-test-write");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("blob");
+
+        System::run(move || {
+            let blob = Blob::new(path.clone()).unwrap();
+            let (first, second, info) = (blob.clone(), blob.clone(), blob);
+
+            Arbiter::spawn(
+                first
+                    .write(None, payload("first, longer content"))
+                    .and_then(move |_| second.write(None, payload("second")))
+                    .and_then(move |_| info.info("blob".into()))
+                    .then(move |r| {
+                        let info = r.unwrap();
+                        let mut sha = Sha1::new();
+                        sha.update(b"second");
+
+                        assert_eq!(fs::read(&path).unwrap(), b"second");
+                        assert_eq!(info.size, Some(6));
+                        assert_eq!(info.hash, Some(format!("sha1:{}", sha.digest())));
+                        System::current().stop();
+                        Ok(())
+                    }),
+            )
+        });
+    }
+}
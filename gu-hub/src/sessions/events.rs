@@ -0,0 +1,68 @@
+//! WebSocket feed of session changes.
+
+use actix::prelude::*;
+use actix_web::{ws, HttpRequest, HttpResponse};
+use futures::sync::mpsc;
+use gu_model::session::SessionEvent;
+use serde_json;
+use std::{marker::PhantomData, time::Duration};
+
+/// interval of keep-alive pings sent to the subscriber
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Forwards session events to the WebSocket peer as JSON text frames.
+pub struct EventsWorker<S> {
+    events: Option<mpsc::UnboundedReceiver<SessionEvent>>,
+    _state: PhantomData<S>,
+}
+
+impl<S: 'static> EventsWorker<S> {
+    pub fn start(
+        r: &HttpRequest<S>,
+        events: mpsc::UnboundedReceiver<SessionEvent>,
+    ) -> Result<HttpResponse, actix_web::Error> {
+        ws::start(
+            r,
+            EventsWorker {
+                events: Some(events),
+                _state: PhantomData,
+            },
+        )
+    }
+}
+
+impl<S: 'static> Actor for EventsWorker<S> {
+    type Context = ws::WebsocketContext<Self, S>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(events) = self.events.take() {
+            ctx.add_stream(events);
+        }
+        ctx.run_interval(PING_INTERVAL, |_, ctx| ctx.ping(""));
+    }
+}
+
+impl<S: 'static> StreamHandler<SessionEvent, ()> for EventsWorker<S> {
+    fn handle(&mut self, event: SessionEvent, ctx: &mut Self::Context) {
+        match serde_json::to_string(&event) {
+            Ok(text) => ctx.text(text),
+            Err(e) => error!("cannot serialize session event: {}", e),
+        }
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        // session was deleted
+        ctx.close(Some(ws::CloseCode::Normal.into()));
+        ctx.stop();
+    }
+}
+
+impl<S: 'static> StreamHandler<ws::Message, ws::ProtocolError> for EventsWorker<S> {
+    fn handle(&mut self, item: ws::Message, ctx: &mut Self::Context) {
+        match item {
+            ws::Message::Ping(m) => ctx.pong(&m),
+            ws::Message::Close(_) => ctx.stop(),
+            _ => (),
+        }
+    }
+}
@@ -5,7 +5,7 @@
 use actix::prelude::*;
 use futures::prelude::*;
 use gu_actix::prelude::*;
-use gu_net::NodeId;
+use gu_net::{rpc::peer::PeerSessionInfo, NodeId};
 use std::marker::PhantomData;
 
 #[derive(Default)]
@@ -14,6 +14,8 @@ pub struct SessionsManager {
     path: PathBuf,
     next_id: u64,
     sessions: HashMap<u64, Session>,
+    /// ids of sessions created with an idempotency key; kept in memory only
+    idempotency_keys: HashMap<String, u64>,
 }
 
 impl Actor for SessionsManager {
@@ -43,15 +45,27 @@ impl Actor for SessionsManager {
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<u64, SessionErr>")]
-/// Creates new hub session
+#[rtype(result = "Result<(u64, bool), SessionErr>")]
+/// Creates new hub session; returns its id and whether it was created
+/// (`false` when the idempotency key was already used)
 pub struct Create {
     inner: SessionInfo,
+    idempotency_key: Option<String>,
 }
 
 impl Create {
     pub fn from_info(inner: SessionInfo) -> Self {
-        Create { inner }
+        Create {
+            inner,
+            idempotency_key: None,
+        }
+    }
+
+    pub fn with_idempotency_key(self, idempotency_key: Option<String>) -> Self {
+        Create {
+            idempotency_key,
+            ..self
+        }
     }
 }
 
@@ -222,10 +236,36 @@ impl Handler<List> for SessionsManager {
 }
 
 impl Handler<Create> for SessionsManager {
-    type Result = ActorResponse<SessionsManager, u64, SessionErr>;
+    type Result = ActorResponse<SessionsManager, (u64, bool), SessionErr>;
 
     fn handle(&mut self, msg: Create, _ctx: &mut Context<Self>) -> Self::Result {
-        ActorResponse::r#async(self.create_session(msg.inner).into_actor(self))
+        let key = match msg.idempotency_key {
+            Some(key) => key,
+            None => {
+                return ActorResponse::r#async(
+                    self.create_session(msg.inner)
+                        .map(|id| (id, true))
+                        .into_actor(self),
+                )
+            }
+        };
+        match self.idempotency_keys.get(&key).cloned() {
+            Some(id) if self.sessions.contains_key(&id) => ActorResponse::reply(Ok((id, false))),
+            _ => {
+                // reserved for the id the session gets, so a retry handled before
+                // the creation completes does not create another session
+                self.idempotency_keys.insert(key.clone(), self.next_id);
+                ActorResponse::r#async(
+                    self.create_session(msg.inner)
+                        .into_actor(self)
+                        .map(|id, _, _| (id, true))
+                        .map_err(move |e, act: &mut SessionsManager, _| {
+                            act.idempotency_keys.remove(&key);
+                            e
+                        }),
+                )
+            }
+        }
     }
 }
 
@@ -335,15 +375,22 @@ impl Handler<CreateDeployment> for SessionsManager {
     fn handle(&mut self, msg: CreateDeployment, _ctx: &mut Self::Context) -> Self::Result {
         let session_id = msg.session_id;
         let node_id = msg.node_id.clone();
+        // nothing is deployed on the peer during dry run
+        let dry_run = msg.deployment_desc.dry_run;
 
         if let Some(session) = self.sessions.get_mut(&msg.session_id) {
+            if session.is_expired() {
+                return ActorResponse::reply(Err(SessionErr::SessionExpiredError));
+            }
             ActorResponse::r#async(
                 fut::wrap_future(session.create_deployment(msg.node_id, msg.deployment_desc))
                     .and_then(move |deployment_id, act: &mut SessionsManager, _ctx| {
-                        act.sessions
-                            .get_mut(&session_id)
-                            .unwrap()
-                            .add_deployment(node_id, deployment_id.clone());
+                        if !dry_run {
+                            act.sessions
+                                .get_mut(&session_id)
+                                .unwrap()
+                                .add_deployment(node_id, deployment_id.clone());
+                        }
                         fut::ok(deployment_id)
                     }),
             )
@@ -353,6 +400,34 @@ impl Handler<CreateDeployment> for SessionsManager {
     }
 }
 
+#[derive(Message)]
+#[rtype(result = "Result<Vec<PeerSessionInfo>, SessionErr>")]
+pub struct ListDeployments {
+    session_id: u64,
+    node_id: NodeId,
+}
+
+impl ListDeployments {
+    pub fn new(session_id: u64, node_id: NodeId) -> ListDeployments {
+        ListDeployments {
+            session_id,
+            node_id,
+        }
+    }
+}
+
+impl Handler<ListDeployments> for SessionsManager {
+    type Result = ActorResponse<SessionsManager, Vec<PeerSessionInfo>, SessionErr>;
+
+    fn handle(&mut self, msg: ListDeployments, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(session) = self.sessions.get(&msg.session_id) {
+            ActorResponse::r#async(fut::wrap_future(session.list_deployments(msg.node_id)))
+        } else {
+            ActorResponse::reply(Err(SessionErr::SessionNotFoundError))
+        }
+    }
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), SessionErr>")]
 pub struct DeleteDeployment {
@@ -400,13 +475,15 @@ impl Handler<DeleteDeployment> for SessionsManager {
     }
 }
 
+/// Results in outputs of the commands; inner error when some of them failed.
 #[derive(Message)]
-#[rtype(result = "Result<Vec<String>, SessionErr>")]
+#[rtype(result = "Result<Result<Vec<String>, Vec<String>>, SessionErr>")]
 pub struct UpdateDeployment {
     session_id: u64,
     node_id: NodeId,
     deployment_id: String,
     commands: Vec<gu_model::envman::Command>,
+    continue_on_error: bool,
 }
 
 impl UpdateDeployment {
@@ -415,25 +492,104 @@ impl UpdateDeployment {
         node_id: NodeId,
         deployment_id: String,
         commands: Vec<gu_model::envman::Command>,
+        continue_on_error: bool,
     ) -> UpdateDeployment {
         UpdateDeployment {
             session_id,
             node_id,
             deployment_id,
             commands,
+            continue_on_error,
         }
     }
 }
 
 impl Handler<UpdateDeployment> for SessionsManager {
-    type Result = ActorResponse<SessionsManager, Vec<String>, SessionErr>;
+    type Result = ActorResponse<SessionsManager, Result<Vec<String>, Vec<String>>, SessionErr>;
 
     fn handle(&mut self, msg: UpdateDeployment, _ctx: &mut Self::Context) -> Self::Result {
         if let Some(session) = self.sessions.get_mut(&msg.session_id) {
+            if session.is_expired() {
+                return ActorResponse::reply(Err(SessionErr::SessionExpiredError));
+            }
             ActorResponse::r#async(fut::wrap_future(session.update_deployment(
                 msg.node_id,
                 msg.deployment_id,
                 msg.commands,
+                msg.continue_on_error,
+            )))
+        } else {
+            ActorResponse::reply(Err(SessionErr::SessionNotFoundError))
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<usize, SessionErr>")]
+pub struct CancelDeploymentUpdate {
+    session_id: u64,
+    node_id: NodeId,
+    deployment_id: String,
+}
+
+impl CancelDeploymentUpdate {
+    pub fn new(session_id: u64, node_id: NodeId, deployment_id: String) -> CancelDeploymentUpdate {
+        CancelDeploymentUpdate {
+            session_id,
+            node_id,
+            deployment_id,
+        }
+    }
+}
+
+impl Handler<CancelDeploymentUpdate> for SessionsManager {
+    type Result = ActorResponse<SessionsManager, usize, SessionErr>;
+
+    fn handle(&mut self, msg: CancelDeploymentUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(session) = self.sessions.get(&msg.session_id) {
+            ActorResponse::r#async(fut::wrap_future(
+                session.cancel_deployment_update(msg.node_id, msg.deployment_id),
+            ))
+        } else {
+            ActorResponse::reply(Err(SessionErr::SessionNotFoundError))
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<gu_model::envman::LogLine>, SessionErr>")]
+pub struct GetDeploymentLogs {
+    session_id: u64,
+    node_id: NodeId,
+    deployment_id: String,
+    offset: usize,
+}
+
+impl GetDeploymentLogs {
+    pub fn new(
+        session_id: u64,
+        node_id: NodeId,
+        deployment_id: String,
+        offset: usize,
+    ) -> GetDeploymentLogs {
+        GetDeploymentLogs {
+            session_id,
+            node_id,
+            deployment_id,
+            offset,
+        }
+    }
+}
+
+impl Handler<GetDeploymentLogs> for SessionsManager {
+    type Result = ActorResponse<SessionsManager, Vec<gu_model::envman::LogLine>, SessionErr>;
+
+    fn handle(&mut self, msg: GetDeploymentLogs, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(session) = self.sessions.get(&msg.session_id) {
+            ActorResponse::r#async(fut::wrap_future(session.deployment_logs(
+                msg.node_id,
+                msg.deployment_id,
+                msg.offset,
             )))
         } else {
             ActorResponse::reply(Err(SessionErr::SessionNotFoundError))
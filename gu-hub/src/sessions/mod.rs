@@ -3,6 +3,7 @@
 //! Session aggregates resources.
 //!
 mod blob;
+mod events;
 mod manager;
 mod module;
 mod responses;
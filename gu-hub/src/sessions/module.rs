@@ -5,19 +5,29 @@ use actix_web::{
     fs::NamedFile,
     http,
     http::{ContentEncoding, Method, StatusCode},
-    App, AsyncResponder, Error as ActixError, HttpMessage, HttpRequest, HttpResponse, Json,
+    App, AsyncResponder, Error as ActixError, HttpMessage, HttpRequest, HttpResponse, Json, Query,
     Responder, Result as ActixResult, Scope,
 };
-use futures::future::Future;
-use futures::stream::Stream;
+use bytes::Bytes;
+use futures::future::{self, Future};
+use futures::stream::{self, Stream};
 use gu_actix::prelude::*;
+use gu_base::files::{gunzip_stream, gzip_stream, read_async};
 use gu_base::Module;
-use gu_model::session::HubSessionSpec;
+use gu_model::deployment::DeploymentFilter;
+use gu_model::envman::{self, CommandResult, LogLine};
+use gu_model::paging::{Page, TOTAL_COUNT_HEADER};
+use gu_model::session::{BlobInfo, HubSessionSpec, IDEMPOTENCY_KEY_HEADER};
 use gu_net::NodeId;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
-use sessions::{manager, manager::SessionsManager, responses::*, session::SessionInfo};
+use sessions::{
+    blob::Blob, events::EventsWorker, manager, manager::SessionsManager, responses::*,
+    session::SessionInfo,
+};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
 
 #[derive(Default)]
 pub struct SessionsModule {}
@@ -33,28 +43,28 @@ fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
         .resource("", |r| {
             r.name("hub-sessions");
 
-            r.get().with_async(|()| {
+            r.get().with_async(|page: Query<Page>| {
                 SessionsManager::from_registry()
                     .send(manager::List)
                     .flatten_fut()
                     .from_err::<actix_web::Error>()
-                    .and_then(|sessions| {
-                        Ok(HttpResponse::Ok().json(
-                            sessions
-                                .into_iter()
-                                .map(|(session_id, session_info)| {
-                                    gu_model::session::SessionDetails {
-                                        id: session_id,
-                                        created: Some(session_info.created),
-                                        name: session_info.name,
-                                        ..gu_model::session::SessionDetails::default()
-                                    }
-                                })
-                                .collect::<Vec<gu_model::session::SessionDetails>>(),
-                        ))
+                    .and_then(move |mut sessions| {
+                        sessions.sort_by_key(|&(session_id, _)| session_id);
+                        let (sessions, total) =
+                            page.apply(sessions.into_iter().map(|(session_id, session_info)| {
+                                gu_model::session::SessionDetails {
+                                    id: session_id,
+                                    created: Some(session_info.created),
+                                    name: session_info.name,
+                                    ..gu_model::session::SessionDetails::default()
+                                }
+                            }));
+                        Ok(HttpResponse::Ok()
+                            .header(TOTAL_COUNT_HEADER, total.to_string())
+                            .json(sessions))
                     })
             });
-            r.post().with_async_config(create_session, |(cfg,)| {
+            r.post().with_async_config(create_session, |(cfg, _)| {
                 cfg.limit(4096);
             });
         })
@@ -68,10 +78,15 @@ fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
                     .and_then(|()| Ok(HttpResponse::NoContent()))
             })
         })
+        .resource("/{sessionId}/events", |r| {
+            r.name("hub-session-events");
+            r.get().with_async(session_events);
+        })
         .resource("/{sessionId}/config", |r| {
             r.name("hub-session-config");
             r.get().with_async(get_config);
             r.put().with_async(set_config);
+            r.method(Method::PATCH).with_async(patch_config);
         })
         .resource("/{sessionId}/blobs", |r| {
             r.name("hub-session-blobs");
@@ -81,6 +96,7 @@ fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
         .resource("/{sessionId}/blobs/{blobId}", |r| {
             r.name("hub-session-blob");
             r.get().with(download_scope);
+            r.head().with(download_scope);
             /* r.get().with_async(download_blob); */
             r.put().with(upload_scope);
             r.delete().with_async(|path: Path<SessionBlobPath>| {
@@ -95,13 +111,22 @@ fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
                     .and_then(|_r| Ok(HttpResponse::build(StatusCode::NO_CONTENT).finish()))
             });
         })
+        .resource("/{sessionId}/blobs/{blobId}/copy", |r| {
+            r.name("hub-session-blob-copy");
+            r.post().with_async(copy_blob);
+        })
         .resource("/{sessionId}/peers", |r| {
             r.name("hub-session-peers");
             r.get().with_async(list_peers);
             r.post().with_async(add_peers);
         })
+        .resource("/{sessionId}/deployments", |r| {
+            r.name("hub-session-deployments");
+            r.get().with_async(find_deployments);
+        })
         .resource("/{sessionId}/peers/{nodeId}/deployments", |r| {
             r.name("hub-session-peers-deployments");
+            r.get().with_async(list_deployments);
             r.post().with_async(create_deployment);
         })
         .resource(
@@ -112,6 +137,27 @@ fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
                 r.method(Method::PATCH).with_async(update_deployment);
             },
         )
+        .resource(
+            "/{sessionId}/peers/{nodeId}/deployments/{deploymentId}/stream",
+            |r| {
+                r.name("hub-session-peers-deployment-stream");
+                r.post().with_async(update_deployment_stream);
+            },
+        )
+        .resource(
+            "/{sessionId}/peers/{nodeId}/deployments/{deploymentId}/cancel",
+            |r| {
+                r.name("hub-session-peers-deployment-cancel");
+                r.post().with_async(cancel_deployment_update);
+            },
+        )
+        .resource(
+            "/{sessionId}/peers/{nodeId}/deployments/{deploymentId}/logs",
+            |r| {
+                r.name("hub-session-peers-deployment-logs");
+                r.get().with(deployment_logs);
+            },
+        )
 }
 
 fn get_param<S>(r: &HttpRequest<S>, name: &'static str) -> ActixResult<u64> {
@@ -150,6 +196,28 @@ struct SessionPeerDeploymentPath {
     deployment_id: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CopyQuery {
+    to_session: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateQuery {
+    #[serde(default)]
+    continue_on_error: bool,
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    #[serde(default)]
+    follow: bool,
+}
+
+/// delay between polls of the peer for new log lines when following the logs
+const LOGS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 fn session_id<S>(r: &HttpRequest<S>) -> ActixResult<u64> {
     get_param(r, "sessionId")
 }
@@ -158,26 +226,52 @@ fn blob_id<S>(r: &HttpRequest<S>) -> ActixResult<u64> {
     get_param(r, "blobId")
 }
 
-fn create_session(
-    spec: Json<HubSessionSpec>,
+fn create_session<S>(
+    (spec, r): (Json<HubSessionSpec>, HttpRequest<S>),
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> + 'static {
+    let idempotency_key = match r.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(key) => Some(key.to_string()),
+            Err(_) => {
+                return future::Either::B(future::err(ErrorBadRequest(
+                    "invalid Idempotency-Key header",
+                )))
+            }
+        },
+        None => None,
+    };
     let spec_inner = spec.into_inner();
+    let created = chrono::Utc::now();
+    let expire_ttl = spec_inner
+        .expires_in
+        .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+        .and_then(|ttl| created.checked_add_signed(ttl));
     let info = SessionInfo {
         name: spec_inner.name,
-        created: chrono::Utc::now(),
-        expire: spec_inner.expires,
+        created,
+        expire: match (spec_inner.expires, expire_ttl) {
+            (Some(expires), Some(expire_ttl)) => Some(expires.min(expire_ttl)),
+            (expires, expire_ttl) => expires.or(expire_ttl),
+        },
         tags: Some(spec_inner.tags),
     };
 
-    SessionsManager::from_registry()
-        .send(manager::Create::from_info(info))
-        .flatten_fut()
-        .from_err()
-        .and_then(|session_id| {
-            Ok(HttpResponse::build(StatusCode::CREATED)
-                .header("Location", format!("/sessions/{}", session_id))
-                .json(session_id))
-        })
+    future::Either::A(
+        SessionsManager::from_registry()
+            .send(manager::Create::from_info(info).with_idempotency_key(idempotency_key))
+            .flatten_fut()
+            .from_err()
+            .and_then(|(session_id, created)| {
+                let status = if created {
+                    StatusCode::CREATED
+                } else {
+                    StatusCode::OK
+                };
+                Ok(HttpResponse::build(status)
+                    .header("Location", format!("/sessions/{}", session_id))
+                    .json(session_id))
+            }),
+    )
 }
 
 fn get_session(
@@ -201,16 +295,66 @@ fn get_config(
         }))
         .flatten_fut()
         .from_err()
-        .and_then(|metadata| Ok(HttpResponse::Ok().json(metadata)))
+        .and_then(|metadata: gu_model::session::Metadata| {
+            Ok(HttpResponse::Ok()
+                .header(http::header::ETAG, format!("\"{}\"", metadata.version))
+                .json(metadata))
+        })
+}
+
+/// parses `If-Match` header holding config version; `None` when the header is missing or `*`
+fn if_match_version<S>(r: &HttpRequest<S>) -> Result<Option<u64>, ActixError> {
+    let value = match r.headers().get(http::header::IF_MATCH) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| ErrorBadRequest("invalid If-Match header"))?,
+        None => return Ok(None),
+    };
+    match value.trim().trim_start_matches("W/").trim_matches('"') {
+        "*" => Ok(None),
+        version => version
+            .parse()
+            .map(Some)
+            .map_err(|_| ErrorBadRequest("invalid If-Match header")),
+    }
 }
 
-fn set_config(
-    (path, body): (Path<SessionPath>, Json<gu_model::session::Metadata>),
+fn set_config<S>(
+    (path, body, r): (
+        Path<SessionPath>,
+        Json<gu_model::session::Metadata>,
+        HttpRequest<S>,
+    ),
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
     let new_metadata = body.into_inner();
+    let expected_version = match if_match_version(&r) {
+        Ok(version) => version,
+        Err(e) => return future::Either::B(future::err(e)),
+    };
+    future::Either::A(
+        SessionsManager::from_registry()
+            .send(manager::Update::new(
+                path.session_id,
+                move |session| match expected_version {
+                    Some(version) => {
+                        future::Either::A(session.set_metadata_if_version(new_metadata, version))
+                    }
+                    None => future::Either::B(session.set_metadata(new_metadata)),
+                },
+            ))
+            .flatten_fut()
+            .from_err()
+            .and_then(|new_version| Ok(HttpResponse::Ok().json(new_version))),
+    )
+}
+
+fn patch_config(
+    (path, body): (Path<SessionPath>, Json<Value>),
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    let patch = body.into_inner();
     SessionsManager::from_registry()
         .send(manager::Update::new(path.session_id, |session| {
-            session.set_metadata(new_metadata)
+            session.patch_metadata(patch)
         }))
         .flatten_fut()
         .from_err()
@@ -235,7 +379,7 @@ fn create_blob_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
 
                         match part {
                             MultipartItem::Field(payload) => futures::future::Either::B(
-                                blob.write(payload)
+                                blob.write(Some(payload.content_type().to_string()), payload)
                                     .map_err(|e| ErrorInternalServerError(format!("err: {}", e)))
                                     .and_then(move |_| {
                                         blobs.push(blob_id);
@@ -271,6 +415,75 @@ fn list_blobs(
         .and_then(|list| Ok(HttpResponse::Ok().json(list)))
 }
 
+/// Copies the blob into other session, as a new blob sharing the stored data when possible.
+fn copy_blob(
+    (path, query): (Path<SessionBlobPath>, Query<CopyQuery>),
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    let manager = SessionsManager::from_registry();
+    let to_session = query.to_session;
+
+    manager
+        .send(manager::GetBlob {
+            session: path.session_id,
+            blob_id: path.blob_id,
+        })
+        .flatten_fut()
+        .from_err::<actix_web::Error>()
+        .and_then(move |res: SessionOk| {
+            let source = match res {
+                SessionOk::Blob(blob) => blob,
+                _ => unreachable!(),
+            };
+            manager
+                .send(manager::CreateBlob {
+                    session: to_session,
+                })
+                .flatten_fut()
+                .map_err(|e| match e {
+                    SessionErr::SessionNotFoundError => {
+                        ErrorBadRequest("destination session not found")
+                    }
+                    e => ActixError::from(e),
+                })
+                .and_then(move |(blob_id, blob)| {
+                    blob.clone()
+                        .copy_from(source)
+                        .and_then(move |_| blob.info(blob_id.to_string()))
+                        .and_then(move |info| {
+                            let hash = info.hash.clone().unwrap_or_default();
+                            // recorded, so later uploads to the destination may refer to the copy
+                            manager
+                                .send(manager::Update::new(to_session, move |session| {
+                                    session.dedup_blob(blob_id, hash, false)
+                                }))
+                                .flatten_fut()
+                                .map(|_| info)
+                        })
+                        .map_err(move |e| {
+                            SessionsManager::from_registry().do_send(manager::DeleteBlob {
+                                session: to_session,
+                                blob_id,
+                            });
+                            ActixError::from(e)
+                        })
+                })
+        })
+        .and_then(|info| Ok(HttpResponse::Created().json(info)))
+}
+
+/// Upgrades to WebSocket streaming `SessionEvent`s of the session as JSON text frames.
+fn session_events<S: 'static>(
+    (path, r): (Path<SessionPath>, HttpRequest<S>),
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    SessionsManager::from_registry()
+        .send(manager::Update::new(path.session_id, |session| {
+            Ok(session.subscribe())
+        }))
+        .flatten_fut()
+        .from_err()
+        .and_then(move |events| EventsWorker::start(&r, events))
+}
+
 fn list_peers(
     path: Path<SessionPath>,
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
@@ -309,6 +522,29 @@ fn delete_deployment(
         .and_then(|result| Ok(HttpResponse::NoContent().json(result)))
 }
 
+fn list_deployments(
+    path: Path<SessionPeerPath>,
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    SessionsManager::from_registry()
+        .send(manager::ListDeployments::new(path.session_id, path.node_id))
+        .flatten_fut()
+        .from_err()
+        .and_then(|deployments| Ok(HttpResponse::Ok().json(deployments)))
+}
+
+fn find_deployments(
+    (path, filter): (Path<SessionPath>, Query<DeploymentFilter>),
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    let filter = filter.into_inner();
+    SessionsManager::from_registry()
+        .send(manager::Update::new(path.session_id, move |session| {
+            session.find_deployments(filter)
+        }))
+        .flatten_fut()
+        .from_err()
+        .and_then(|deployments| Ok(HttpResponse::Ok().json(deployments)))
+}
+
 fn create_deployment(
     (path, body): (Path<SessionPeerPath>, Json<gu_model::envman::CreateSession>),
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
@@ -337,8 +573,9 @@ fn create_deployment(
 }
 
 fn update_deployment(
-    (path, body): (
+    (path, query, body): (
         Path<SessionPeerDeploymentPath>,
+        Query<UpdateQuery>,
         Json<Vec<gu_model::envman::Command>>,
     ),
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
@@ -348,10 +585,173 @@ fn update_deployment(
             path.node_id,
             path.deployment_id.clone(),
             body.into_inner(),
+            query.continue_on_error,
         ))
         .flatten_fut()
         .from_err()
-        .and_then(|results| Ok(HttpResponse::Ok().json(results)))
+        .and_then(|results| Ok(HttpResponse::Ok().json(results.unwrap_or_else(|e| e))))
+}
+
+/// Runs commands one by one, streaming outcome of each as newline delimited
+/// JSON `CommandResult`s. Without `continueOnError` it ends after first failure.
+///
+/// Every command is sent to the peer separately; the session, peer and deployment
+/// are checked before the response starts.
+fn update_deployment_stream(
+    (path, query, body): (
+        Path<SessionPeerDeploymentPath>,
+        Query<UpdateQuery>,
+        Json<Vec<gu_model::envman::Command>>,
+    ),
+) -> impl Future<Item = HttpResponse, Error = ActixError> {
+    let continue_on_error = query.continue_on_error;
+    let session_id = path.session_id;
+    let node_id = path.node_id;
+    let deployment_id = path.deployment_id.clone();
+    let checked_id = path.deployment_id.clone();
+
+    SessionsManager::from_registry()
+        .send(manager::Update::new(session_id, move |session| {
+            session.check_deployment(node_id, &checked_id)
+        }))
+        .flatten_fut()
+        .from_err()
+        .map(move |()| {
+            stream_deployment_update(
+                session_id,
+                node_id,
+                deployment_id,
+                body.into_inner(),
+                continue_on_error,
+            )
+        })
+}
+
+fn stream_deployment_update(
+    session_id: u64,
+    node_id: NodeId,
+    deployment_id: String,
+    commands: Vec<gu_model::envman::Command>,
+    continue_on_error: bool,
+) -> HttpResponse {
+    // state is the commands left to run; none after a failure
+    let results = stream::unfold(Some(commands.into_iter().enumerate()), move |state| {
+        let mut commands = state?;
+        let (index, command) = commands.next()?;
+
+        Some(
+            SessionsManager::from_registry()
+                .send(manager::UpdateDeployment::new(
+                    session_id,
+                    node_id,
+                    deployment_id.clone(),
+                    vec![command],
+                    false,
+                ))
+                .flatten_fut()
+                .from_err()
+                .map(move |results| {
+                    let ok = results.is_ok();
+                    let output = match results {
+                        Ok(mut outputs) | Err(mut outputs) => outputs.pop().unwrap_or_default(),
+                    };
+                    // provider numbers the failed command within the single command batch
+                    let output = match envman::split_failed_command(&output) {
+                        Some((_, error)) => error.to_owned(),
+                        None => output,
+                    };
+                    let next = if ok || continue_on_error {
+                        Some(commands)
+                    } else {
+                        None
+                    };
+                    (CommandResult { index, ok, output }, next)
+                }),
+        )
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(results.and_then(|result| {
+            let mut body = serde_json::to_vec(&result)
+                .map_err(|e| ErrorInternalServerError(format!("err: {}", e)))?;
+            body.push(b'\n');
+            Ok(Bytes::from(body))
+        }))
+}
+
+fn cancel_deployment_update(
+    path: Path<SessionPeerDeploymentPath>,
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    SessionsManager::from_registry()
+        .send(manager::CancelDeploymentUpdate::new(
+            path.session_id,
+            path.node_id,
+            path.deployment_id.clone(),
+        ))
+        .flatten_fut()
+        .from_err()
+        .and_then(|cancelled| Ok(HttpResponse::Ok().json(cancelled)))
+}
+
+/// Streams deployment output as newline delimited JSON `LogLine`s.
+fn deployment_logs(
+    (path, query): (Path<SessionPeerDeploymentPath>, Query<LogsQuery>),
+) -> HttpResponse {
+    let follow = query.follow;
+    let session_id = path.session_id;
+    let node_id = path.node_id;
+    let deployment_id = path.deployment_id.clone();
+
+    // state is offset of the next line and whether to wait before asking for it
+    let lines = stream::unfold(Some((0, false)), move |state| {
+        let (offset, wait) = state?;
+        let poll_at = if wait {
+            Instant::now() + LOGS_POLL_INTERVAL
+        } else {
+            Instant::now()
+        };
+        let deployment_id = deployment_id.clone();
+
+        Some(
+            Delay::new(poll_at)
+                .map_err(|e| ErrorInternalServerError(format!("err: {}", e)))
+                .and_then(move |_| {
+                    SessionsManager::from_registry()
+                        .send(manager::GetDeploymentLogs::new(
+                            session_id,
+                            node_id,
+                            deployment_id,
+                            offset,
+                        ))
+                        .flatten_fut()
+                        .from_err()
+                })
+                .map(move |lines: Vec<LogLine>| {
+                    let next_offset = offset + lines.len();
+                    (
+                        lines,
+                        if follow {
+                            Some((next_offset, true))
+                        } else {
+                            None
+                        },
+                    )
+                }),
+        )
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines.and_then(|lines| {
+            let mut body = Vec::new();
+            for line in lines {
+                serde_json::to_writer(&mut body, &line)
+                    .map_err(|e| ErrorInternalServerError(format!("err: {}", e)))?;
+                body.push(b'\n');
+            }
+            Ok(Bytes::from(body))
+        }))
 }
 
 fn session_future_responder<F, E, R>(fut: F) -> impl Responder
@@ -363,24 +763,147 @@ where
     fut.map_err(|err| Into::<ActixError>::into(err)).responder()
 }
 
+/// Parses start offset from `Content-Range: bytes <start>-<end>/<size>` header.
+fn content_range_start<S>(r: &HttpRequest<S>) -> Result<Option<u64>, SessionErr> {
+    match r.headers().get(http::header::CONTENT_RANGE) {
+        None => Ok(None),
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().trim_left_matches("bytes").split('-').next())
+            .and_then(|start| start.trim().parse().ok())
+            .map(Some)
+            .ok_or(SessionErr::RangeNotSatisfiable),
+    }
+}
+
+/// Tells whether the body is gzip compressed, per `Content-Encoding` header.
+fn gzip_content<S>(r: &HttpRequest<S>) -> Result<bool, SessionErr> {
+    match r.headers().get(http::header::CONTENT_ENCODING) {
+        None => Ok(false),
+        Some(value) => match value.to_str().map(|value| value.trim().to_lowercase()) {
+            Ok(ref encoding) if encoding == "identity" => Ok(false),
+            Ok(ref encoding) if encoding == "gzip" => Ok(true),
+            _ => Err(SessionErr::UnsupportedEncoding(format!("{:?}", value))),
+        },
+    }
+}
+
+/// Tells whether the client takes gzip compressed body, per `Accept-Encoding` header.
+fn gzip_accepted<S>(r: &HttpRequest<S>) -> bool {
+    r.headers()
+        .get(http::header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .filter_map(|encoding| encoding.split(';').next())
+                .any(|encoding| encoding.trim().eq_ignore_ascii_case("gzip"))
+        })
+        .unwrap_or(false)
+}
+
 fn upload_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
     let session = session_id(&r).map_err(|e| return e).unwrap();
     let blob_id = blob_id(&r).map_err(|e| return e).unwrap();
     let manager = SessionsManager::from_registry();
 
+    let target = content_range_start(&r).and_then(|offset| match (offset, gzip_content(&r)?) {
+        // compressed content is stored decompressed, so offsets would not match
+        (Some(_), true) => Err(SessionErr::UnsupportedEncoding(
+            "gzip with Content-Range".to_string(),
+        )),
+        (offset, gzip) => Ok((offset, gzip)),
+    });
+    let content_type = Some(r.content_type())
+        .filter(|content_type| !content_type.is_empty())
+        .map(str::to_owned);
+    let dedup = r
+        .query()
+        .get("dedup")
+        .map(|dedup| dedup != "false")
+        .unwrap_or(true);
+
     let blob_fut = manager
-        .send(manager::GetBlob { session, blob_id })
+        .send(manager::Update::new(session, move |session| {
+            session.blob_for_write(blob_id)
+        }))
         .flatten_fut();
     let res_fut = blob_fut
-        .and_then(move |res: SessionOk| match res {
-            SessionOk::Blob(blob) => blob.write(r.payload()),
-            _ => unreachable!(),
+        .and_then(move |blob: Blob| {
+            let (info_blob, written) = (blob.clone(), blob.clone());
+            match target {
+                Ok((None, false)) => future::Either::A(blob.write(content_type, r.payload())),
+                Ok((None, true)) => future::Either::B(future::Either::A(blob.write(
+                    content_type,
+                    gunzip_stream(r.payload().map_err(|e| e.to_string())),
+                ))),
+                Ok((Some(offset), _)) => future::Either::B(future::Either::B(future::Either::A(
+                    blob.write_at(offset, content_type, r.payload()),
+                ))),
+                Err(e) => future::Either::B(future::Either::B(future::Either::B(future::err(e)))),
+            }
+            .and_then(move |_| info_blob.info(blob_id.to_string()))
+            .map(move |info| (written, info))
         })
-        .and_then(|_| Ok(HttpResponse::build(StatusCode::NO_CONTENT).finish()));
+        .and_then(move |(blob, info)| match (info.hash.clone(), info.size) {
+            (Some(hash), Some(size)) if size > 0 => future::Either::A(
+                manager
+                    .send(manager::Update::new(session, move |session| {
+                        session.dedup_blob(blob_id, hash, dedup)
+                    }))
+                    .flatten_fut()
+                    .and_then(move |existing| match existing {
+                        Some((other_id, other)) => {
+                            future::Either::A(dedup_blob_with(blob, blob_id, other_id, other, info))
+                        }
+                        None => future::Either::B(future::ok(info)),
+                    }),
+            ),
+            _ => future::Either::B(future::ok(info)),
+        })
+        .and_then(|info| Ok(HttpResponse::Ok().json(info)))
+        // other upload to the blob started before the content could be read back
+        .or_else(|e| match e {
+            SessionErr::BlobLockedError => Ok(HttpResponse::Conflict()
+                .header(http::header::RETRY_AFTER, "1")
+                .body("Blob is being written by another request")),
+            e => Err(e),
+        });
 
     session_future_responder(res_fut)
 }
 
+/// Makes the uploaded blob a reference to `other` with the same content; the upload
+/// is kept as it is when that fails.
+fn dedup_blob_with(
+    blob: Blob,
+    blob_id: u64,
+    other_id: u64,
+    other: Blob,
+    info: BlobInfo,
+) -> impl Future<Item = BlobInfo, Error = SessionErr> {
+    let hash = info.hash.clone().unwrap_or_default();
+
+    blob.dedup_with(other, hash).then(move |linked| {
+        let id = match linked {
+            Ok(true) => {
+                debug!("blob {} deduplicated with {}", blob_id, other_id);
+                other_id
+            }
+            Ok(false) => blob_id,
+            Err(e) => {
+                warn!("cannot link blob {} to {}: {}", blob_id, other_id, e);
+                blob_id
+            }
+        };
+        Ok(BlobInfo {
+            id: id.to_string(),
+            ..info
+        })
+    })
+}
+
 /*
 fn download_blob(
     path: Path<SessionBlobPath>,
@@ -400,24 +923,42 @@ fn download_blob(
 */
 
 fn download_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
-    use actix_web::http::header::ETAG;
+    use actix_web::http::header::{HeaderValue, CONTENT_ENCODING, CONTENT_TYPE, ETAG, RANGE};
 
     let session = session_id(&r).map_err(|e| return e).unwrap();
     let blob_id = blob_id(&r).map_err(|e| return e).unwrap();
     let manager = SessionsManager::from_registry();
+    // ranges refer to stored bytes, so those requests are served as they are
+    let gzip = r.method() == Method::GET && !r.headers().contains_key(RANGE) && gzip_accepted(&r);
 
     let blob_fut = manager
         .send(manager::GetBlob { session, blob_id })
         .flatten_fut();
     let res_fut = blob_fut
         .and_then(move |res: SessionOk| match res {
-            SessionOk::Blob(blob) => blob.read(),
+            SessionOk::Blob(blob) => {
+                let content_type = blob.content_type();
+                blob.read().map(move |(n, sha)| (n, sha, content_type))
+            }
             _oth => unreachable!(),
         })
-        .and_then(move |(n, sha)| {
+        .and_then(move |(n, sha, content_type)| {
+            if gzip {
+                return Ok(HttpResponse::Ok()
+                    .header(CONTENT_ENCODING, "gzip")
+                    .header(ETAG, sha)
+                    .content_type(content_type)
+                    .content_encoding(ContentEncoding::Identity)
+                    .streaming(
+                        gzip_stream(read_async(n.path())).map_err(ErrorInternalServerError),
+                    ));
+            }
             n.respond_to(&r)
-                .and_then(|mut r| {
+                .and_then(move |mut r| {
                     r.headers_mut().insert(ETAG, sha);
+                    if let Ok(content_type) = HeaderValue::from_str(&content_type) {
+                        r.headers_mut().insert(CONTENT_TYPE, content_type);
+                    }
                     r.set_content_encoding(actix_web::http::ContentEncoding::Identity);
                     Ok(r)
                 })
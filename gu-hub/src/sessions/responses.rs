@@ -38,16 +38,26 @@ pub enum SessionOk {
 pub enum SessionErr {
     OverwriteError,
     SessionNotFoundError,
+    SessionExpiredError,
     BlobNotFoundError,
     BlobLockedError,
+    RangeNotSatisfiable,
+    /// hub disk is full
+    InsufficientStorage,
     DirectoryCreationError(String),
     FileError(String),
     MailboxError(String),
     NodeNotFound(NodeId),
     DeploymentNotFound(String),
     CannotCreatePeerDeployment,
+    CannotListPeerDeployments,
     CannotDeletePeerDeployment,
     CannotUpdatePeerDeployment,
+    CannotCancelPeerDeploymentUpdate,
+    CannotGetPeerDeploymentLogs,
+    InvalidConfigPatch(String),
+    VersionMismatch,
+    UnsupportedEncoding(String),
 }
 
 impl ::std::fmt::Display for SessionErr {
@@ -62,7 +72,11 @@ impl From<MailboxError> for SessionErr {
     }
 }
 
-impl actix_web::ResponseError for SessionErr {}
+impl actix_web::ResponseError for SessionErr {
+    fn error_response(&self) -> HttpResponse {
+        self.clone().into()
+    }
+}
 
 impl ::std::error::Error for SessionErr {}
 
@@ -89,11 +103,29 @@ impl Into<HttpResponse> for SessionErr {
         match self {
             SessionErr::OverwriteError => HttpResponse::InternalServerError().body("Id conflict"),
             SessionErr::SessionNotFoundError => HttpResponse::NotFound().body("Session not found"),
+            SessionErr::VersionMismatch => {
+                HttpResponse::PreconditionFailed().body("Config version changed")
+            }
+            SessionErr::InvalidConfigPatch(s) => {
+                HttpResponse::BadRequest().body(format!("Invalid config patch: {}", s))
+            }
+            SessionErr::SessionExpiredError => HttpResponse::Gone().body("Session expired"),
             SessionErr::BlobNotFoundError => HttpResponse::NotFound().body("Blob not found"),
             SessionErr::BlobLockedError => {
                 HttpResponse::build(StatusCode::from_u16(423).expect("Wrong http code - 423"))
                     .finish()
             }
+            SessionErr::RangeNotSatisfiable => {
+                HttpResponse::build(StatusCode::RANGE_NOT_SATISFIABLE).body("Invalid blob offset")
+            }
+            SessionErr::UnsupportedEncoding(s) => {
+                HttpResponse::build(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                    .body(format!("Unsupported content encoding: {}", s))
+            }
+            SessionErr::InsufficientStorage => {
+                HttpResponse::build(StatusCode::INSUFFICIENT_STORAGE)
+                    .body("Insufficient storage on the hub")
+            }
             SessionErr::DirectoryCreationError(s) => {
                 HttpResponse::InternalServerError().body(format!("Cannot create directory: {}", s))
             }
@@ -106,12 +138,19 @@ impl Into<HttpResponse> for SessionErr {
             SessionErr::CannotCreatePeerDeployment => {
                 HttpResponse::InternalServerError().body(format!("Cannot create peer deployment."))
             }
+            SessionErr::CannotListPeerDeployments => {
+                HttpResponse::InternalServerError().body(format!("Cannot list peer deployments."))
+            }
             SessionErr::CannotDeletePeerDeployment => {
                 HttpResponse::InternalServerError().body(format!("Cannot delete peer deployment."))
             }
             SessionErr::CannotUpdatePeerDeployment => {
                 HttpResponse::InternalServerError().body(format!("Cannot update peer deployment."))
             }
+            SessionErr::CannotCancelPeerDeploymentUpdate => HttpResponse::InternalServerError()
+                .body(format!("Cannot cancel peer deployment update.")),
+            SessionErr::CannotGetPeerDeploymentLogs => HttpResponse::InternalServerError()
+                .body(format!("Cannot get peer deployment logs.")),
             SessionErr::NodeNotFound(node_id) => {
                 HttpResponse::NotFound().body(format!("Node not found {:?}.", node_id))
             }
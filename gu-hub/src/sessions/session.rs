@@ -2,9 +2,12 @@ use actix_web::HttpResponse;
 use bytes::Bytes;
 use chrono::DateTime;
 use chrono::Utc;
-use futures::{future, prelude::*, stream};
+use futures::{future, prelude::*, stream, sync::mpsc};
 use gu_base::files::{read_async, write_async};
-use gu_model::session::{BlobInfo, Metadata};
+use gu_model::deployment::{
+    DeploymentFilter, DeploymentInfo, DeploymentStatus, PeerDeploymentInfo,
+};
+use gu_model::session::{BlobInfo, Metadata, SessionEvent};
 use gu_net::{rpc::peer, NodeId};
 use serde_json;
 use sessions::{
@@ -24,8 +27,11 @@ pub struct Session {
     path: PathBuf,
     next_id: u64,
     storage: HashMap<u64, Blob>,
+    /// content hashes of blobs uploaded since the session was loaded
+    hashes: HashMap<u64, String>,
     version: u64,
     peers: HashMap<NodeId, PeerState>,
+    subscribers: Vec<mpsc::UnboundedSender<SessionEvent>>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -91,8 +97,10 @@ impl Session {
             path: path.clone(),
             next_id: 0,
             storage: HashMap::new(),
+            hashes: HashMap::new(),
             version: 0,
             peers: HashMap::new(),
+            subscribers: Vec::new(),
         };
 
         let fut = fs::DirBuilder::new()
@@ -128,8 +136,10 @@ impl Session {
             path: path.clone(),
             next_id: 0,
             storage: HashMap::new(),
+            hashes: HashMap::new(),
             version: 0,
             peers: HashMap::new(),
+            subscribers: Vec::new(),
         };
 
         entries_id_iter(&path).for_each(|id| {
@@ -159,6 +169,25 @@ impl Session {
         self.info.clone()
     }
 
+    pub fn is_expired(&self) -> bool {
+        self.info
+            .expire
+            .map(|expire| expire < Utc::now())
+            .unwrap_or(false)
+    }
+
+    /// returns stream of changes of this session; ends when the session is dropped
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<SessionEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    fn notify(&mut self, event: SessionEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
     pub fn metadata(&self) -> &Metadata {
         &self.state
     }
@@ -173,6 +202,9 @@ impl Session {
         self.version += 1;
 
         let new_state_version = self.state.version;
+        self.notify(SessionEvent::ConfigChanged {
+            version: new_state_version,
+        });
 
         futures::future::Either::A(
             write_async(
@@ -184,6 +216,31 @@ impl Session {
         )
     }
 
+    /// sets the session config, provided its current version is `version`
+    pub fn set_metadata_if_version(
+        &mut self,
+        mut val: Metadata,
+        version: u64,
+    ) -> impl Future<Item = u64, Error = SessionErr> {
+        if self.state.version != version {
+            return future::Either::B(future::err(SessionErr::VersionMismatch));
+        }
+        val.version = version;
+        future::Either::A(self.set_metadata(val))
+    }
+
+    /// applies JSON merge patch to the session config
+    pub fn patch_metadata(
+        &mut self,
+        patch: serde_json::Value,
+    ) -> impl Future<Item = u64, Error = SessionErr> {
+        let mut metadata = self.state.clone();
+        match metadata.apply_patch(&patch) {
+            Ok(()) => future::Either::A(self.set_metadata(metadata)),
+            Err(e) => future::Either::B(future::err(SessionErr::InvalidConfigPatch(e))),
+        }
+    }
+
     fn new_blob_inner(&mut self, blob: Blob, id: Option<u64>) -> Result<(u64, Blob), SessionErr> {
         let id = match id {
             None => self.next_id,
@@ -201,7 +258,11 @@ impl Session {
     pub fn new_blob(&mut self) -> Result<(u64, Blob), SessionErr> {
         let blob = Blob::new(self.path.join(format!("{}", self.next_id)))
             .map_err(|e| SessionErr::FileError(e.to_string()))?;
-        self.new_blob_inner(blob, None)
+        let (id, blob) = self.new_blob_inner(blob, None)?;
+        self.notify(SessionEvent::BlobCreated {
+            blob_id: id.to_string(),
+        });
+        Ok((id, blob))
     }
 
     pub fn set_blob(&mut self, id: u64, blob: Blob) -> SessionResult {
@@ -219,10 +280,57 @@ impl Session {
         }
     }
 
+    /// Gets blob for a write; its content hash is forgotten until the write is
+    /// recorded by `dedup_blob`, so no upload is deduplicated against it meanwhile.
+    pub fn blob_for_write(&mut self, id: u64) -> Result<Blob, SessionErr> {
+        let blob = self
+            .storage
+            .get(&id)
+            .cloned()
+            .ok_or(SessionErr::BlobNotFoundError)?;
+        self.hashes.remove(&id);
+        Ok(blob)
+    }
+
+    /// Records content hash of an uploaded blob; when `link` is set, returns other
+    /// blob with the same content the blob may become a reference to
+    /// (see `Blob::dedup_with`).
+    pub fn dedup_blob(
+        &mut self,
+        id: u64,
+        hash: String,
+        link: bool,
+    ) -> Result<Option<(u64, Blob)>, SessionErr> {
+        if !self.storage.contains_key(&id) {
+            return Err(SessionErr::BlobNotFoundError);
+        }
+        self.hashes.remove(&id);
+        let existing = match link {
+            true => self
+                .hashes
+                .iter()
+                .find(|(_, other_hash)| **other_hash == hash)
+                .and_then(|(other_id, _)| {
+                    self.storage
+                        .get(other_id)
+                        .map(|other| (*other_id, other.clone()))
+                }),
+            false => None,
+        };
+        self.hashes.insert(id, hash);
+        Ok(existing)
+    }
+
     pub fn delete_blob(&mut self, id: u64) -> SessionResult {
         self.version += 1;
+        self.hashes.remove(&id);
         match self.storage.remove(&id).map(|b| b.clean_file()) {
-            Some(Ok(())) => Ok(SessionOk::Ok),
+            Some(Ok(())) => {
+                self.notify(SessionEvent::BlobDeleted {
+                    blob_id: id.to_string(),
+                });
+                Ok(SessionOk::Ok)
+            }
             Some(Err(e)) => Err(SessionErr::FileError(e.to_string())),
             None => Ok(SessionOk::BlobAlreadyDeleted),
         }
@@ -237,8 +345,12 @@ impl Session {
 
     pub fn list_blobs(&self) -> Vec<BlobInfo> {
         self.storage
-            .keys()
-            .map(|e| BlobInfo { id: e.to_string() })
+            .iter()
+            .map(|(id, blob)| BlobInfo {
+                id: id.to_string(),
+                content_type: Some(blob.content_type()),
+                ..BlobInfo::default()
+            })
             .collect()
     }
 
@@ -258,22 +370,56 @@ impl Session {
             .filter(|p| !self.peers.contains_key(p))
             .map(|peer| (peer, PeerState::default()))
             .collect::<Vec<_>>();
+        for (node_id, _) in &new_peers {
+            self.notify(SessionEvent::PeerAdded { node_id: *node_id });
+        }
         self.peers.extend(new_peers);
         self.peers.keys().cloned().collect()
     }
 
+    /// fails unless the session has not expired and the deployment was created on
+    /// the peer within it
+    pub fn check_deployment(&self, node_id: NodeId, deployment_id: &str) -> Result<(), SessionErr> {
+        if self.is_expired() {
+            return Err(SessionErr::SessionExpiredError);
+        }
+        match self.peers.get(&node_id) {
+            None => Err(SessionErr::NodeNotFound(node_id)),
+            Some(peer) if !peer.deployments.contains(deployment_id) => {
+                Err(SessionErr::DeploymentNotFound(deployment_id.to_owned()))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
     pub fn remove_deployment(&mut self, node_id: NodeId, deployment_id: String) -> bool {
-        match self.peers.get_mut(&node_id) {
+        let removed = match self.peers.get_mut(&node_id) {
             None => false,
             Some(peer) => peer.deployments.remove(&deployment_id),
+        };
+        if removed {
+            self.notify(SessionEvent::DeploymentStatusChanged {
+                node_id,
+                deployment_id,
+                status: DeploymentStatus::DESTROYING,
+            });
         }
+        removed
     }
 
     pub fn add_deployment(&mut self, node_id: NodeId, deployment_id: String) {
-        let _ = self
+        let added = self
             .peers
             .get_mut(&node_id)
-            .and_then(|node_info| Some(node_info.deployments.insert(deployment_id)));
+            .map(|node_info| node_info.deployments.insert(deployment_id.clone()))
+            .unwrap_or(false);
+        if added {
+            self.notify(SessionEvent::DeploymentStatusChanged {
+                node_id,
+                deployment_id,
+                status: DeploymentStatus::CREATED,
+            });
+        }
     }
 
     pub fn create_deployment(
@@ -295,6 +441,63 @@ impl Session {
         )
     }
 
+    pub fn list_deployments(
+        &self,
+        node_id: NodeId,
+    ) -> impl Future<Item = Vec<peer::PeerSessionInfo>, Error = SessionErr> {
+        let deployments = match self.peers.get(&node_id) {
+            None => return future::Either::A(future::err(SessionErr::NodeNotFound(node_id))),
+            Some(peer_state) => peer_state.deployments.clone(),
+        };
+        future::Either::B(
+            peer(node_id)
+                .into_endpoint()
+                .send(gu_model::envman::GetSessions::default())
+                .map_err(|_| SessionErr::CannotListPeerDeployments)
+                .and_then(|v| future::result(v).map_err(|_| SessionErr::CannotListPeerDeployments))
+                .map(move |sessions| {
+                    sessions
+                        .into_iter()
+                        .filter(|session| deployments.contains(&session.id))
+                        .collect()
+                }),
+        )
+    }
+
+    /// deployments of the session on all its peers matching the filter;
+    /// peers which cannot be asked are skipped
+    pub fn find_deployments(
+        &self,
+        filter: DeploymentFilter,
+    ) -> impl Future<Item = Vec<PeerDeploymentInfo>, Error = SessionErr> {
+        future::join_all(
+            self.peers
+                .keys()
+                .map(|&node_id| {
+                    let filter = filter.clone();
+                    self.list_deployments(node_id).then(move |r| match r {
+                        Ok(sessions) => Ok(sessions
+                            .into_iter()
+                            .map(DeploymentInfo::from)
+                            .filter(|deployment| filter.matches(deployment))
+                            .map(|deployment| PeerDeploymentInfo {
+                                node_id,
+                                deployment,
+                            })
+                            .collect()),
+                        Err(e) => {
+                            warn!("cannot list deployments of {:?}: {:?}", node_id, e);
+                            Ok(Vec::new())
+                        }
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )
+        .map(|deployments: Vec<Vec<PeerDeploymentInfo>>| {
+            deployments.into_iter().flatten().collect()
+        })
+    }
+
     pub fn delete_deployment(
         &mut self,
         node_id: NodeId,
@@ -336,7 +539,8 @@ impl Session {
         node_id: NodeId,
         deployment_id: String,
         commands: Vec<gu_model::envman::Command>,
-    ) -> impl Future<Item = Vec<String>, Error = SessionErr> {
+        continue_on_error: bool,
+    ) -> impl Future<Item = Result<Vec<String>, Vec<String>>, Error = SessionErr> {
         if self.peers.get(&node_id).is_none() {
             return future::Either::A(future::err(SessionErr::NodeNotFound(node_id)));
         }
@@ -346,11 +550,52 @@ impl Session {
                 .send(gu_model::envman::SessionUpdate {
                     session_id: deployment_id,
                     commands: commands,
+                    continue_on_error,
+                })
+                .map_err(|_| SessionErr::CannotUpdatePeerDeployment),
+        )
+    }
+
+    pub fn cancel_deployment_update(
+        &self,
+        node_id: NodeId,
+        deployment_id: String,
+    ) -> impl Future<Item = usize, Error = SessionErr> {
+        if self.peers.get(&node_id).is_none() {
+            return future::Either::A(future::err(SessionErr::NodeNotFound(node_id)));
+        }
+        future::Either::B(
+            peer(node_id)
+                .into_endpoint()
+                .send(gu_model::envman::CancelUpdate {
+                    session_id: deployment_id,
                 })
-                .map_err(|_| SessionErr::CannotUpdatePeerDeployment)
-                .map(|results| match results {
-                    Ok(r) => r,
-                    Err(e) => e,
+                .map_err(|_| SessionErr::CannotCancelPeerDeploymentUpdate)
+                .and_then(|v| {
+                    future::result(v).map_err(|_| SessionErr::CannotCancelPeerDeploymentUpdate)
+                }),
+        )
+    }
+
+    pub fn deployment_logs(
+        &self,
+        node_id: NodeId,
+        deployment_id: String,
+        offset: usize,
+    ) -> impl Future<Item = Vec<gu_model::envman::LogLine>, Error = SessionErr> {
+        if self.peers.get(&node_id).is_none() {
+            return future::Either::A(future::err(SessionErr::NodeNotFound(node_id)));
+        }
+        future::Either::B(
+            peer(node_id)
+                .into_endpoint()
+                .send(gu_model::envman::GetSessionLogs {
+                    session_id: deployment_id,
+                    offset,
+                })
+                .map_err(|_| SessionErr::CannotGetPeerDeploymentLogs)
+                .and_then(|v| {
+                    future::result(v).map_err(|_| SessionErr::CannotGetPeerDeploymentLogs)
                 }),
         )
     }
@@ -5,7 +5,9 @@ pub type Pid = String;
 
 pub type PidSet = super::Map<Pid, ProcessInfo>;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+/// Status of a deployment as reported by the hub; mirrors `PeerSessionStatus`
+/// of the provider, in lowercase.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeploymentStatus {
     /// during session creation
     #[serde(rename = "pending")]
@@ -34,9 +36,71 @@ pub struct DeploymentInfo {
     pub tags: Tags,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub note: Option<String>,
+    /// url of the image the deployment was created from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
     pub processes: PidSet,
 }
 
+/// Deployment together with the peer it runs on.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDeploymentInfo {
+    pub node_id: gu_net::NodeId,
+    pub deployment: DeploymentInfo,
+}
+
+/// `?envType=docker&imageUrl=...&tags=a,b` query of deployment search;
+/// a deployment matches when it meets every criterion given.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentFilter {
+    /// environment type, e.g. `hd` or `docker`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    /// comma separated tags, all of which the deployment must carry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+}
+
+impl DeploymentFilter {
+    /// tags are sent comma separated; a valid `Tag` never contains a `,`
+    pub fn with_tags<S: AsRef<str>>(self, tags: &[S]) -> Self {
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        DeploymentFilter {
+            tags: if tags.is_empty() {
+                None
+            } else {
+                Some(tags.join(","))
+            },
+            ..self
+        }
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags
+            .iter()
+            .flat_map(|tags| tags.split(','))
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+    }
+
+    /// checks the deployment against this filter; an empty filter matches every deployment
+    pub fn matches(&self, deployment: &DeploymentInfo) -> bool {
+        // deployment ids are prefixed with their environment type, e.g. `docker::`
+        let env_type = deployment.id.split("::").next();
+        self.env_type
+            .as_ref()
+            .map_or(true, |expected| env_type == Some(expected.as_str()))
+            && self.image_url.as_ref().map_or(true, |expected| {
+                deployment.image_url.as_ref() == Some(expected)
+            })
+            && self.tags().all(|tag| deployment.tags.contains(tag))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ProcessInfo {
@@ -51,6 +115,7 @@ impl From<gu_net::rpc::peer::PeerSessionInfo> for DeploymentInfo {
             status: peer.status.into(),
             tags: peer.tags.into_iter().collect(),
             note: peer.note,
+            image_url: peer.image_url,
             processes: PidSet::new(),
         }
     }
@@ -69,3 +134,52 @@ impl From<gu_net::rpc::peer::PeerSessionStatus> for DeploymentStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use gu_net::rpc::peer::PeerSessionStatus;
+
+    #[test]
+    fn status_names_follow_peer_session_status() {
+        for status in vec![
+            PeerSessionStatus::PENDING,
+            PeerSessionStatus::CREATED,
+            PeerSessionStatus::RUNNING,
+            PeerSessionStatus::CONFIGURED,
+            PeerSessionStatus::DESTROYING,
+        ] {
+            let raw = serde_json::to_string(&status).unwrap();
+            let deployment_status: DeploymentStatus = status.into();
+            assert_eq!(
+                serde_json::to_string(&deployment_status).unwrap(),
+                raw.to_lowercase()
+            );
+        }
+    }
+
+    #[test]
+    fn test_deployment_filter() {
+        let deployment = DeploymentInfo {
+            id: "docker::1234".into(),
+            name: "worker".into(),
+            status: DeploymentStatus::RUNNING,
+            tags: vec!["gpu".to_string(), "linux".to_string()]
+                .into_iter()
+                .collect(),
+            note: None,
+            image_url: Some("golemfactory/worker".into()),
+            processes: PidSet::new(),
+        };
+        let filter = |json: &str| serde_json::from_str::<DeploymentFilter>(json).unwrap();
+
+        assert!(DeploymentFilter::default().matches(&deployment));
+        assert!(filter(r#"{"envType": "docker", "tags": "gpu, linux"}"#).matches(&deployment));
+        assert!(filter(r#"{"imageUrl": "golemfactory/worker"}"#).matches(&deployment));
+        assert!(!filter(r#"{"envType": "hd"}"#).matches(&deployment));
+        assert!(!filter(r#"{"imageUrl": "alpine"}"#).matches(&deployment));
+        assert!(!DeploymentFilter::default()
+            .with_tags(&["gpu", "win"])
+            .matches(&deployment));
+    }
+}
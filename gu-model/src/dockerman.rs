@@ -4,6 +4,45 @@ use serde_derive::*;
 pub struct CreateOptions {
     pub volumes: Vec<VolumeDef>,
     pub cmd: Option<Vec<String>>,
+    #[serde(default)]
+    pub env: Vec<(String, String)>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    /// memory limit of the container; unlimited when not set
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    /// CPU limit of the container in cores, e.g. 0.5; unlimited when not set
+    #[serde(default)]
+    pub cpu_quota: Option<f64>,
+    /// mounts container root filesystem read-only; volumes stay writable
+    #[serde(default)]
+    pub read_only_rootfs: bool,
+    /// linux capabilities dropped from the container, e.g. `NET_RAW` or `ALL`
+    #[serde(default)]
+    pub cap_drop: Vec<String>,
+    /// seconds given to the container to stop on destroy before it is killed; 10 when not set
+    #[serde(default)]
+    pub stop_timeout: Option<u64>,
+    /// credentials for pulling the image from a private registry
+    #[serde(default)]
+    pub registry_auth: Option<RegistryAuth>,
+    /// hostname and IP address pairs added to `/etc/hosts` of the container
+    #[serde(default)]
+    pub extra_hosts: Vec<(String, String)>,
+    /// `none`, `host`, `bridge` or name of a docker network; default bridge when not set
+    #[serde(default)]
+    pub network_mode: Option<String>,
+}
+
+/// Credentials of a docker registry.
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub struct RegistryAuth {
+    pub username: String,
+    pub password: String,
+    /// registry address, e.g. `registry.example.com:5000`
+    pub server: String,
 }
 
 #[derive(Serialize, Deserialize, Hash, Clone, Eq, PartialEq)]
@@ -2,6 +2,8 @@ use actix::prelude::*;
 use gu_net::rpc::peer::PeerSessionInfo;
 use gu_net::rpc::PublicMessage;
 use serde_derive::*;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::{fmt, io};
 
 /// Errors
@@ -14,6 +16,8 @@ pub enum Error {
     NoSuchSession(String),
     NoSuchChild(String),
     UnknownEnv(String),
+    /// the environment already runs its maximal number of sessions
+    CapacityExceeded(usize),
 }
 
 impl From<io::Error> for Error {
@@ -39,6 +43,9 @@ impl fmt::Display for Error {
             Error::NoSuchSession(msg) => write!(f, "session not found: {}", msg)?,
             Error::NoSuchChild(msg) => write!(f, "child not found: {}", msg)?,
             Error::UnknownEnv(env_id) => write!(f, "unknown exec environment: {}", env_id)?,
+            Error::CapacityExceeded(max_sessions) => {
+                write!(f, "session limit of {} reached", max_sessions)?
+            }
         }
         Ok(())
     }
@@ -50,6 +57,84 @@ impl From<String> for Error {
     }
 }
 
+/// Maximal length (in bytes) of a single session tag.
+pub const MAX_TAG_LEN: usize = 256;
+
+/// Session tag; guaranteed to be non-blank, free of control characters and
+/// commas (which separate tags in query filters), and at most `MAX_TAG_LEN`
+/// bytes long. Serialized as a plain string.
+#[derive(Serialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(transparent)]
+pub struct Tag(String);
+
+impl Tag {
+    pub fn new<S: Into<String>>(tag: S) -> Result<Tag, Error> {
+        let tag = tag.into();
+        if tag.trim().is_empty() {
+            return Err(Error::Error(format!("invalid tag {:?}: empty", tag)));
+        }
+        if tag.chars().any(char::is_control) {
+            return Err(Error::Error(format!(
+                "invalid tag {:?}: contains control characters",
+                tag
+            )));
+        }
+        if tag.contains(',') {
+            return Err(Error::Error(format!("invalid tag {:?}: contains ','", tag)));
+        }
+        if tag.len() > MAX_TAG_LEN {
+            return Err(Error::Error(format!(
+                "invalid tag {:?}: longer than {} bytes",
+                tag, MAX_TAG_LEN
+            )));
+        }
+        Ok(Tag(tag))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Tag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> Self {
+        tag.0
+    }
+}
+
+impl std::str::FromStr for Tag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tag::new(s)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Tag {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let tag = String::deserialize(deserializer)?;
+        Tag::new(tag).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Deduplicated set of tags, serialized as a string array.
+pub type TagSet = crate::Set<Tag>;
+
 /// image with binaries and resources for given session
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -59,7 +144,7 @@ pub struct Image {
 }
 
 /// Message for session creation: local provisioning: downloads and unpacks the binaries
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateSession<Options = ()> {
     pub env_type: String,
@@ -69,8 +154,14 @@ pub struct CreateSession<Options = ()> {
     pub note: Option<String>,
     #[serde(default)]
     pub options: Options,
+    /// only validate the request; no image is pulled and no session is created
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
+/// Prefix of session ids returned for `CreateSession` with `dry_run` set.
+pub const DRY_RUN_SESSION_PREFIX: &str = "dryrun";
+
 impl<Options> PublicMessage for CreateSession<Options> {
     const ID: u32 = 37;
 }
@@ -85,17 +176,61 @@ impl<Options> Message for CreateSession<Options> {
 pub struct SessionUpdate {
     pub session_id: String,
     pub commands: Vec<Command>,
+    /// runs remaining commands after a failure instead of skipping them
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+/// Result entry of a command not run because an earlier one in the batch failed.
+pub const SKIPPED_COMMAND: &str = "skipped";
+
+/// Result entry of the command at `index` of the batch which failed with `error`.
+pub fn failed_command(index: usize, error: &str) -> String {
+    format!("command {} failed: {}", index, error)
+}
+
+/// Splits result entry made by `failed_command` into index and error.
+pub fn split_failed_command(entry: &str) -> Option<(usize, &str)> {
+    if !entry.starts_with("command ") {
+        return None;
+    }
+    let mut parts = entry["command ".len()..].splitn(2, " failed: ");
+    let index = parts.next()?.parse().ok()?;
+    parts.next().map(|error| (index, error))
+}
+
+/// Outcome of command number `index` of a streamed update, sent as a line of
+/// newline delimited JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandResult {
+    pub index: usize,
+    pub ok: bool,
+    /// command output, or error message when it failed
+    pub output: String,
+}
+
+impl CommandResult {
+    pub fn into_result(self) -> Result<String, String> {
+        match self.ok {
+            true => Ok(self.output),
+            false => Err(self.output),
+        }
+    }
 }
 
 impl PublicMessage for SessionUpdate {
     const ID: u32 = 38;
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, Ord, PartialOrd, PartialEq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, Ord, PartialOrd, PartialEq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum ResourceFormat {
     Raw,
     Tar,
+    // gzip compressed tar, decompressed on the fly
+    TarGz,
+    Zip,
 }
 
 impl Default for ResourceFormat {
@@ -104,13 +239,31 @@ impl Default for ResourceFormat {
     }
 }
 
-#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum Command {
+    #[serde(rename_all = "camelCase")]
     Exec {
         // return cmd output
         executable: String,
         args: Vec<String>,
+        // seconds after which the command is killed and reported as failed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+        // output is returned as `ExecLines` instead of `ExecResult`
+        #[serde(default)]
+        output_lines: bool,
+    },
+    // like `Exec`, with `stdin` bytes fed to standard input of the command; returns `ExecResult`
+    #[serde(rename_all = "camelCase")]
+    ExecWithInput {
+        executable: String,
+        args: Vec<String>,
+        stdin: Vec<u8>,
+    },
+    // runs script with `/bin/sh -c`; returns stdout followed by stderr
+    Shell {
+        script: String,
     },
     Open,
     Close,
@@ -124,15 +277,41 @@ pub enum Command {
     Stop {
         child_id: String,
     },
+    #[serde(rename_all = "camelCase")]
+    Signal {
+        child_id: String,
+        signal: i32,
+    },
     Wait,
-    AddTags(Vec<String>),
-    DelTags(Vec<String>),
+    // like `Wait`, but fails when main process exits with code other than
+    // `expected_exit`; when it is not given, any non-zero code fails if `strict`
+    #[serde(rename_all = "camelCase")]
+    WaitFor {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expected_exit: Option<i32>,
+        #[serde(default)]
+        strict: bool,
+    },
+    // returns `InspectResult` with the state of the deployment
+    Inspect,
+    Restart {
+        // seconds to wait for stop before killing the session
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+    // sets environment variables of subsequent execs; an empty value unsets the variable
+    SetEnv(Vec<(String, String)>),
+    AddTags(TagSet),
+    DelTags(TagSet),
     #[serde(rename_all = "camelCase")]
     DownloadFile {
         uri: String,
         file_path: String,
         #[serde(default)]
         format: ResourceFormat,
+        // hex encoded checksum of the downloaded resource; fetch is retried on mismatch
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
     },
     #[serde(rename_all = "camelCase")]
     UploadFile {
@@ -141,12 +320,160 @@ pub enum Command {
         #[serde(default)]
         format: ResourceFormat,
     },
+    // copies a file from workspace of another session of the same provider
+    #[serde(rename_all = "camelCase")]
+    CopyFile {
+        from_session: String,
+        from_path: PathBuf,
+        to_path: PathBuf,
+    },
+    // creates directory together with missing parents
+    #[serde(rename_all = "camelCase")]
+    Mkdir {
+        path: PathBuf,
+    },
+    // returns JSON-encoded `Vec<FileEntry>` with direct children of the directory
+    #[serde(rename_all = "camelCase")]
+    ListDir {
+        path: PathBuf,
+    },
+    // removes file, or directory with its content when `recursive`
+    #[serde(rename_all = "camelCase")]
+    RemovePath {
+        path: PathBuf,
+        #[serde(default)]
+        recursive: bool,
+    },
+}
+
+/// Output of `Command::Exec`, sent JSON-encoded in `SessionUpdate` results.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
+impl ExecResult {
+    pub fn is_success(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    /// splits output on line ends, e.g. for tools emitting JSON per line
+    pub fn into_lines(self) -> ExecLines {
+        fn lines(output: String) -> Vec<String> {
+            output.lines().map(str::to_owned).collect()
+        }
+
+        ExecLines {
+            stdout: lines(self.stdout),
+            stderr: lines(self.stderr),
+            exit_code: self.exit_code,
+        }
+    }
+
+    /// JSON-encoded result of `Command::Exec` with given `output_lines` flag
+    pub fn to_json(self, output_lines: bool) -> Result<String, String> {
+        match output_lines {
+            true => serde_json::to_string(&self.into_lines()),
+            false => serde_json::to_string(&self),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// Output of `Command::Exec` with `output_lines` set.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecLines {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+    pub exit_code: i32,
+}
+
+/// Output of `Command::Inspect`, sent JSON-encoded in `SessionUpdate` results.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectResult {
+    pub state: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// pids of processes started with `Command::Start`, by child id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub processes: BTreeMap<String, u32>,
+}
+
+/// Directory entry listed by `Command::ListDir`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    /// permission bits, e.g. `0o644`
+    pub mode: u32,
 }
 
 impl Message for SessionUpdate {
     type Result = Result<Vec<String>, Vec<String>>;
 }
 
+/// Message aborting `SessionUpdate`s in progress for given session.
+///
+/// Commands already started by the environment (e.g. spawned processes) are not undone.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelUpdate {
+    pub session_id: String,
+}
+
+impl PublicMessage for CancelUpdate {
+    const ID: u32 = 41;
+}
+
+impl Message for CancelUpdate {
+    /// number of cancelled updates
+    type Result = Result<usize, Error>;
+}
+
+/// Output stream a log line was written to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// Single line of session output, without the line terminator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub text: String,
+}
+
+/// Message returning output of a session, starting with line number `offset`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionLogs {
+    pub session_id: String,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+impl PublicMessage for GetSessionLogs {
+    const ID: u32 = 42;
+}
+
+impl Message for GetSessionLogs {
+    type Result = Result<Vec<LogLine>, Error>;
+}
+
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct GetSessions {}
 
@@ -158,6 +485,73 @@ impl Message for GetSessions {
     type Result = Result<Vec<PeerSessionInfo>, ()>;
 }
 
+/// Message returning environment types the provider can create sessions in, e.g. `hd`, `docker`.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct GetCapabilities {}
+
+impl PublicMessage for GetCapabilities {
+    const ID: u32 = 43;
+}
+
+impl Message for GetCapabilities {
+    type Result = Result<Vec<String>, ()>;
+}
+
+/// Number of sessions run by an execution environment and how many it accepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionCapacity {
+    pub active_sessions: usize,
+    /// `None` when the number of sessions is not limited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sessions: Option<usize>,
+}
+
+impl SessionCapacity {
+    /// sessions that can still be created; `None` when not limited
+    pub fn available(&self) -> Option<usize> {
+        self.max_sessions
+            .map(|max_sessions| max_sessions.saturating_sub(self.active_sessions))
+    }
+}
+
+/// Message returning session capacity of the provider, by environment type.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct GetCapacity {}
+
+impl PublicMessage for GetCapacity {
+    const ID: u32 = 45;
+}
+
+impl Message for GetCapacity {
+    type Result = Result<BTreeMap<String, SessionCapacity>, ()>;
+}
+
+/// Resources of the provider still free for new sessions.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceAvailability {
+    pub cpu_cores: usize,
+    pub free_memory_bytes: u64,
+    /// sessions that can still be created in environments limiting their
+    /// sessions; `None` when no environment is limited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_sessions: Option<usize>,
+}
+
+/// Message returning resources available on the provider, read from host
+/// stats and the current session count.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct GetResources {}
+
+impl PublicMessage for GetResources {
+    const ID: u32 = 46;
+}
+
+impl Message for GetResources {
+    type Result = Result<ResourceAvailability, ()>;
+}
+
 /// Message for session destruction: clean local resources and kill all child processes
 #[derive(Serialize, Deserialize)]
 pub struct DestroySession {
@@ -172,6 +566,27 @@ impl Message for DestroySession {
     type Result = Result<String, Error>;
 }
 
+/// Message changing name and tags of a running session in place; fields left
+/// as `None` are kept. Returns the updated session.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSessionMetadata {
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// replaces all tags of the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<TagSet>,
+}
+
+impl PublicMessage for SetSessionMetadata {
+    const ID: u32 = 44;
+}
+
+impl Message for SetSessionMetadata {
+    type Result = Result<PeerSessionInfo, Error>;
+}
+
 #[cfg(test)]
 mod test {
 
@@ -201,6 +616,7 @@ mod test {
         assert_eq!(c.image.hash, "12345");
         assert_eq!(c.tags.len(), 1);
         assert_eq!(c.tags[0], "lato");
+        assert!(!c.dry_run);
     }
 
     #[test]
@@ -221,13 +637,18 @@ mod test {
         // then
         assert_eq!(u.session_id, "hd::08087f8f-a0f3-41d4-a192-3388f46aa678");
         assert_eq!(u.commands.len(), 1);
+        assert!(!u.continue_on_error);
         if let Command::Exec {
             ref executable,
             ref args,
+            timeout_secs,
+            output_lines,
         } = u.commands[0]
         {
             assert_eq!(executable, "gu-mine");
             assert_eq!(args, &vec!(String::from("spec")));
+            assert_eq!(timeout_secs, None);
+            assert!(!output_lines);
         } else {
             panic!("Exec command expected");
         }
@@ -242,13 +663,15 @@ mod test {
             "commands":[
                 {"stop":{"childId":"145ccba6-ce24-4809-8856-7eae40092fdd"}},
                 {"delTags":["gu:mine:working"]}
-            ]
+            ],
+            "continueOnError":true
         }"#;
 
         // when
         let u: SessionUpdate = serde_json::from_str(json).unwrap();
         assert_eq!(u.session_id, "hd::4c562af4-db3f-4e57-8fac-cf30249db682");
         assert_eq!(u.commands.len(), 2);
+        assert!(u.continue_on_error);
 
         // then
         if let Command::Stop { ref child_id } = u.commands[0] {
@@ -259,9 +682,153 @@ mod test {
 
         if let Command::DelTags(ref tags) = u.commands[1] {
             assert_eq!(tags.len(), 1);
-            assert_eq!(tags, &vec!(String::from("gu:mine:working")));
+            assert!(tags.contains(&Tag::new("gu:mine:working").unwrap()));
         } else {
             panic!("DelTags command expected");
         }
     }
+
+    #[test]
+    fn test_session_update_signal_deserialization() {
+        // given
+        let json = r#"
+        {
+            "sessionId":"hd::4c562af4-db3f-4e57-8fac-cf30249db682",
+            "commands":[
+                {"signal":{"childId":"145ccba6-ce24-4809-8856-7eae40092fdd","signal":15}}
+            ]
+        }"#;
+
+        // when
+        let u: SessionUpdate = serde_json::from_str(json).unwrap();
+
+        // then
+        if let Command::Signal {
+            ref child_id,
+            signal,
+        } = u.commands[0]
+        {
+            assert_eq!(child_id, "145ccba6-ce24-4809-8856-7eae40092fdd");
+            assert_eq!(signal, 15);
+        } else {
+            panic!("Signal command expected");
+        }
+    }
+
+    #[test]
+    fn test_exec_result_deserialization() {
+        let json = r#"{"stdout":"out","stderr":"err","exitCode":2}"#;
+
+        let r: ExecResult = serde_json::from_str(json).unwrap();
+
+        assert_eq!(r.stdout, "out");
+        assert_eq!(r.stderr, "err");
+        assert_eq!(r.exit_code, 2);
+        assert!(!r.is_success());
+    }
+
+    #[test]
+    fn test_exec_result_lines() {
+        let r = ExecResult {
+            stdout: "{\"a\":1}\n{\"a\":2}\n".into(),
+            stderr: "warn\r\n".into(),
+            exit_code: 0,
+        };
+
+        assert_eq!(
+            r.to_json(true).unwrap(),
+            r#"{"stdout":["{\"a\":1}","{\"a\":2}"],"stderr":["warn"],"exitCode":0}"#
+        );
+    }
+
+    #[test]
+    fn test_split_failed_command() {
+        assert_eq!(
+            split_failed_command(&failed_command(3, "no such child: a failed")),
+            Some((3, "no such child: a failed"))
+        );
+        assert_eq!(split_failed_command("skipped"), None);
+        assert_eq!(split_failed_command("command x failed: err"), None);
+    }
+
+    #[test]
+    fn test_inspect_result_serialization() {
+        let r = InspectResult {
+            state: "running".into(),
+            pid: Some(42),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_string(&r).unwrap(),
+            r#"{"state":"running","pid":42}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Command>(r#""inspect""#).unwrap(),
+            Command::Inspect
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        assert!(Tag::new("gu:mine:working").is_ok());
+        assert!(Tag::new("").is_err());
+        assert!(Tag::new("  ").is_err());
+        assert!(Tag::new("a\nb").is_err());
+        assert!(Tag::new("a,b").is_err());
+        assert!(Tag::new("x".repeat(MAX_TAG_LEN + 1)).is_err());
+
+        let json = r#"{"addTags":["b","a","b"]}"#;
+        let c: Command = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&c).unwrap(),
+            r#"{"addTags":["a","b"]}"#
+        );
+
+        assert!(serde_json::from_str::<Command>(r#"{"addTags":[""]}"#).is_err());
+    }
+
+    #[test]
+    fn test_session_capacity() {
+        let capacity = SessionCapacity {
+            active_sessions: 3,
+            max_sessions: Some(2),
+        };
+        assert_eq!(capacity.available(), Some(0));
+        assert_eq!(
+            serde_json::to_string(&capacity).unwrap(),
+            r#"{"activeSessions":3,"maxSessions":2}"#
+        );
+        assert_eq!(SessionCapacity::default().available(), None);
+    }
+
+    #[test]
+    fn test_resource_availability_serialization() {
+        let resources = ResourceAvailability {
+            cpu_cores: 4,
+            free_memory_bytes: 1 << 30,
+            free_sessions: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&resources).unwrap(),
+            r#"{"cpuCores":4,"freeMemoryBytes":1073741824}"#
+        );
+    }
+
+    #[test]
+    fn test_set_session_metadata() {
+        let msg: SetSessionMetadata =
+            serde_json::from_str(r#"{"sessionId":"hd::1","name":"worker"}"#).unwrap();
+        assert_eq!(msg.name, Some("worker".into()));
+        assert!(msg.tags.is_none());
+        assert_eq!(
+            serde_json::to_string(&msg).unwrap(),
+            r#"{"sessionId":"hd::1","name":"worker"}"#
+        );
+
+        assert!(serde_json::from_str::<SetSessionMetadata>(
+            r#"{"sessionId":"hd::1","tags":["  "]}"#
+        )
+        .is_err());
+    }
 }
@@ -153,6 +153,9 @@ impl<'a> ParsedHash<'a> {
             (b"SHA3", 384) => Box::new(self.value_checker::<sha3::Sha3_384>()?),
             (b"SHA3", 512) => Box::new(self.value_checker::<sha3::Sha3_512>()?),
             (b"SHA1", 160) => Box::new(self.value_checker::<sha1::Sha1>()?),
+            (b"SHA256", 256) | (b"sha256", 256) => {
+                Box::new(self.value_checker::<sha2::Sha256>()?)
+            }
             _ => return Err(Error::InvalidHashFormat),
         })
     }
@@ -179,6 +182,7 @@ pub fn digest<R: AsRef<[u8]>>(hash_name: R, bits: usize) -> Result<Box<dyn DynDi
         (b"SHA3", 384) => Box::new(sha3::Sha3_384::default()),
         (b"SHA3", 512) => Box::new(sha3::Sha3_512::default()),
         (b"SHA1", 160) => Box::new(sha1::Sha1::default()),
+        (b"SHA256", 256) | (b"sha256", 256) => Box::new(sha2::Sha256::default()),
         _ => {
             return Err(Error::UnknownHashFunc(
                 String::from_utf8(hash_name.as_ref().to_vec()).unwrap_or_else(|_| "invalid".into()),
@@ -237,7 +241,11 @@ mod test {
             "SHA3:7ba62e92095980b4fd8a743d608d8a5b0b0224105ddab845845b7c622c60f248",
         );
         test_value(b"golem2", "SHA3:9fa5c15b117a49c638aa438e2b6e33601360732e8d1f776535d93e21f733dd501c9756fa2feb508d3daf180253ecc1ef");
-        test_value(b"golem1", "SHA3:e43d55ac264ee607918a78561e1f45779b192c747f5844d08a63697314ccf2445edb823cd6bbe14782a40a932176bcda9f35c097cbf49872095205ad102a7960")
+        test_value(b"golem1", "SHA3:e43d55ac264ee607918a78561e1f45779b192c747f5844d08a63697314ccf2445edb823cd6bbe14782a40a932176bcda9f35c097cbf49872095205ad102a7960");
+        test_value(
+            b"golem1",
+            "sha256:59cef7abdcc42ac3ed8e2bc0a6597be3776c92977b841db757cd3c463fa1a591",
+        )
     }
 
     #[test]
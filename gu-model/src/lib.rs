@@ -2,6 +2,7 @@ pub mod dockerman;
 pub mod envman;
 
 pub mod deployment;
+pub mod paging;
 pub mod peers;
 pub mod session;
 
@@ -0,0 +1,56 @@
+//! Paging of hub listings.
+
+use serde_derive::*;
+
+/// Response header with the number of all items of a paged listing.
+pub const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+/// `?offset=&limit=` query of a listing. Without `limit` all items past `offset` are returned.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Page {
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    pub fn new(offset: usize, limit: usize) -> Self {
+        Page {
+            offset,
+            limit: Some(limit),
+        }
+    }
+
+    /// returns items of this page and the number of all items
+    pub fn apply<T, I: IntoIterator<Item = T>>(&self, items: I) -> (Vec<T>, usize) {
+        let items: Vec<T> = items.into_iter().collect();
+        let total = items.len();
+        let page = items
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(total))
+            .collect();
+        (page, total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(Page::new(1, 2).apply(0..5), (vec![1, 2], 5));
+        assert_eq!(Page::new(4, 2).apply(0..5), (vec![4], 5));
+        assert_eq!(Page::new(7, 2).apply(0..5), (vec![], 5));
+        assert_eq!(Page::default().apply(0..3), (vec![0, 1, 2], 3));
+    }
+
+    #[test]
+    fn test_query() {
+        let page: Page = serde_json::from_str(r#"{"offset": 10}"#).unwrap();
+        assert_eq!(page.offset, 10);
+        assert_eq!(page.limit, None);
+    }
+}
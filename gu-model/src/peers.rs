@@ -1,4 +1,5 @@
 use super::deployment::DeploymentInfo;
+use super::envman::{ResourceAvailability, SessionCapacity};
 use super::Tags;
 use gu_net::NodeId;
 use serde_derive::*;
@@ -25,4 +26,87 @@ pub struct PeerDetails {
     pub tags: Tags,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub sessions: Vec<DeploymentInfo>,
+    /// environment types of the peer, e.g. `hd`, `docker`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    /// session capacity by environment type
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub capacity: BTreeMap<String, SessionCapacity>,
+    /// resources free for new sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceAvailability>,
+}
+
+/// `?tags=a,b&matchAll=true` query of the peer listing.
+///
+/// Without `matchAll` a peer matches when it carries any of the tags.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TagFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub match_all: bool,
+}
+
+impl TagFilter {
+    /// tags are sent comma separated, so none of them may contain a `,`
+    pub fn new<S: AsRef<str>>(tags: &[S], match_all: bool) -> Self {
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        TagFilter {
+            tags: if tags.is_empty() {
+                None
+            } else {
+                Some(tags.join(","))
+            },
+            match_all,
+        }
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags
+            .iter()
+            .flat_map(|tags| tags.split(','))
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+    }
+
+    /// checks peer tags against this filter; an empty filter matches every peer
+    pub fn matches<'a, I: IntoIterator<Item = &'a String>>(&self, peer_tags: I) -> bool {
+        let peer_tags: BTreeSet<&str> = peer_tags.into_iter().map(String::as_str).collect();
+        let mut tags = self.tags().peekable();
+
+        if tags.peek().is_none() {
+            true
+        } else if self.match_all {
+            tags.all(|tag| peer_tags.contains(tag))
+        } else {
+            tags.any(|tag| peer_tags.contains(tag))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tag_filter() {
+        let peer_tags = vec!["gpu".to_string(), "linux".to_string()];
+
+        assert!(TagFilter::default().matches(&peer_tags));
+        assert!(TagFilter::new(&["gpu", "win"], false).matches(&peer_tags));
+        assert!(!TagFilter::new(&["gpu", "win"], true).matches(&peer_tags));
+        assert!(TagFilter::new(&["gpu", "linux"], true).matches(&peer_tags));
+        assert!(!TagFilter::new(&["win"], false).matches(&peer_tags));
+    }
+
+    #[test]
+    fn test_tag_filter_query() {
+        let filter: TagFilter =
+            serde_json::from_str(r#"{"tags": "gpu, linux", "matchAll": true}"#).unwrap();
+
+        assert_eq!(filter.tags().collect::<Vec<_>>(), vec!["gpu", "linux"]);
+        assert!(filter.match_all);
+    }
 }
@@ -1,9 +1,16 @@
+use super::deployment::DeploymentStatus;
 use super::Map;
 use super::Tags;
 use chrono::prelude::*;
 use chrono::DateTime;
+use gu_net::NodeId;
 use serde_derive::*;
 use serde_json::Value as JsonValue;
+use std::time::Duration;
+
+/// Header with a client generated key of `POST /sessions`; a repeated key
+/// returns the session created before instead of a new one.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
 
 #[derive(Serialize, Deserialize)]
 pub struct HubSessionUpdate {
@@ -27,6 +34,13 @@ pub enum Command {
 pub struct HubSessionSpec {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires: Option<DateTime<Utc>>,
+    /// max session lifetime, counted from its creation
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "opt_duration_secs"
+    )]
+    pub expires_in: Option<Duration>,
     #[serde(default)]
     pub allocation: AllocationMode,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -35,6 +49,46 @@ pub struct HubSessionSpec {
     pub tags: Tags,
 }
 
+impl HubSessionSpec {
+    /// checks the spec before it is sent to the hub
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.name {
+            if name.trim().is_empty() {
+                return Err("session name must not be empty".into());
+            }
+        }
+        if let Some(expires) = self.expires {
+            if expires <= Utc::now() {
+                return Err(format!("session expiration {} is in the past", expires));
+            }
+        }
+        if self.expires_in == Some(Duration::from_secs(0)) {
+            return Err("session lifetime must be positive".into());
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err("session tags must not be empty".into());
+        }
+        Ok(())
+    }
+}
+
+/// (De)serializes `Option<Duration>` as a number of seconds.
+mod opt_duration_secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match d {
+            Some(d) => s.serialize_some(&d.as_secs()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(d)?.map(Duration::from_secs))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct HubExistingSession {
@@ -88,6 +142,80 @@ pub struct SessionDetails {
 #[serde(rename_all = "camelCase")]
 pub struct BlobInfo {
     pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// size in bytes; reported after upload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// `sha1:<hex>` checksum of the content; reported after upload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Change of a hub session pushed to `GET /sessions/{sessionId}/events` subscribers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum SessionEvent {
+    #[serde(rename_all = "camelCase")]
+    PeerAdded { node_id: NodeId },
+    #[serde(rename_all = "camelCase")]
+    BlobCreated { blob_id: String },
+    #[serde(rename_all = "camelCase")]
+    BlobDeleted { blob_id: String },
+    #[serde(rename_all = "camelCase")]
+    DeploymentStatusChanged {
+        node_id: NodeId,
+        deployment_id: String,
+        status: DeploymentStatus,
+    },
+    /// session config was replaced; `version` is the new config version
+    #[serde(rename_all = "camelCase")]
+    ConfigChanged { version: u64 },
+}
+
+impl Metadata {
+    /// applies JSON merge patch (RFC 7386) to config entries; `version` is left unchanged
+    pub fn apply_patch(&mut self, patch: &JsonValue) -> Result<(), String> {
+        let patch = match patch {
+            JsonValue::Object(patch) => patch,
+            _ => return Err("config patch must be a JSON object".into()),
+        };
+
+        for (key, value) in patch.iter().filter(|(key, _)| key.as_str() != "version") {
+            if value.is_null() {
+                self.entry.remove(key);
+            } else {
+                merge_patch(
+                    self.entry.entry(key.clone()).or_insert(JsonValue::Null),
+                    value,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies JSON merge patch (RFC 7386) to `target`.
+pub fn merge_patch(target: &mut JsonValue, patch: &JsonValue) {
+    let patch = match patch {
+        JsonValue::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = JsonValue::Object(Default::default());
+    }
+    if let JsonValue::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(JsonValue::Null), value);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -95,6 +223,24 @@ mod test {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_metadata_patch() {
+        let mut metadata: Metadata =
+            serde_json::from_str(r#"{"version": 3, "ala": 10, "kot": {"a": 89, "b": 1}}"#).unwrap();
+
+        metadata
+            .apply_patch(
+                &json!({"version": 7, "ala": null, "kot": {"b": null, "c": [2]}, "pies": "x"}),
+            )
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&metadata).unwrap(),
+            json!({"version": 3, "kot": {"a": 89, "c": [2]}, "pies": "x"})
+        );
+        assert!(metadata.apply_patch(&json!([1])).is_err());
+    }
+
     #[test]
     fn test_metadata() {
         let m0 = r#"{"version": 0, "ala": 10, "kot": {"a": 89}}"#;
@@ -129,6 +275,50 @@ mod test {
         eprintln!("{}", j1);
     }
 
+    #[test]
+    fn test_session_event_json() {
+        let event = SessionEvent::DeploymentStatusChanged {
+            node_id: NodeId::default(),
+            deployment_id: "d1".into(),
+            status: DeploymentStatus::RUNNING,
+        };
+        let value = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(value["event"], json!("deploymentStatusChanged"));
+        assert_eq!(value["deploymentId"], json!("d1"));
+        assert_eq!(value["status"], json!("running"));
+        assert_eq!(
+            serde_json::from_value::<SessionEvent>(value).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn test_spec_validate() {
+        assert!(HubSessionSpec::default().validate().is_ok());
+
+        let invalid = vec![
+            r#"{"name": " "}"#,
+            r#"{"expires": "2000-01-01T00:00:00Z"}"#,
+            r#"{"expiresIn": 0}"#,
+            r#"{"tags": ["a", ""]}"#,
+        ];
+        for spec in invalid {
+            let spec: HubSessionSpec = serde_json::from_str(spec).unwrap();
+            assert!(spec.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_spec_expires_in() {
+        let spec: HubSessionSpec = serde_json::from_str(r#"{"expiresIn": 60}"#).unwrap();
+        assert_eq!(spec.expires_in, Some(Duration::from_secs(60)));
+        assert_eq!(serde_json::to_value(&spec).unwrap()["expiresIn"], json!(60));
+
+        let spec: HubSessionSpec = serde_json::from_str("{}").unwrap();
+        assert_eq!(spec.expires_in, None);
+    }
+
     #[test]
     fn test_hub_command() {
         let command = HubSessionUpdate {
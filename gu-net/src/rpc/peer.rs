@@ -47,6 +47,12 @@ pub struct PeerSessionInfo {
     pub tags: Vec<String>,
     pub note: Option<String>,
     pub processes: HashSet<String>,
+    /// size of the session workspace; `None` until it is computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_bytes: Option<u64>,
+    /// url of the image the session was created from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -55,6 +55,11 @@ impl<T: IntoDeployInfo + Destroy + GetStatus> DeployManager<T> {
         self.deploys.insert(id, deploy);
     }
 
+    /// number of sessions kept
+    pub fn count(&self) -> usize {
+        self.deploys.len()
+    }
+
     pub fn contains_deploy(&self, key: &String) -> bool {
         self.deploys.contains_key(key)
     }
@@ -2,32 +2,45 @@
 
 use super::deployment::{DeployManager, Destroy, IntoDeployInfo};
 use super::envman;
+use crate::id::{generate_new_id, new_id};
 use crate::provision;
-use crate::workspace::{Workspace, WorkspacesManager};
+use crate::workspace::{Retention, Workspace, WorkspacesManager, DISK_USAGE_REFRESH_INTERVAL};
 use actix::prelude::*;
 use actix_web::error::ErrorInternalServerError;
 use actix_web::http::StatusCode;
 use async_docker::models::ContainerConfig;
 use async_docker::{self, new_docker, DockerApi};
-use futures::future;
+use futures::future::{self, Shared};
 use futures::prelude::*;
-use gu_model::dockerman::{CreateOptions, VolumeDef};
+use futures::stream;
+use futures::sync::oneshot;
+use gu_actix::prelude::*;
+use gu_model::dockerman::{CreateOptions, RegistryAuth, VolumeDef};
 use gu_model::envman::*;
 use gu_net::rpc::peer::PeerSessionInfo;
 use gu_net::rpc::peer::PeerSessionStatus;
-use gu_persist::config::ConfigModule;
-use log::{debug, error, info};
+use gu_persist::config::{ConfigManager, ConfigModule, GetConfig, HasSectionId};
+use log::{debug, error, info, warn};
+use serde_derive::*;
 use serde_json::json;
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 // Actor.
 struct DockerMan {
     docker_api: Option<Box<DockerApi>>,
     deploys: DeployManager<DockerSession>,
     workspaces_man: WorkspacesManager,
+    /// sessions waiting for their image to be pulled
+    pending: HashMap<String, PendingPull>,
+    config: Arc<DockerConfig>,
 }
 
 impl Default for DockerMan {
@@ -37,17 +50,217 @@ impl Default for DockerMan {
             docker_api: None,
             deploys: DeployManager::default(),
             workspaces_man: WorkspacesManager::new(&config, "docker").unwrap(),
+            pending: HashMap::new(),
+            config: Arc::new(DockerConfig::default()),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct DockerConfig {
+    /// host directories that volumes may be bound from; only the workspaces directory
+    /// when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allowed_volume_sources: Option<Vec<PathBuf>>,
+    /// directory keeping session workspaces, e.g. on a dedicated scratch disk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace_dir: Option<PathBuf>,
+    /// on startup, workspaces left unused for this many hours are removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace_retention_hours: Option<u64>,
+    /// sessions (including ones pulling their image) run at once; not limited when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_concurrent_sessions: Option<usize>,
+}
+
+impl HasSectionId for DockerConfig {
+    const SECTION_ID: &'static str = "docker-cfg";
+}
+
+impl DockerConfig {
+    fn workspaces_manager(&self) -> Option<WorkspacesManager> {
+        let root = match self.workspace_dir {
+            Some(ref dir) => dir.clone(),
+            None => WorkspacesManager::default_root(&ConfigModule::new()),
+        };
+        let retention = match self.workspace_retention_hours {
+            Some(hours) => Retention::RemoveOlderThan(Duration::from_secs(hours * 3600)),
+            None => Retention::KeepAll,
+        };
+        WorkspacesManager::with_root(root, "docker", retention)
+    }
+}
+
 struct DockerSession {
     workspace: Workspace,
     container: async_docker::communicate::Container,
     status: PeerSessionStatus,
+    children: HashMap<String, DockerChild>,
+    /// variables set with `Command::SetEnv`, passed to every exec
+    env: BTreeMap<String, String>,
+    /// time given to the container to stop on destroy
+    stop_timeout: Duration,
+    /// whether `/bin/sh` can be run in the container; probed before the first exec
+    shell: Option<bool>,
+    /// lines of container output collected by `follow_logs`
+    logs: Rc<RefCell<Vec<LogLine>>>,
+}
+
+/// Process started inside of the container with `Command::Start`
+struct DockerChild {
+    /// resolves when the exec attached to the process is finished
+    finished: Shared<oneshot::Receiver<()>>,
+}
+
+/// Session which image is being pulled, reported as a `PENDING` deployment
+struct PendingPull {
+    name: String,
+    tags: Vec<String>,
+    image_url: String,
+    /// layer id -> (downloaded bytes, total bytes)
+    layers: HashMap<String, (u64, u64)>,
+}
+
+impl PendingPull {
+    /// updates layers progress with a docker pull event
+    fn update(&mut self, event: &serde_json::Value) {
+        let layer_id = match event["id"].as_str() {
+            Some(layer_id) => layer_id,
+            None => return,
+        };
+        let detail = &event["progressDetail"];
+
+        match (
+            event["status"].as_str(),
+            detail["current"].as_u64(),
+            detail["total"].as_u64(),
+        ) {
+            (Some("Downloading"), Some(current), Some(total)) => {
+                self.layers.insert(layer_id.to_string(), (current, total));
+            }
+            (Some("Download complete"), _, _) | (Some("Pull complete"), _, _) => {
+                if let Some(layer) = self.layers.get_mut(layer_id) {
+                    layer.0 = layer.1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn percent(&self) -> u64 {
+        let (current, total) = self
+            .layers
+            .values()
+            .fold((0, 0), |(c, t), (current, total)| (c + current, t + total));
+
+        match total {
+            0 => 0,
+            total => current * 100 / total,
+        }
+    }
+
+    fn info(&self, id: &str) -> PeerSessionInfo {
+        PeerSessionInfo {
+            id: id.to_string(),
+            name: self.name.clone(),
+            status: PeerSessionStatus::PENDING,
+            tags: self.tags.clone(),
+            note: Some(format!("pulling image: {}%", self.percent())),
+            processes: Default::default(),
+            disk_bytes: None,
+            image_url: Some(self.image_url.clone()),
+        }
+    }
+}
+
+#[derive(Message)]
+struct PullProgress {
+    pending_id: String,
+    event: serde_json::Value,
+}
+
+impl Handler<PullProgress> for DockerMan {
+    type Result = ();
+
+    fn handle(&mut self, msg: PullProgress, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(pending) = self.pending.get_mut(&msg.pending_id) {
+            pending.update(&msg.event)
+        }
+    }
+}
+
+/// Splits output chunks into lines, separately for each stream.
+#[derive(Default)]
+struct LineSplitter {
+    stdout: String,
+    stderr: String,
+}
+
+impl LineSplitter {
+    fn push(&mut self, stream: LogStream, chunk: &str) -> Vec<LogLine> {
+        let pending = match stream {
+            LogStream::Stdout => &mut self.stdout,
+            LogStream::Stderr => &mut self.stderr,
+        };
+        pending.push_str(chunk);
+
+        let mut lines = Vec::new();
+        while let Some(end) = pending.find('\n') {
+            let text = pending[..end].trim_end_matches('\r').to_string();
+            pending.drain(..=end);
+            lines.push(LogLine { stream, text });
+        }
+        lines
+    }
+}
+
+/// Path (inside of the container) of the file holding pid of the given child
+fn pid_file(child_id: &str) -> String {
+    format!("/tmp/gu-{}.pid", child_id)
+}
+
+/// Shell script running its arguments with pid of the command stored in `pid_file`;
+/// the file is removed once the command finishes.
+fn pid_wrapper(child_id: &str) -> String {
+    format!(
+        "/bin/sh -c 'echo $$ > {0}; exec \"$@\"' sh \"$@\"; code=$?; rm -f {0}; exit $code",
+        pid_file(child_id)
+    )
+}
+
+/// directory of the container input of `Command::ExecWithInput` is stored in
+const EXEC_INPUT_DIR: &str = "/tmp";
+
+fn input_file(exec_id: &str) -> String {
+    format!("gu-{}.stdin", exec_id)
 }
 
 impl DockerSession {
+    /// fails unless the container is running (or, when `running` is not set, stopped);
+    /// docker inspect is asked, as the session status also follows `Command::Start`
+    fn check_container_running(
+        &mut self,
+        running: bool,
+    ) -> impl Future<Item = String, Error = String> {
+        self.container
+            .inspect()
+            .map_err(|e| format!("{}", e))
+            .and_then(move |details| {
+                let is_running = details
+                    .state()
+                    .and_then(|state| state.status())
+                    .map(|status| status == "running")
+                    .unwrap_or(false);
+                match (running, is_running) {
+                    (true, false) => Err("session is not running".to_string()),
+                    (false, true) => Err("session is already running".to_string()),
+                    _ => Ok("OK".into()),
+                }
+            })
+    }
+
+    /// starts the container; the session is `RUNNING` afterwards
     fn do_open(&mut self) -> impl Future<Item = String, Error = String> {
         self.container.start().then(|r| match r {
             Ok(status) => Ok("OK".into()),
@@ -55,6 +268,7 @@ impl DockerSession {
         })
     }
 
+    /// stops the container; the session is `CREATED` afterwards
     fn do_close(&mut self) -> impl Future<Item = String, Error = String> {
         self.container
             .stop(None)
@@ -62,48 +276,496 @@ impl DockerSession {
             .and_then(|v| Ok("OK".into()))
     }
 
-    fn do_start(&mut self) -> impl Future<Item = String, Error = String> {
+    /// restarts the container; children started in it are gone after the restart
+    fn do_restart(&mut self, timeout: Option<u64>) -> impl Future<Item = String, Error = String> {
         self.container
-            .start()
+            .restart(timeout.map(Duration::from_secs))
             .map_err(|e| format!("{}", e))
-            .and_then(|v| Ok("OK".into()))
+            .and_then(|_| Ok("OK".into()))
     }
 
+    /// stores variables for subsequent execs; an empty value removes the variable
+    fn do_set_env(&mut self, vars: Vec<(String, String)>) -> Result<String, String> {
+        DockerMan::env_vars(&vars).map_err(|e| e.to_string())?;
+        for (key, value) in vars {
+            if value.is_empty() {
+                self.env.remove(&key);
+            } else {
+                self.env.insert(key, value);
+            }
+        }
+        Ok(format!(
+            "environment set: {:?}",
+            self.env.keys().collect::<Vec<_>>()
+        ))
+    }
+
+    /// applies variables set with `Command::SetEnv`
+    fn with_session_env(
+        &self,
+        cfg: async_docker::models::ExecConfig,
+    ) -> async_docker::models::ExecConfig {
+        if self.env.is_empty() {
+            return cfg;
+        }
+        cfg.with_env(
+            self.env
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect(),
+        )
+    }
+
+    fn do_start(
+        &mut self,
+        executable: String,
+        args: Vec<String>,
+    ) -> impl Future<Item = String, Error = String> {
+        let child_id = generate_new_id(&self.children);
+        // shell wrapper stores pid of the process, so it can be signalled later
+        let mut cmd = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            pid_wrapper(&child_id),
+            "sh".to_string(),
+            executable,
+        ];
+        cmd.extend(args);
+
+        let cfg = {
+            use async_docker::models::*;
+
+            self.with_session_env(
+                ExecConfig::new()
+                    .with_attach_stdout(true)
+                    .with_attach_stderr(true)
+                    .with_cmd(cmd),
+            )
+        };
+
+        let (tx, rx) = oneshot::channel();
+        let log_id = child_id.clone();
+        Arbiter::spawn(
+            self.container
+                .exec(&cfg)
+                .for_each(|_| Ok(()))
+                .then(move |r| {
+                    match r {
+                        Ok(()) => debug!("child {} finished", log_id),
+                        Err(e) => error!("child {} failed: {}", log_id, e),
+                    }
+                    let _ = tx.send(());
+                    Ok(())
+                }),
+        );
+
+        self.children.insert(
+            child_id.clone(),
+            DockerChild {
+                finished: rx.shared(),
+            },
+        );
+        self.status = PeerSessionStatus::RUNNING;
+        future::ok(child_id)
+    }
+
+    /// terminates the child and waits until it is finished; the child is kept
+    /// until then, so a failed stop can be retried
+    fn do_stop(&mut self, child_id: String) -> impl Future<Item = String, Error = String> {
+        let finished = match self.children.get(&child_id) {
+            Some(child) => child.finished.clone(),
+            None => {
+                return future::Either::A(future::err(Error::NoSuchChild(child_id).to_string()))
+            }
+        };
+
+        future::Either::B(
+            self.kill_child(&child_id, "TERM")
+                .and_then(move |_| finished.then(move |_| Ok(format!("{} stopped", child_id)))),
+        )
+    }
+
+    /// sends signal to the child with `kill` run by `/bin/sh`, which the child needs anyway
+    /// to be started; signals missing from `signal_name` are rejected
+    fn do_signal(
+        &mut self,
+        child_id: String,
+        signal: i32,
+    ) -> impl Future<Item = String, Error = String> {
+        if !self.children.contains_key(&child_id) {
+            return future::Either::A(future::err(Error::NoSuchChild(child_id).to_string()));
+        }
+        let signal_name = match signal_name(signal) {
+            Some(name) => name,
+            None => {
+                return future::Either::A(future::err(
+                    Error::IncorrectOptions(format!(
+                        "signal {} is not supported in docker sessions",
+                        signal
+                    ))
+                    .to_string(),
+                ));
+            }
+        };
+
+        future::Either::B(
+            self.kill_child(&child_id, &signal_name[3..])
+                .and_then(move |_| Ok(format!("{} sent to {}", signal_name, child_id))),
+        )
+    }
+
+    fn kill_child(
+        &mut self,
+        child_id: &str,
+        signal: &str,
+    ) -> impl Future<Item = String, Error = String> {
+        self.run_checked(
+            "/bin/sh".into(),
+            vec![
+                "-c".into(),
+                format!("kill -s {} $(cat {})", signal, pid_file(child_id)),
+            ],
+        )
+    }
+
+    /// runs command to completion; fails with its stderr on non-zero exit code
+    fn run_checked(
+        &mut self,
+        executable: String,
+        args: Vec<String>,
+    ) -> impl Future<Item = String, Error = String> {
+        self.do_exec(executable, args)
+            .and_then(|result| match result.is_success() {
+                true => Ok(result.stdout),
+                false => Err(result.stderr),
+            })
+    }
+
+    /// removes children which exited on their own
+    fn remove_finished(&mut self) {
+        let finished: Vec<String> = self
+            .children
+            .iter()
+            .filter_map(|(id, child)| match child.finished.peek() {
+                Some(_) => Some(id.clone()),
+                None => None,
+            })
+            .collect();
+
+        for f in finished {
+            self.children.remove(&f);
+            info!("finished {:?}; removing", f)
+        }
+    }
+
+    /// state of the container, as reported by docker inspect
+    fn do_inspect(&mut self) -> impl Future<Item = String, Error = String> {
+        self.container
+            .inspect()
+            .map_err(|e| format!("{}", e))
+            .and_then(|details| {
+                let state = details.state();
+                let result = InspectResult {
+                    state: state
+                        .and_then(|state| state.status())
+                        .cloned()
+                        .unwrap_or_default(),
+                    started_at: state.and_then(|state| state.started_at()).cloned(),
+                    ip: details
+                        .network_settings()
+                        .and_then(|network| network.ip_address())
+                        .filter(|ip| !ip.is_empty())
+                        .cloned(),
+                    // docker reports 0 for stopped containers
+                    pid: state
+                        .and_then(|state| state.pid())
+                        .filter(|pid| **pid > 0)
+                        .map(|pid| *pid as u32),
+                    processes: Default::default(),
+                };
+                serde_json::to_string(&result).map_err(|e| e.to_string())
+            })
+    }
+
+    /// waits for all started children or, if there are none, for the container
     fn do_wait(&mut self) -> impl Future<Item = String, Error = String> {
+        if self.children.is_empty() {
+            return future::Either::A(
+                self.container
+                    .wait()
+                    .map_err(|e| format!("{}", e))
+                    .and_then(|v| Ok("OK".into())),
+            );
+        }
+
+        future::Either::B(
+            future::join_all(
+                self.children
+                    .values()
+                    .map(|child| child.finished.clone().then(|_| Ok::<(), String>(())))
+                    .collect::<Vec<_>>(),
+            )
+            .and_then(|_| Ok("OK".into())),
+        )
+    }
+
+    /// Waits for the main process of the container and checks its exit code.
+    ///
+    /// Processes started with `Command::Start` are not awaited, as docker
+    /// does not report their exit codes.
+    fn do_wait_for(
+        &mut self,
+        expected_exit: Option<i32>,
+        strict: bool,
+    ) -> impl Future<Item = String, Error = String> {
         self.container
             .wait()
             .map_err(|e| format!("{}", e))
-            .and_then(|v| Ok("OK".into()))
+            .and_then(move |exit| {
+                check_exit_code(*exit.status_code() as i32, expected_exit, strict)
+            })
     }
 
+    /// runs `cmd` as is, collecting its output
+    fn exec_output(&mut self, cmd: Vec<String>) -> impl Future<Item = ExecOutput, Error = String> {
+        let cfg = {
+            use async_docker::models::*;
+
+            self.with_session_env(
+                ExecConfig::new()
+                    .with_attach_stdout(true)
+                    .with_attach_stderr(true)
+                    .with_cmd(cmd),
+            )
+        };
+
+        self.container
+            .exec(&cfg)
+            .map_err(|e| format!("{}", e))
+            .fold(ExecOutput::default(), |output, (t, it)| {
+                Ok::<_, String>(output.push(t, it.into_bytes().as_ref()))
+            })
+    }
+
+    /// checks whether `/bin/sh` can be run in the container; a failed exec means it cannot
+    fn probe_shell(&mut self) -> impl Future<Item = bool, Error = String> {
+        let token = new_id();
+        let cmd = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            format!("echo {}", token),
+        ];
+
+        self.exec_output(cmd).then(move |output| match output {
+            Ok(output) => Ok(output.into_result().stdout.contains(&token)),
+            Err(e) => {
+                debug!("shell probe failed: {}", e);
+                Ok(false)
+            }
+        })
+    }
+
+    /// Runs command to completion, keeping stdout and stderr apart.
+    ///
+    /// The command is wrapped in a shell which reports its exit status as
+    /// the last stderr line, marked with a token unique to the exec; the marker
+    /// is stripped from the result. Without a shell in the container the command
+    /// is run as is and its exit code is reported as `EXIT_CODE_UNKNOWN`.
     fn do_exec(
         &mut self,
         executable: String,
         mut args: Vec<String>,
-    ) -> impl Future<Item = String, Error = String> {
+    ) -> impl Future<Item = ExecResult, Error = String> {
         args.insert(0, executable);
-        let cfg = {
+        if self.shell == Some(false) {
+            return future::Either::A(self.exec_output(args).map(|output| ExecResult {
+                exit_code: EXIT_CODE_UNKNOWN,
+                ..output.into_result()
+            }));
+        }
+
+        let marker = format!("{}{}:", EXIT_CODE_MARKER, new_id());
+        let mut cmd = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            format!("\"$@\"; echo \"{}$?\" >&2", marker),
+            "sh".to_string(),
+        ];
+        cmd.extend(args);
+
+        future::Either::B(self.exec_output(cmd).and_then(move |output| {
+            let mut result = output.into_result();
+            let (stderr_len, exit_code) = match split_exit_code(&result.stderr, &marker) {
+                Some(split) => split,
+                None => return Err(EXIT_CODE_NOT_REPORTED.to_string()),
+            };
+            result.stderr.truncate(stderr_len);
+            result.exit_code = exit_code;
+
+            Ok(result)
+        }))
+    }
+
+    /// Runs script with `/bin/sh -c`; fails when the script exits with non-zero code.
+    fn do_shell(&mut self, script: String) -> impl Future<Item = String, Error = String> {
+        if self.shell == Some(false) {
+            return future::Either::A(future::err(
+                "shell is not available in this image".to_string(),
+            ));
+        }
+
+        future::Either::B(
+            self.do_exec("/bin/sh".into(), vec!["-c".into(), script])
+                .map_err(|e| match e.as_str() {
+                    EXIT_CODE_NOT_REPORTED => "cannot run /bin/sh in the container".to_string(),
+                    _ => e,
+                })
+                .and_then(|result| {
+                    let output = result.stdout + &result.stderr;
+                    match result.exit_code {
+                        0 => Ok(output),
+                        code => Err(format!("script exited with code {}: {}", code, output)),
+                    }
+                }),
+        )
+    }
+
+    /// streams newline terminated lines of container output until the container stops
+    fn do_logs(&mut self) -> impl Stream<Item = LogLine, Error = String> {
+        use async_docker::tty::StreamType;
+
+        let options = {
             use async_docker::models::*;
 
-            ExecConfig::new()
-                .with_attach_stdout(true)
-                .with_attach_stderr(true)
-                .with_cmd(args)
+            LogsOptions::new()
+                .with_follow(true)
+                .with_stdout(true)
+                .with_stderr(true)
         };
+        let mut splitter = LineSplitter::default();
 
         self.container
-            .exec(&cfg)
+            .logs(&options)
             .map_err(|e| format!("{}", e))
-            .fold(String::new(), |mut s, (t, it)| {
-                use std::str;
-
-                match str::from_utf8(it.into_bytes().as_ref()) {
-                    Ok(chunk_str) => s.push_str(chunk_str),
-                    Err(_) => (),
+            .map(move |(t, it)| {
+                let chunk = String::from_utf8_lossy(it.into_bytes().as_ref()).into_owned();
+                let stream = match t {
+                    StreamType::StdErr => LogStream::Stderr,
+                    _ => LogStream::Stdout,
                 };
-
-                Ok::<String, String>(s)
+                stream::iter_ok(splitter.push(stream, &chunk))
             })
+            .flatten()
+    }
+
+    /// Collects output lines of the started container into `logs` until it stops.
+    ///
+    /// Docker sends the output from its beginning, so a line is added only when
+    /// no earlier request (e.g. from before a restart) has added it already.
+    fn follow_logs(&mut self) {
+        let logs = self.logs.clone();
+        let mut seen = 0;
+
+        Arbiter::spawn(
+            self.do_logs()
+                .for_each(move |line| {
+                    let mut logs = logs.borrow_mut();
+                    if seen == logs.len() {
+                        logs.push(line);
+                    }
+                    seen += 1;
+                    Ok(())
+                })
+                .map_err(|e| warn!("cannot follow container output: {}", e)),
+        );
+    }
+
+    /// runs `do_exec` with pid of the process stored, so it can be killed with `kill_child`;
+    /// without a shell in the container the pid is not stored and the command cannot be killed
+    fn do_exec_killable(
+        &mut self,
+        exec_id: &str,
+        executable: String,
+        args: Vec<String>,
+    ) -> impl Future<Item = ExecResult, Error = String> {
+        if self.shell == Some(false) {
+            return self.do_exec(executable, args);
+        }
+
+        let mut wrapper_args = vec![
+            "-c".to_string(),
+            pid_wrapper(exec_id),
+            "sh".to_string(),
+            executable,
+        ];
+        wrapper_args.extend(args);
+
+        self.do_exec("/bin/sh".into(), wrapper_args)
+    }
+
+    /// stores `input` in the container, to be read by `do_exec_with_input`; fails without
+    /// a shell in the container, as the input is redirected by it
+    fn put_input(
+        &mut self,
+        exec_id: &str,
+        input: Vec<u8>,
+    ) -> impl Future<Item = String, Error = String> {
+        if self.shell == Some(false) {
+            return future::Either::B(future::err(
+                "exec input needs a shell, which is not available in this image".to_string(),
+            ));
+        }
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(input.len() as u64);
+        // the file is owned by root, while the command may run as the image user
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        let archive = builder
+            .append_data(&mut header, input_file(exec_id), input.as_slice())
+            .and_then(|_| builder.into_inner());
+
+        match archive {
+            Ok(archive) => future::Either::A(
+                self.put_archive(
+                    Box::new(stream::once(Ok(bytes::Bytes::from(archive)))),
+                    EXEC_INPUT_DIR.into(),
+                )
+                .map(|_| "OK".into()),
+            ),
+            Err(e) => future::Either::B(future::err(format!("cannot store exec input: {}", e))),
+        }
+    }
+
+    /// like `do_exec`, with standard input redirected from the file stored by `put_input`;
+    /// the file is removed once the command finishes
+    fn do_exec_with_input(
+        &mut self,
+        exec_id: &str,
+        executable: String,
+        args: Vec<String>,
+    ) -> impl Future<Item = ExecResult, Error = String> {
+        let input_path = format!("{}/{}", EXEC_INPUT_DIR, input_file(exec_id));
+        let mut wrapper_args = vec![
+            "-c".to_string(),
+            format!("\"$@\" < {0}; code=$?; rm -f {0}; exit $code", input_path),
+            "sh".to_string(),
+            executable,
+        ];
+        wrapper_args.extend(args);
+
+        self.do_exec("/bin/sh".into(), wrapper_args)
+    }
+
+    fn download_stream(
+        url: &str,
+        sha256: Option<String>,
+    ) -> Box<Stream<Item = bytes::Bytes, Error = String>> {
+        match sha256 {
+            Some(sha256) => Box::new(provision::checked_download_stream(url, &sha256)),
+            None => Box::new(provision::download_stream(url)),
+        }
     }
 
     fn do_download(
@@ -111,10 +773,8 @@ impl DockerSession {
         url: String,
         file_path: String,
         format: ResourceFormat,
+        sha256: Option<String>,
     ) -> impl Future<Item = String, Error = String> {
-        use futures::sync::mpsc;
-        use std::io;
-
         let mut untar_path = PathBuf::from(file_path.clone());
 
         let non_dir = self
@@ -137,13 +797,31 @@ impl DockerSession {
                 Box::new(
                     non_dir
                         .and_then(|_| name.ok_or("Invalid filename".to_string()))
-                        .map(move |filename| {
-                            provision::tarred_download_stream(url.as_str(), filename)
+                        .map(move |filename| -> Box<Stream<Item = _, Error = _>> {
+                            match sha256 {
+                                Some(sha256) => {
+                                    Box::new(provision::checked_tarred_download_stream(
+                                        url.as_str(),
+                                        &sha256,
+                                        filename,
+                                    ))
+                                }
+                                None => Box::new(provision::tarred_download_stream(
+                                    url.as_str(),
+                                    filename,
+                                )),
+                            }
                         })
                         .flatten_stream(),
                 )
             }
-            ResourceFormat::Tar => Box::new(provision::download_stream(url.as_str())),
+            ResourceFormat::Tar => Self::download_stream(&url, sha256),
+            ResourceFormat::TarGz => Box::new(provision::gunzip_stream(Self::download_stream(
+                &url, sha256,
+            ))),
+            ResourceFormat::Zip => Box::new(provision::zip_to_tar_stream(Self::download_stream(
+                &url, sha256,
+            ))),
         };
 
         let untar_path = match untar_path.to_str() {
@@ -153,8 +831,20 @@ impl DockerSession {
             }
         };
 
+        future::Either::B(self.put_archive(stream, untar_path).map(|_| "OK".into()))
+    }
+
+    /// unpacks tar `stream` into `remote_path` directory of the container
+    fn put_archive(
+        &mut self,
+        stream: Box<Stream<Item = bytes::Bytes, Error = String>>,
+        remote_path: String,
+    ) -> impl Future<Item = (), Error = String> {
+        use futures::sync::mpsc;
+        use std::io;
+
         let opts = async_docker::build::ContainerArchivePutOptions::builder()
-            .remote_path(untar_path)
+            .remote_path(remote_path)
             .build();
 
         let (send, recv) = mpsc::channel(16);
@@ -173,7 +863,141 @@ impl DockerSession {
             .send_all(stream)
             .and_then(|(mut sink, _)| sink.close());
 
-        future::Either::B(send_fut.join(recv_fut).map(|_| "OK".into()))
+        send_fut.join(recv_fut).map(|_| ())
+    }
+
+    /// creates directory by unpacking archive with a single directory entry,
+    /// so no shell is needed in the image; `path` is relative to the container root
+    fn do_mkdir(&mut self, path: PathBuf) -> impl Future<Item = String, Error = String> {
+        use std::io;
+
+        let archive = path
+            .to_str()
+            .ok_or_else(|| "Invalid unicode in filepath".to_string())
+            .and_then(|path_str| {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                header.set_mode(0o755);
+
+                let mut builder = tar::Builder::new(Vec::new());
+                builder
+                    .append_data(&mut header, path_str.trim_left_matches('/'), io::empty())
+                    .and_then(|()| builder.into_inner())
+                    .map_err(|e| Error::IoError(e.to_string()).to_string())
+            });
+        let archive = match archive {
+            Ok(archive) => archive,
+            Err(e) => return future::Either::A(future::err(e)),
+        };
+
+        future::Either::B(
+            self.put_archive(
+                Box::new(stream::once(Ok(bytes::Bytes::from(archive)))),
+                "/".into(),
+            )
+            .map_err(|e| Error::IoError(e).to_string())
+            .map(move |()| format!("{:?} created", path)),
+        )
+    }
+
+    /// lists the directory from entry headers of its archive, so no shell is needed in the image;
+    /// `path` is relative to the container root
+    fn do_list_dir(&mut self, path: PathBuf) -> impl Future<Item = String, Error = String> {
+        use tar_async::decode::full;
+
+        let path_str = match path.to_str() {
+            Some(path_str) => path_str.to_owned(),
+            None => {
+                return future::Either::A(future::err("Invalid unicode in filepath".to_string()));
+            }
+        };
+        let archive = self
+            .container
+            .archive_get(path_str.as_str())
+            .map_err(|e| Error::IoError(e.to_string()).to_string());
+
+        future::Either::B(
+            full::decode_tar(archive)
+                .map_err(|e| format!("tar: {}", e))
+                .and_then(|entry| {
+                    let header = entry.header();
+                    let path = header.path().map_err(|e| format!("tar: {}", e))?;
+                    let file_entry = FileEntry {
+                        name: path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        size: header.size().unwrap_or(0),
+                        is_dir: header.entry_type().is_dir(),
+                        mode: header.mode().unwrap_or(0) & 0o7777,
+                    };
+                    Ok((path.into_owned(), file_entry))
+                })
+                .collect()
+                .and_then(dir_entries)
+                .and_then(|entries| serde_json::to_string(&entries).map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Runs `rm` on the path, without a shell, as docker archive API has no way
+    /// to delete entries; returns output of `rm`. Its exit code is not known in
+    /// images without a shell, so the result is checked with `check_removed`.
+    fn do_remove_path(
+        &mut self,
+        path: &Path,
+        recursive: bool,
+    ) -> impl Future<Item = String, Error = String> {
+        let path_str = match path.to_str() {
+            Some(path_str) => path_str.to_owned(),
+            None => {
+                return future::Either::A(future::err("Invalid unicode in filepath".to_string()));
+            }
+        };
+        let flags = if recursive { "-rf" } else { "-f" };
+        let cmd = vec!["rm".into(), flags.into(), "--".into(), path_str];
+
+        future::Either::B(
+            self.exec_output(cmd)
+                .map_err(|e| Error::IoError(e).to_string())
+                .map(|output| {
+                    let result = output.into_result();
+                    (result.stderr + &result.stdout).trim().to_string()
+                }),
+        )
+    }
+
+    /// fails when the path is still there after `do_remove_path`, e.g. when the image has no `rm`
+    fn check_removed(
+        &mut self,
+        path: PathBuf,
+        rm_output: String,
+    ) -> impl Future<Item = String, Error = String> {
+        let path_str = match path.to_str() {
+            Some(path_str) => path_str.to_owned(),
+            None => {
+                return future::Either::A(future::err("Invalid unicode in filepath".to_string()));
+            }
+        };
+
+        future::Either::B(
+            self.container
+                .file_info(path_str.as_str())
+                .map_err(|e| Error::IoError(e.to_string()).to_string())
+                .and_then(move |info| match info {
+                    None => Ok(format!("{:?} removed", path)),
+                    Some(_) if rm_output.is_empty() => Err(Error::IoError(format!(
+                        "cannot remove {:?}: rm is not available in the image",
+                        path
+                    ))
+                    .to_string()),
+                    Some(_) => Err(Error::IoError(format!(
+                        "cannot remove {:?}: {}",
+                        path, rm_output
+                    ))
+                    .to_string()),
+                }),
+        )
     }
 
     fn do_upload(
@@ -193,6 +1017,8 @@ impl DockerSession {
         let data: Box<Stream<Item = bytes::Bytes, Error = String>> = match format {
             ResourceFormat::Raw => Box::new(provision::untar_single_file_stream(data)),
             ResourceFormat::Tar => Box::new(data),
+            ResourceFormat::TarGz => Box::new(provision::gzip_stream(data)),
+            ResourceFormat::Zip => Box::new(provision::tar_to_zip_stream(data)),
         };
 
         let data = data.map_err(|x| ErrorInternalServerError(x));
@@ -210,6 +1036,36 @@ impl DockerSession {
     }
 }
 
+/// prefix of the marker the shell wrapper of `do_exec` reports exit code with
+const EXIT_CODE_MARKER: &str = "gu-exit-code-";
+/// error of an exec which did not run the shell wrapper, e.g. without `/bin/sh` in the image
+const EXIT_CODE_NOT_REPORTED: &str = "command exit code not reported";
+/// exit code reported for execs run without a shell, as for killed hd processes
+const EXIT_CODE_UNKNOWN: i32 = -1;
+/// default of `CreateOptions::stop_timeout`
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maps a POSIX signal number onto the name passed to `kill -s` in the container.
+///
+/// Children are not signalled with the docker kill endpoint, which only reaches
+/// the main process of the container.
+fn signal_name(signal: i32) -> Option<&'static str> {
+    Some(match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        6 => "SIGABRT",
+        9 => "SIGKILL",
+        10 => "SIGUSR1",
+        12 => "SIGUSR2",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        18 => "SIGCONT",
+        19 => "SIGSTOP",
+        _ => return None,
+    })
+}
+
 impl IntoDeployInfo for DockerSession {
     fn convert(&self, id: &String) -> PeerSessionInfo {
         PeerSessionInfo {
@@ -218,18 +1074,42 @@ impl IntoDeployInfo for DockerSession {
             status: self.status.clone(),
             tags: self.workspace.tags(),
             note: None,
-            processes: HashSet::new(),
+            processes: self
+                .children
+                .iter()
+                .filter(|(_, child)| child.finished.peek().is_none())
+                .map(|(id, _)| id.clone())
+                .collect(),
+            disk_bytes: self.workspace.disk_usage(),
+            image_url: self.workspace.image_url().map(ToOwned::to_owned),
         }
     }
 }
 
 impl Destroy for DockerSession {
+    /// stops the container, so its processes get `stop_timeout` to exit, then force
+    /// removes it; the workspace is cleared only when the container is gone
     fn destroy(&mut self) -> Box<Future<Item = (), Error = Error>> {
         let workspace = self.workspace.clone();
+        let stop = self
+            .container
+            .stop(Some(self.stop_timeout))
+            .then(|r| -> Result<(), Error> {
+                // container may be already stopped or gone; removal decides
+                if let Err(e) = r {
+                    debug!("stop before destroy: {}", e);
+                }
+                Ok(())
+            });
+        let remove = self.container.remove(
+            &async_docker::build::RmContainerOptions::builder()
+                .force(true)
+                .build(),
+        );
+
         Box::new(
-            self.container
-                .delete()
-                .then(|x| {
+            stop.and_then(|()| remove.then(Ok))
+                .and_then(|x| {
                     if x.is_ok() {
                         return Ok(());
                     }
@@ -258,8 +1138,14 @@ impl DockerMan {
     fn container_config(
         image: String,
         host_config: async_docker::models::HostConfig,
-    ) -> ContainerConfig {
-        ContainerConfig::new()
+        options: &CreateOptions,
+    ) -> Result<ContainerConfig, Error> {
+        let extra_hosts = Self::extra_hosts(&options.extra_hosts)?;
+        let host_config = match extra_hosts.is_empty() {
+            true => host_config,
+            false => host_config.with_extra_hosts(extra_hosts),
+        };
+        let mut config = ContainerConfig::new()
             .with_image(image.into())
             .with_tty(true)
             .with_open_stdin(true)
@@ -272,17 +1158,259 @@ impl DockerMan {
                     .into_iter()
                     .collect(),
             )
-            .with_host_config(host_config)
+            .with_host_config(host_config);
+
+        let env = Self::env_vars(&options.env)?;
+        if !env.is_empty() {
+            config = config.with_env(env);
+        }
+        if let Some(ref working_dir) = options.working_dir {
+            config = config.with_working_dir(working_dir.clone());
+        }
+        if let Some(ref entrypoint) = options.entrypoint {
+            config = config.with_entrypoint(entrypoint.clone());
+        }
+
+        Ok(config)
+    }
+
+    fn env_vars(env: &[(String, String)]) -> Result<Vec<String>, Error> {
+        env.iter()
+            .map(|(key, value)| match key.contains('=') {
+                true => Err(Error::Error(format!(
+                    "invalid environment variable name {:?}: must not contain '='",
+                    key
+                ))),
+                false => Ok(format!("{}={}", key, value)),
+            })
+            .collect()
+    }
+
+    /// `host:ip` entries for `HostConfig.ExtraHosts`
+    fn extra_hosts(hosts: &[(String, String)]) -> Result<Vec<String>, Error> {
+        hosts
+            .iter()
+            .map(|(host, ip)| {
+                let host = host.trim();
+                if host.is_empty() || host.contains(|c: char| c == ':' || c.is_whitespace()) {
+                    return Err(Error::IncorrectOptions(format!(
+                        "invalid extra host name {:?}",
+                        host
+                    )));
+                }
+                ip.trim()
+                    .parse::<IpAddr>()
+                    .map(|ip| format!("{}:{}", host, ip))
+                    .map_err(|_| {
+                        Error::IncorrectOptions(format!(
+                            "invalid IP address {:?} of extra host {}",
+                            ip, host
+                        ))
+                    })
+            })
+            .collect()
+    }
+
+    fn pull_config(url: String, auth: Option<&RegistryAuth>) -> async_docker::build::PullOptions {
+        let mut builder = async_docker::build::PullOptions::builder();
+        builder.image(url);
+        if let Some(auth) = auth {
+            builder.auth(Self::registry_auth_header(auth));
+        }
+        builder.build()
+    }
+
+    /// `X-Registry-Auth` header value: base64url encoded JSON with the credentials
+    fn registry_auth_header(auth: &RegistryAuth) -> String {
+        let credentials = json!({
+            "username": auth.username,
+            "password": auth.password,
+            "serveraddress": auth.server,
+        });
+        base64::encode_config(credentials.to_string().as_bytes(), base64::URL_SAFE)
+    }
+
+    /// tells rejected credentials apart from other pull failures
+    fn pull_error(url: &str, auth: Option<&RegistryAuth>, message: String) -> Error {
+        let lower = message.to_lowercase();
+        let rejected = lower.contains("unauthorized")
+            || lower.contains("authentication required")
+            || lower.contains("denied");
+
+        match (auth, rejected) {
+            (Some(auth), true) => Error::IncorrectOptions(format!(
+                "registry {} rejected credentials of {:?} for {}: {}",
+                auth.server, auth.username, url, message
+            )),
+            (None, true) => Error::IncorrectOptions(format!(
+                "registry requires authentication for {}: {}",
+                url, message
+            )),
+            (_, false) => Error::IoError(format!("image pull error: {}", message)),
+        }
+    }
+
+    /// Checks pulled image against the declared hash: either the image id
+    /// or one of its repo digests has to match. Empty hash is not checked.
+    fn check_image_digest(
+        url: &str,
+        hash: &str,
+        id: &str,
+        repo_digests: &[String],
+    ) -> Result<(), Error> {
+        if hash.is_empty() {
+            return Ok(());
+        }
+        let expected = match hash.contains(':') {
+            true => hash.to_lowercase(),
+            false => format!("sha256:{}", hash.to_lowercase()),
+        };
+        let digest_matches = |digest: &str| {
+            digest
+                .rsplit('@')
+                .next()
+                .map(|digest| digest.to_lowercase() == expected)
+                .unwrap_or(false)
+        };
+
+        if digest_matches(id) || repo_digests.iter().any(|digest| digest_matches(digest)) {
+            Ok(())
+        } else {
+            Err(Error::Error(format!(
+                "image {} does not match declared hash {}: id {}, digests {:?}",
+                url, hash, id, repo_digests
+            )))
+        }
+    }
+
+    /// checks that volumes bind a host directory to an absolute container path
+    fn check_volumes(volumes: &[VolumeDef]) -> Result<(), Error> {
+        for vol in volumes {
+            match (vol.source_dir(), vol.target_dir()) {
+                (Some(src), Some(target)) if !src.is_empty() && target.starts_with('/') => (),
+                (src, target) => {
+                    return Err(Error::IncorrectOptions(format!(
+                        "invalid volume binding {:?} -> {:?}",
+                        src, target
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// checks that bound host directories lie under one of the allowed prefixes;
+    /// symlinks are resolved first, so sources that do not exist are rejected
+    fn check_volume_sources(volumes: &[VolumeDef], allowed: &[PathBuf]) -> Result<(), Error> {
+        let allowed: Vec<PathBuf> = allowed
+            .iter()
+            .map(|prefix| prefix.canonicalize().unwrap_or_else(|_| prefix.clone()))
+            .collect();
+        for src in volumes.iter().filter_map(VolumeDef::source_dir) {
+            let path = Path::new(src);
+            let permitted = path.is_absolute()
+                && !path.components().any(|c| c == Component::ParentDir)
+                && path
+                    .canonicalize()
+                    .map(|path| allowed.iter().any(|prefix| path.starts_with(prefix)))
+                    .unwrap_or(false);
+            if !permitted {
+                warn!("rejected volume source {}", src);
+                return Err(Error::Error("volume path not permitted".into()));
+            }
+        }
+        Ok(())
+    }
+
+    /// checks that resource limits, when given, are positive
+    fn check_limits(options: &CreateOptions) -> Result<(), Error> {
+        if options.memory_bytes == Some(0) {
+            return Err(Error::IncorrectOptions(
+                "memory limit must be greater than zero".into(),
+            ));
+        }
+        match options.cpu_quota {
+            Some(cpu_quota) if cpu_quota <= 0.0 || !cpu_quota.is_finite() => {
+                Err(Error::IncorrectOptions(format!(
+                    "invalid cpu quota {}: must be positive",
+                    cpu_quota
+                )))
+            }
+            Some(cpu_quota) if nano_cpus(cpu_quota, num_cpus::get()) == 0 => Err(
+                Error::IncorrectOptions(format!("cpu quota {} is too small", cpu_quota)),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// checks that network mode, when given, names something; whether a named
+    /// network exists is checked by docker on container creation
+    fn check_network_mode(options: &CreateOptions) -> Result<(), Error> {
+        match options.network_mode {
+            Some(ref network_mode) if network_mode.trim().is_empty() => Err(
+                Error::IncorrectOptions("network mode must not be empty".into()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// sessions run, including ones still pulling their image, against the configured limit
+    fn capacity(&self) -> SessionCapacity {
+        SessionCapacity {
+            active_sessions: self.deploys.count() + self.pending.len(),
+            max_sessions: self.config.max_concurrent_sessions,
+        }
+    }
+
+    fn check_capacity(&self) -> Result<(), Error> {
+        let capacity = self.capacity();
+        match (capacity.max_sessions, capacity.available()) {
+            (Some(max_sessions), Some(0)) => Err(Error::CapacityExceeded(max_sessions)),
+            _ => Ok(()),
+        }
+    }
+
+    /// tells missing network apart from other container creation failures
+    fn create_error(network_mode: Option<&str>, message: String) -> Error {
+        let lower = message.to_lowercase();
+        match network_mode {
+            Some(network_mode) if lower.contains("network") && lower.contains("not found") => {
+                Error::IncorrectOptions(format!(
+                    "docker network {:?} not found: {}",
+                    network_mode, message
+                ))
+            }
+            _ => Error::IoError(message),
+        }
     }
 
-    fn pull_config(url: String) -> async_docker::build::PullOptions {
-        async_docker::build::PullOptions::builder()
-            .image(url)
-            .build()
+    fn host_config(
+        binds: Vec<String>,
+        options: &CreateOptions,
+    ) -> async_docker::models::HostConfig {
+        let mut host_config = async_docker::models::HostConfig::new().with_binds(binds);
+        if let Some(memory_bytes) = options.memory_bytes {
+            host_config = host_config.with_memory(memory_bytes as i64);
+        }
+        if let Some(cpu_quota) = options.cpu_quota {
+            host_config = host_config.with_nano_cp_us(nano_cpus(cpu_quota, num_cpus::get()));
+        }
+        // binds (including the workspace) are mounted read-write regardless
+        if options.read_only_rootfs {
+            host_config = host_config.with_readonly_rootfs(true);
+        }
+        if !options.cap_drop.is_empty() {
+            host_config = host_config.with_cap_drop(options.cap_drop.clone());
+        }
+        if let Some(ref network_mode) = options.network_mode {
+            host_config = host_config.with_network_mode(network_mode.clone());
+        }
+        host_config
     }
 
     fn binds_and_workspace(&self, msg: &CreateSession<CreateOptions>) -> (Vec<String>, Workspace) {
         let mut workspace = self.workspaces_man.workspace();
+        workspace.set_image_url(msg.image.url.clone());
         let binds = msg
             .options
             .volumes
@@ -305,10 +1433,40 @@ impl Actor for DockerMan {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut <Self as Actor>::Context) {
+        ctx.wait(
+            ConfigManager::from_registry()
+                .send(GetConfig::new())
+                .flatten_fut()
+                .into_actor(self)
+                .then(|config: Result<Arc<DockerConfig>, _>, act, _| {
+                    match config {
+                        Ok(config) => {
+                            if let Some(workspaces_man) = config.workspaces_manager() {
+                                act.workspaces_man = workspaces_man;
+                            }
+                            act.config = config
+                        }
+                        Err(e) => error!("docker config: {}", e),
+                    }
+                    fut::ok(())
+                }),
+        );
+
         match new_docker(None) {
             Ok(docker_api) => {
                 self.docker_api = Some(docker_api);
-                envman::register("docker", ctx.address())
+                envman::register("docker", ctx.address());
+
+                ctx.run_interval(Duration::from_secs(10), |act, _| {
+                    for session in act.deploys.values_mut() {
+                        session.remove_finished()
+                    }
+                });
+                ctx.run_interval(DISK_USAGE_REFRESH_INTERVAL, |act, _| {
+                    for session in act.deploys.values_mut() {
+                        session.workspace.refresh_disk_usage()
+                    }
+                });
             }
             Err(e) => {
                 error!("docker start failed: {}", e);
@@ -328,7 +1486,7 @@ impl Handler<CreateSession<CreateOptions>> for DockerMan {
     fn handle(
         &mut self,
         msg: CreateSession<CreateOptions>,
-        _ctx: &mut Self::Context,
+        ctx: &mut Self::Context,
     ) -> <Self as Handler<CreateSession<CreateOptions>>>::Result {
         debug!("create session for: {}", &msg.image.url);
 
@@ -336,32 +1494,112 @@ impl Handler<CreateSession<CreateOptions>> for DockerMan {
             Some(ref api) => {
                 let Image { url, hash } = msg.image.clone();
 
+                if let Err(e) = Self::check_volumes(&msg.options.volumes) {
+                    return ActorResponse::reply(Err(e));
+                }
+                let allowed = match self.config.allowed_volume_sources {
+                    Some(ref allowed) => allowed.clone(),
+                    None => vec![self.workspaces_man.path().to_owned()],
+                };
+                if let Err(e) = Self::check_volume_sources(&msg.options.volumes, &allowed) {
+                    return ActorResponse::reply(Err(e));
+                }
+                if let Err(e) = Self::check_limits(&msg.options) {
+                    return ActorResponse::reply(Err(e));
+                }
+                if let Err(e) = Self::check_network_mode(&msg.options) {
+                    return ActorResponse::reply(Err(e));
+                }
+                if let Err(e) = self.check_capacity() {
+                    return ActorResponse::reply(Err(e));
+                }
                 let (binds, workspace) = self.binds_and_workspace(&msg);
+                let host_config = Self::host_config(binds, &msg.options);
+
+                let opts = match Self::container_config(url.clone(), host_config, &msg.options) {
+                    Ok(opts) => opts,
+                    Err(e) => return ActorResponse::reply(Err(e)),
+                };
+                info!("config: {:?}", &opts);
 
+                if msg.dry_run {
+                    return ActorResponse::reply(Ok(new_id()));
+                }
                 workspace
                     .create_dirs()
                     .expect("Creating session dirs failed");
-                let host_config = async_docker::models::HostConfig::new().with_binds(binds);
-
-                let opts = Self::container_config(url.clone(), host_config);
-                info!("config: {:?}", &opts);
 
-                let pull_image_fut = api.images().pull(&Self::pull_config(url));
+                let registry_auth = msg.options.registry_auth.clone();
+                let network_mode = msg.options.network_mode.clone();
+                let stop_timeout = msg
+                    .options
+                    .stop_timeout
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_STOP_TIMEOUT);
+                let pull_image_fut = api
+                    .images()
+                    .pull(&Self::pull_config(url.clone(), registry_auth.as_ref()));
+                let inspect_image_fut = api.image(Cow::from(url.clone())).inspect();
                 let create_container_fut = api.containers().create(&opts);
 
+                let pending_id = generate_new_id(&self.pending);
+                self.pending.insert(
+                    pending_id.clone(),
+                    PendingPull {
+                        name: msg.name.clone(),
+                        tags: msg.tags.clone(),
+                        image_url: url.clone(),
+                        layers: HashMap::new(),
+                    },
+                );
+                let address = ctx.address();
+                let progress_id = pending_id.clone();
+                let pull_url = url.clone();
+
                 let pull_and_create = pull_image_fut
-                    .for_each(|x| Ok(debug!("{:?}", x)))
-                    .and_then(|_| create_container_fut)
-                    .map(|c| c.id().to_owned())
-                    .map_err(|e| Error::IoError(format!("{}", e)));
+                    .for_each(move |x| {
+                        debug!("{:?}", x);
+                        Ok(address.do_send(PullProgress {
+                            pending_id: progress_id.clone(),
+                            event: x,
+                        }))
+                    })
+                    .map_err(move |e| {
+                        Self::pull_error(&pull_url, registry_auth.as_ref(), e.to_string())
+                    })
+                    .and_then(|_| inspect_image_fut.map_err(|e| Error::IoError(format!("{}", e))))
+                    .and_then(move |image| {
+                        let repo_digests = image.repo_digests().cloned().unwrap_or_default();
+                        Self::check_image_digest(&url, &hash, image.id(), &repo_digests)
+                    })
+                    .and_then(move |_| {
+                        create_container_fut.map_err(move |e| {
+                            Self::create_error(
+                                network_mode.as_ref().map(String::as_str),
+                                e.to_string(),
+                            )
+                        })
+                    })
+                    .map(|c| c.id().to_owned());
+
+                ActorResponse::r#async(fut::wrap_future(pull_and_create).then(
+                    move |res, act: &mut DockerMan, _| {
+                        act.pending.remove(&pending_id);
+                        let id = match res {
+                            Ok(id) => id,
+                            Err(e) => return fut::err(e),
+                        };
 
-                ActorResponse::r#async(fut::wrap_future(pull_and_create).and_then(
-                    move |id, act: &mut DockerMan, _| {
                         if let Some(ref api) = act.docker_api {
                             let deploy = DockerSession {
                                 workspace,
                                 container: api.container(Cow::from(id.clone())),
                                 status: PeerSessionStatus::CREATED,
+                                children: HashMap::new(),
+                                env: BTreeMap::new(),
+                                stop_timeout,
+                                shell: None,
+                                logs: Rc::new(RefCell::new(Vec::new())),
                             };
                             act.deploys.insert_deploy(id.clone(), deploy);
                             fut::ok(id)
@@ -393,6 +1631,276 @@ impl DockerMan {
 
         Box::new(fut::wrap_future(f(deployment)))
     }
+
+    /// like `run_for_deployment`, but probes the container for a shell first,
+    /// so `f` can run execs in images without one
+    fn run_with_shell_probe<F, R>(
+        &mut self,
+        deployment_id: String,
+        f: F,
+    ) -> Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>>
+    where
+        F: FnOnce(&mut DockerSession) -> R + 'static,
+        R: Future<Item = String, Error = String> + 'static,
+    {
+        let probe = match self.deploys.deploy_mut(&deployment_id) {
+            Ok(deployment) => match deployment.shell {
+                Some(_) => None,
+                None => Some(deployment.probe_shell()),
+            },
+            Err(e) => return Box::new(fut::err(format!("{}", e))),
+        };
+        let probe = match probe {
+            Some(probe) => probe,
+            None => return self.run_for_deployment(deployment_id, f),
+        };
+
+        Box::new(
+            fut::wrap_future(probe).and_then(move |shell, act: &mut DockerMan, _| {
+                if !shell {
+                    info!(
+                        "no shell in {}; exit codes will not be reported",
+                        deployment_id
+                    );
+                }
+                if let Ok(deployment) = act.deploys.deploy_mut(&deployment_id) {
+                    deployment.shell = Some(shell);
+                }
+                act.run_for_deployment(deployment_id, f)
+            }),
+        )
+    }
+
+    /// like `run_for_deployment`, but moves the deployment to `status` once `f` succeeds
+    fn run_with_status<F, R>(
+        &mut self,
+        deployment_id: String,
+        status: PeerSessionStatus,
+        f: F,
+    ) -> Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>>
+    where
+        F: FnOnce(&mut DockerSession) -> R,
+        R: Future<Item = String, Error = String> + 'static,
+    {
+        Box::new(self.run_for_deployment(deployment_id.clone(), f).and_then(
+            move |result, act: &mut DockerMan, _| {
+                if let Ok(deployment) = act.deploys.deploy_mut(&deployment_id) {
+                    deployment.status = status;
+                }
+                fut::ok(result)
+            },
+        ))
+    }
+
+    /// like `run_with_status`, but runs `f` only when the container is running or,
+    /// when `running` is not set, stopped
+    fn run_with_container_state<F, R>(
+        &mut self,
+        deployment_id: String,
+        running: bool,
+        status: PeerSessionStatus,
+        f: F,
+    ) -> Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>>
+    where
+        F: FnOnce(&mut DockerSession) -> R + 'static,
+        R: Future<Item = String, Error = String> + 'static,
+    {
+        let run_id = deployment_id.clone();
+        Box::new(
+            self.run_for_deployment(deployment_id, move |deployment| {
+                deployment.check_container_running(running)
+            })
+            .and_then(move |_, act: &mut DockerMan, _| act.run_with_status(run_id, status, f)),
+        )
+    }
+
+    /// starts collecting output of the just started container of the deployment
+    fn follow_logs(&mut self, deployment_id: &String) {
+        if let Ok(deployment) = self.deploys.deploy_mut(deployment_id) {
+            deployment.follow_logs();
+        }
+    }
+}
+
+/// Cpu quota in docker NanoCPUs; docker rejects quotas above the cpu count of the host,
+/// so those are capped at it.
+fn nano_cpus(cpu_quota: f64, host_cpus: usize) -> i64 {
+    (cpu_quota.min(host_cpus as f64) * 1e9) as i64
+}
+
+/// Result of `Command::WaitFor` for given exit code of the main process.
+fn check_exit_code(code: i32, expected_exit: Option<i32>, strict: bool) -> Result<String, String> {
+    match expected_exit {
+        Some(expected) if code != expected => Err(format!(
+            "process exited with code {}, expected {}",
+            code, expected
+        )),
+        None if strict && code != 0 => Err(format!("process exited with code {}", code)),
+        _ => Ok(code.to_string()),
+    }
+}
+
+/// Finds exit code reported by the shell wrapper of `do_exec` after `marker` at the end
+/// of `stderr`; returns it with the length of the output preceding the marker.
+fn split_exit_code(stderr: &str, marker: &str) -> Option<(usize, i32)> {
+    let start = stderr.rfind(marker)?;
+    let exit_code = stderr[start + marker.len()..].trim_end().parse().ok()?;
+    Some((start, exit_code))
+}
+
+/// Raw output of an exec, collected from the frames of the attached stream.
+#[derive(Default)]
+struct ExecOutput {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl ExecOutput {
+    fn push(mut self, stream: async_docker::tty::StreamType, chunk: &[u8]) -> Self {
+        match stream {
+            async_docker::tty::StreamType::StdErr => self.stderr.extend_from_slice(chunk),
+            _ => self.stdout.extend_from_slice(chunk),
+        }
+        self
+    }
+
+    /// Decodes the output; it is done at the end, as multi-byte characters may be
+    /// split between frames. Invalid sequences become replacement characters.
+    fn into_result(self) -> ExecResult {
+        ExecResult {
+            stdout: String::from_utf8_lossy(&self.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&self.stderr).into_owned(),
+            exit_code: 0,
+        }
+    }
+}
+
+/// Runs `Command::CopyFile`: the source archive is unpacked into a scratch
+/// directory of the target container and then moved into place with `mv`,
+/// because archive entries keep the name of the source file.
+fn copy_file(
+    docker_man: &mut DockerMan,
+    session_id: String,
+    from_session: String,
+    from_path: PathBuf,
+    to_path: PathBuf,
+) -> Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>> {
+    let (from_path, to_path) = match (from_path.to_str(), to_path.to_str()) {
+        (Some(from_path), Some(to_path)) => (from_path.to_owned(), to_path.to_owned()),
+        _ => return Box::new(fut::err("Invalid unicode in filepath".to_string())),
+    };
+    let name = match Path::new(&from_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+    {
+        Some(name) => name.to_owned(),
+        None => return Box::new(fut::err("Invalid filename".to_string())),
+    };
+
+    let from_session = from_session.trim_left_matches("docker::");
+    let archive: Box<Stream<Item = bytes::Bytes, Error = String>> =
+        match docker_man.deploys.deploy_mut(from_session) {
+            Ok(source) => Box::new(
+                source
+                    .container
+                    .archive_get(from_path.as_str())
+                    .map_err(|e| e.to_string()),
+            ),
+            Err(e) => return Box::new(fut::err(e.to_string())),
+        };
+
+    let scratch_dir = format!("/tmp/gu-copy-{}", new_id());
+    let (scratch_path, target) = (format!("{}/{}", scratch_dir, name), to_path.clone());
+    let (put_id, move_id, cleanup_id) =
+        (session_id.clone(), session_id.clone(), session_id.clone());
+
+    Box::new(
+        docker_man
+            .run_for_deployment(session_id, |deployment| {
+                deployment.do_mkdir(PathBuf::from(&scratch_dir))
+            })
+            .and_then(move |_, act: &mut DockerMan, _| {
+                let dir = scratch_dir.clone();
+                act.run_for_deployment(put_id, |deployment| {
+                    deployment.put_archive(archive, dir).map(|_| String::new())
+                })
+                .map(move |_, _, _| scratch_dir)
+            })
+            .and_then(move |scratch_dir, act: &mut DockerMan, _| {
+                act.run_for_deployment(move_id, |deployment| {
+                    deployment.run_checked("mv".into(), vec!["-f".into(), scratch_path, target])
+                })
+                .map(move |_, _, _| scratch_dir)
+            })
+            .and_then(move |scratch_dir, act: &mut DockerMan, _| {
+                act.run_for_deployment(cleanup_id, |deployment| {
+                    deployment.run_checked("rmdir".into(), vec![scratch_dir.clone()])
+                })
+                .then(move |r, _, _| {
+                    if let Err(e) = r {
+                        warn!("cannot remove {}: {}", scratch_dir, e);
+                    }
+                    fut::ok(format!("{} copied to {}", from_path, to_path))
+                })
+            }),
+    )
+}
+
+/// Picks direct children from entries of a directory archive, which start with
+/// the directory itself.
+fn dir_entries(entries: Vec<(PathBuf, FileEntry)>) -> Result<Vec<FileEntry>, String> {
+    match entries.first() {
+        Some((_, dir)) if dir.is_dir => (),
+        _ => return Err(Error::IoError("not a directory".into()).to_string()),
+    }
+    Ok(entries
+        .into_iter()
+        .skip(1)
+        .filter(|(path, _)| path.components().count() == 2)
+        .map(|(_, entry)| entry)
+        .collect())
+}
+
+/// Runs `Command::Exec` which is killed when it does not finish in time.
+fn exec_with_timeout(
+    docker_man: &mut DockerMan,
+    session_id: String,
+    executable: String,
+    args: Vec<String>,
+    timeout: Duration,
+    output_lines: bool,
+) -> Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>> {
+    let exec_id = format!("exec-{}", new_id());
+    let kill_id = exec_id.clone();
+    let started = Instant::now();
+
+    Box::new(
+        docker_man
+            .run_with_shell_probe(session_id.clone(), move |deployment| {
+                deployment
+                    .do_exec_killable(&exec_id, executable, args)
+                    .and_then(move |result| result.to_json(output_lines))
+            })
+            // `None` marks expired timeout
+            .map_err(|e, _, _| Some(e))
+            .timeout(timeout, None)
+            .then(move |result, act: &mut DockerMan, _| match result {
+                Ok(output) => Box::new(fut::ok(output))
+                    as Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>>,
+                Err(Some(e)) => Box::new(fut::err(e)),
+                Err(None) => {
+                    let elapsed = started.elapsed();
+                    act.run_for_deployment(session_id, move |deployment| {
+                        deployment.kill_child(&kill_id, "KILL").then(move |r| {
+                            if let Err(e) = r {
+                                warn!("cannot kill timed out command {}: {}", kill_id, e);
+                            }
+                            Err(format!("command timed out after {:?}", elapsed))
+                        })
+                    })
+                }
+            }),
+    )
 }
 
 fn run_command(
@@ -405,23 +1913,148 @@ fn run_command(
     }
 
     match command {
-        Command::Open => docker_man.run_for_deployment(session_id, DockerSession::do_open),
-        Command::Close => docker_man.run_for_deployment(session_id, DockerSession::do_close),
-        Command::Exec { executable, args } => docker_man
+        Command::Open => {
+            let logs_id = session_id.clone();
+            Box::new(
+                docker_man
+                    .run_with_container_state(
+                        session_id,
+                        false,
+                        PeerSessionStatus::RUNNING,
+                        DockerSession::do_open,
+                    )
+                    .map(move |result, act: &mut DockerMan, _| {
+                        act.follow_logs(&logs_id);
+                        result
+                    }),
+            )
+        }
+        Command::Close => docker_man.run_with_container_state(
+            session_id,
+            true,
+            PeerSessionStatus::CREATED,
+            DockerSession::do_close,
+        ),
+        Command::Exec {
+            executable,
+            args,
+            timeout_secs: None,
+            output_lines,
+        } => docker_man.run_with_shell_probe(session_id, move |deployment| {
+            deployment
+                .do_exec(executable, args)
+                .and_then(move |result| result.to_json(output_lines))
+        }),
+        Command::Exec {
+            executable,
+            args,
+            timeout_secs: Some(timeout_secs),
+            output_lines,
+        } => exec_with_timeout(
+            docker_man,
+            session_id,
+            executable,
+            args,
+            Duration::from_secs(timeout_secs),
+            output_lines,
+        ),
+        Command::ExecWithInput {
+            executable,
+            args,
+            stdin,
+        } => {
+            let exec_id = format!("exec-{}", new_id());
+            Box::new(
+                docker_man
+                    .run_with_shell_probe(session_id.clone(), |deployment| {
+                        deployment.put_input(&exec_id, stdin)
+                    })
+                    .and_then(move |_, act: &mut DockerMan, _| {
+                        act.run_for_deployment(session_id, move |deployment| {
+                            deployment
+                                .do_exec_with_input(&exec_id, executable, args)
+                                .and_then(|result| result.to_json(false))
+                        })
+                    }),
+            )
+        }
+        Command::Shell { script } => {
+            docker_man.run_with_shell_probe(session_id, |deployment| deployment.do_shell(script))
+        }
+        Command::Start { executable, args } => docker_man
+            .run_for_deployment(session_id, |deployment| {
+                deployment.do_start(executable, args)
+            }),
+        Command::Stop { child_id } => {
+            let (deployment_id, stopped_id) = (session_id.clone(), child_id.clone());
+            Box::new(
+                docker_man
+                    .run_for_deployment(session_id, |deployment| deployment.do_stop(child_id))
+                    .map(move |result, act: &mut DockerMan, _| {
+                        if let Ok(deployment) = act.deploys.deploy_mut(&deployment_id) {
+                            deployment.children.remove(&stopped_id);
+                        }
+                        result
+                    }),
+            )
+        }
+        Command::Signal { child_id, signal } => docker_man
             .run_for_deployment(session_id, |deployment| {
-                deployment.do_exec(executable, args)
+                deployment.do_signal(child_id, signal)
             }),
-        Command::Start { executable, args } => {
-            docker_man.run_for_deployment(session_id, DockerSession::do_start)
+        Command::Wait => {
+            // without children the container is waited for, so it is stopped afterwards
+            let waits_for_container = docker_man
+                .deploys
+                .deploy(&session_id)
+                .map(|deployment| deployment.children.is_empty())
+                .unwrap_or(false);
+            match waits_for_container {
+                true => docker_man.run_with_status(
+                    session_id,
+                    PeerSessionStatus::CREATED,
+                    DockerSession::do_wait,
+                ),
+                false => docker_man.run_for_deployment(session_id, DockerSession::do_wait),
+            }
+        }
+        Command::Inspect => docker_man.run_for_deployment(session_id, DockerSession::do_inspect),
+        Command::WaitFor {
+            expected_exit,
+            strict,
+        } => docker_man.run_with_status(session_id, PeerSessionStatus::CREATED, |deployment| {
+            deployment.do_wait_for(expected_exit, strict)
+        }),
+        Command::SetEnv(vars) => Box::new(fut::result(
+            docker_man
+                .deploys
+                .deploy_mut(&session_id)
+                .map_err(|e| e.to_string())
+                .and_then(|session| session.do_set_env(vars)),
+        )),
+        Command::Restart { timeout } => {
+            let restarted_id = session_id.clone();
+            Box::new(
+                docker_man
+                    .run_with_status(session_id, PeerSessionStatus::RUNNING, |deployment| {
+                        deployment.do_restart(timeout)
+                    })
+                    .map(move |result, act: &mut DockerMan, _| {
+                        if let Ok(deployment) = act.deploys.deploy_mut(&restarted_id) {
+                            deployment.children.clear();
+                        }
+                        act.follow_logs(&restarted_id);
+                        result
+                    }),
+            )
         }
-        Command::Stop { child_id } => Box::new(fut::ok("Stop mock".to_string())),
-        Command::Wait => docker_man.run_for_deployment(session_id, DockerSession::do_wait),
         Command::DownloadFile {
             uri,
             file_path,
             format,
+            sha256,
         } => docker_man.run_for_deployment(session_id, |deployment| {
-            deployment.do_download(uri, file_path, format)
+            deployment.do_download(uri, file_path, format, sha256)
         }),
         Command::UploadFile {
             uri,
@@ -430,6 +2063,31 @@ fn run_command(
         } => docker_man.run_for_deployment(session_id, |deployment| {
             deployment.do_upload(uri, file_path, format)
         }),
+        Command::CopyFile {
+            from_session,
+            from_path,
+            to_path,
+        } => copy_file(docker_man, session_id, from_session, from_path, to_path),
+        Command::Mkdir { path } => {
+            docker_man.run_for_deployment(session_id, |deployment| deployment.do_mkdir(path))
+        }
+        Command::ListDir { path } => {
+            docker_man.run_for_deployment(session_id, |deployment| deployment.do_list_dir(path))
+        }
+        Command::RemovePath { path, recursive } => {
+            let check_id = session_id.clone();
+            Box::new(
+                docker_man
+                    .run_for_deployment(session_id, |deployment| {
+                        deployment.do_remove_path(&path, recursive)
+                    })
+                    .and_then(move |rm_output, act: &mut DockerMan, _| {
+                        act.run_for_deployment(check_id, |deployment| {
+                            deployment.check_removed(path, rm_output)
+                        })
+                    }),
+            )
+        }
         Command::AddTags(tags) => Box::new(fut::result(
             docker_man
                 .deploys
@@ -459,29 +2117,45 @@ fn run_command(
     }
 }
 
+/// Runs commands in order; on failure the remaining ones are marked as skipped,
+/// unless `continue_on_error` is set. Fails when any of the commands failed.
 fn run_commands(
     hd_man: &mut DockerMan,
     session_id: String,
     commands: Vec<Command>,
+    continue_on_error: bool,
 ) -> impl ActorFuture<Actor = DockerMan, Item = Vec<String>, Error = Vec<String>> {
-    let f: Box<dyn ActorFuture<Actor = DockerMan, Item = Vec<String>, Error = Vec<String>>> =
-        Box::new(future::ok(Vec::new()).into_actor(hd_man));
-
-    commands.into_iter().fold(f, |acc, command| {
-        let session_id = session_id.clone();
-        Box::new(acc.and_then(|mut vec, act, _ctx| {
-            run_command(act, session_id, command).then(move |i, _, _| match i {
-                Ok(a) => {
-                    vec.push(a);
-                    fut::ok(vec)
-                }
-                Err(a) => {
-                    vec.push(a);
-                    fut::err(vec)
-                }
-            })
-        }))
-    })
+    let f: Box<
+        dyn ActorFuture<Actor = DockerMan, Item = (Vec<String>, bool), Error = Vec<String>>,
+    > = Box::new(future::ok((Vec::new(), false)).into_actor(hd_man));
+
+    commands
+        .into_iter()
+        .enumerate()
+        .fold(f, |acc, (idx, command)| {
+            let session_id = session_id.clone();
+            Box::new(acc.and_then(move |(mut vec, failed), act, _ctx| {
+                let run: Box<ActorFuture<Actor = DockerMan, Item = String, Error = String>> =
+                    match failed && !continue_on_error {
+                        true => Box::new(fut::ok(SKIPPED_COMMAND.to_string())),
+                        false => run_command(act, session_id, command),
+                    };
+                run.then(move |i, _, _| match i {
+                    Ok(a) => {
+                        vec.push(a);
+                        fut::ok((vec, failed))
+                    }
+                    Err(a) => {
+                        vec.push(failed_command(idx, &a));
+                        fut::ok((vec, true))
+                    }
+                })
+            }))
+        })
+        .and_then(|(vec, failed), _, _| match failed {
+            true => fut::err(vec),
+            false => fut::ok(vec),
+        })
 }
 
 impl Handler<SessionUpdate> for DockerMan {
@@ -495,7 +2169,12 @@ impl Handler<SessionUpdate> for DockerMan {
         }
         let session_id = msg.session_id.clone();
 
-        ActorResponse::r#async(run_commands(self, session_id, msg.commands))
+        ActorResponse::r#async(run_commands(
+            self,
+            session_id,
+            msg.commands,
+            msg.continue_on_error,
+        ))
     }
 }
 
@@ -507,7 +2186,10 @@ impl Handler<GetSessions> for DockerMan {
         _msg: GetSessions,
         _ctx: &mut Self::Context,
     ) -> <Self as Handler<GetSessions>>::Result {
-        ActorResponse::reply(Ok(self.deploys.deploys_info()))
+        let mut sessions = self.deploys.deploys_info();
+        sessions.extend(self.pending.iter().map(|(id, pending)| pending.info(id)));
+
+        ActorResponse::reply(Ok(sessions))
     }
 }
 
@@ -533,6 +2215,40 @@ impl Handler<DestroySession> for DockerMan {
     }
 }
 
+impl Handler<SetSessionMetadata> for DockerMan {
+    type Result = Result<PeerSessionInfo, Error>;
+
+    fn handle(&mut self, msg: SetSessionMetadata, _ctx: &mut Self::Context) -> Self::Result {
+        let session = self.deploys.deploy_mut(&msg.session_id)?;
+        if let Some(name) = msg.name {
+            session.workspace.set_name(name);
+        }
+        if let Some(tags) = msg.tags {
+            session.workspace.set_tags(tags);
+        }
+        Ok(session.convert(&msg.session_id))
+    }
+}
+
+impl Handler<envman::GetEnvCapacity> for DockerMan {
+    type Result = MessageResult<envman::GetEnvCapacity>;
+
+    fn handle(&mut self, _msg: envman::GetEnvCapacity, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.capacity())
+    }
+}
+
+impl Handler<GetSessionLogs> for DockerMan {
+    type Result = Result<Vec<LogLine>, Error>;
+
+    fn handle(&mut self, msg: GetSessionLogs, _ctx: &mut Self::Context) -> Self::Result {
+        let deployment = self.deploys.deploy(&msg.session_id)?;
+        let logs = deployment.logs.borrow();
+
+        Ok(logs.iter().skip(msg.offset).cloned().collect())
+    }
+}
+
 struct Init;
 
 impl gu_base::Module for Init {
@@ -546,3 +2262,415 @@ impl gu_base::Module for Init {
 pub fn module() -> impl gu_base::Module {
     Init
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dir_entries() {
+        let entry = |name: &str, is_dir| FileEntry {
+            name: name.into(),
+            is_dir,
+            ..FileEntry::default()
+        };
+        let entries = vec![
+            (PathBuf::from("data/"), entry("data", true)),
+            (PathBuf::from("data/a.txt"), entry("a.txt", false)),
+            (PathBuf::from("data/sub/"), entry("sub", true)),
+            (PathBuf::from("data/sub/b.txt"), entry("b.txt", false)),
+        ];
+
+        assert_eq!(
+            dir_entries(entries).unwrap(),
+            vec![entry("a.txt", false), entry("sub", true)]
+        );
+        assert!(dir_entries(vec![(PathBuf::from("a.txt"), entry("a.txt", false))]).is_err());
+        assert!(dir_entries(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_line_splitter() {
+        let mut splitter = LineSplitter::default();
+
+        assert_eq!(splitter.push(LogStream::Stdout, "ab"), vec![]);
+        assert_eq!(
+            splitter.push(LogStream::Stderr, "err\r\n"),
+            vec![LogLine {
+                stream: LogStream::Stderr,
+                text: "err".into()
+            }]
+        );
+        assert_eq!(
+            splitter.push(LogStream::Stdout, "c\n\nd"),
+            vec![
+                LogLine {
+                    stream: LogStream::Stdout,
+                    text: "abc".into()
+                },
+                LogLine {
+                    stream: LogStream::Stdout,
+                    text: "".into()
+                }
+            ]
+        );
+        assert_eq!(splitter.push(LogStream::Stderr, "e"), vec![]);
+        assert_eq!(
+            splitter.push(LogStream::Stdout, "e\n"),
+            vec![LogLine {
+                stream: LogStream::Stdout,
+                text: "de".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_volumes() {
+        let bind = |src: &str, target: &str| VolumeDef::BindRw {
+            src: src.into(),
+            target: target.into(),
+        };
+
+        assert!(DockerMan::check_volumes(&[bind("/tmp/in", "/in")]).is_ok());
+        assert!(DockerMan::check_volumes(&[bind("/tmp/in", "in")]).is_err());
+        assert!(DockerMan::check_volumes(&[bind("", "/in")]).is_err());
+    }
+
+    #[test]
+    fn test_check_volume_sources() {
+        let bind = |src: &str| VolumeDef::BindRw {
+            src: src.into(),
+            target: "/in".into(),
+        };
+        let dir = std::env::temp_dir().join("gu-provider-volume-sources-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let data = dir.join("data");
+        std::fs::create_dir_all(data.join("in")).unwrap();
+        std::fs::create_dir_all(dir.join("datafoo")).unwrap();
+        let path = |p: &str| dir.join(p).to_str().unwrap().to_owned();
+        let allowed = vec![data.clone()];
+
+        assert!(DockerMan::check_volume_sources(&[], &allowed).is_ok());
+        assert!(DockerMan::check_volume_sources(&[bind(&path("data"))], &allowed).is_ok());
+        assert!(DockerMan::check_volume_sources(&[bind(&path("data/in"))], &allowed).is_ok());
+        assert!(DockerMan::check_volume_sources(&[bind(&path("datafoo"))], &allowed).is_err());
+        assert!(DockerMan::check_volume_sources(&[bind(&path("data/../etc"))], &allowed).is_err());
+        assert!(DockerMan::check_volume_sources(&[bind(&path("data/missing"))], &allowed).is_err());
+        assert!(DockerMan::check_volume_sources(&[bind("data/in")], &allowed).is_err());
+        assert!(
+            DockerMan::check_volume_sources(&[bind(&path("data/in")), bind("/etc")], &allowed)
+                .is_err()
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", data.join("etc")).unwrap();
+            assert!(DockerMan::check_volume_sources(&[bind(&path("data/etc"))], &allowed).is_err());
+        }
+    }
+
+    #[test]
+    fn test_registry_auth() {
+        let auth = RegistryAuth {
+            username: "user".into(),
+            password: "secret".into(),
+            server: "registry.example.com".into(),
+        };
+        let header = DockerMan::registry_auth_header(&auth);
+        let decoded: serde_json::Value =
+            serde_json::from_slice(&base64::decode_config(&header, base64::URL_SAFE).unwrap())
+                .unwrap();
+        assert_eq!(
+            decoded,
+            json!({"username": "user", "password": "secret", "serveraddress": "registry.example.com"})
+        );
+
+        let url = "registry.example.com/app";
+        match DockerMan::pull_error(url, Some(&auth), "unauthorized: access denied".into()) {
+            Error::IncorrectOptions(msg) => assert!(msg.contains("rejected credentials")),
+            e => panic!("unexpected error: {}", e),
+        }
+        match DockerMan::pull_error(url, None, "connection reset".into()) {
+            Error::IoError(_) => (),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_exec_output() {
+        use async_docker::tty::StreamType;
+
+        let snowman = "\u{2603}".as_bytes();
+        let result = ExecOutput::default()
+            .push(StreamType::StdOut, &snowman[..1])
+            .push(StreamType::StdErr, b"err")
+            .push(StreamType::StdOut, &snowman[1..])
+            .push(StreamType::StdOut, &[b'a', 0xff, b'b'])
+            .into_result();
+
+        assert_eq!(result.stdout, "\u{2603}a\u{fffd}b");
+        assert_eq!(result.stderr, "err");
+    }
+
+    #[test]
+    fn test_split_exit_code() {
+        let marker = "gu-exit-code-1234:";
+
+        assert_eq!(
+            split_exit_code("err\ngu-exit-code-1234:3\n", marker),
+            Some((4, 3))
+        );
+        assert_eq!(
+            split_exit_code("errgu-exit-code-1234:0\n", marker),
+            Some((3, 0))
+        );
+        assert_eq!(
+            split_exit_code("gu-exit-code-1234:1\nmore output", marker),
+            None
+        );
+        assert_eq!(split_exit_code("gu-exit-code-5678:0\n", marker), None);
+        assert_eq!(split_exit_code("no marker", marker), None);
+    }
+
+    #[test]
+    fn test_check_exit_code() {
+        assert_eq!(check_exit_code(1, None, false), Ok("1".into()));
+        assert!(check_exit_code(1, None, true).is_err());
+        assert_eq!(check_exit_code(0, None, true), Ok("0".into()));
+        assert_eq!(check_exit_code(3, Some(3), true), Ok("3".into()));
+        assert!(check_exit_code(0, Some(3), false).is_err());
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let limits = |memory_bytes, cpu_quota| CreateOptions {
+            memory_bytes,
+            cpu_quota,
+            ..CreateOptions::default()
+        };
+
+        assert!(DockerMan::check_limits(&limits(None, None)).is_ok());
+        assert!(DockerMan::check_limits(&limits(Some(1 << 30), Some(0.5))).is_ok());
+        assert!(DockerMan::check_limits(&limits(Some(0), None)).is_err());
+        assert!(DockerMan::check_limits(&limits(None, Some(0.0))).is_err());
+        assert!(DockerMan::check_limits(&limits(None, Some(-1.0))).is_err());
+        assert!(DockerMan::check_limits(&limits(None, Some(std::f64::NAN))).is_err());
+        assert!(DockerMan::check_limits(&limits(None, Some(1e-10))).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pid_wrapper() {
+        use std::process::Command;
+
+        let child_id = format!("pid-wrapper-test-{}", std::process::id());
+        let status = Command::new("/bin/sh")
+            .args(&["-c", &pid_wrapper(&child_id), "sh", "/bin/sh", "-c"])
+            .arg(format!(
+                "test \"$(cat {})\" = $$ && exit 3",
+                pid_file(&child_id)
+            ))
+            .status()
+            .unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        assert!(!Path::new(&pid_file(&child_id)).exists());
+    }
+
+    #[test]
+    fn test_nano_cpus() {
+        assert_eq!(nano_cpus(0.5, 4), 500_000_000);
+        assert_eq!(nano_cpus(16.0, 4), 4_000_000_000);
+        assert_eq!(nano_cpus(1e-10, 4), 0);
+    }
+
+    #[test]
+    fn test_check_image_digest() {
+        let digests = vec!["busybox@sha256:abcd".to_string()];
+        assert!(DockerMan::check_image_digest("busybox", "", "sha256:1234", &digests).is_ok());
+        assert!(
+            DockerMan::check_image_digest("busybox", "sha256:1234", "sha256:1234", &digests)
+                .is_ok()
+        );
+        assert!(DockerMan::check_image_digest("busybox", "ABCD", "sha256:1234", &digests).is_ok());
+        match DockerMan::check_image_digest("busybox", "sha256:ffff", "sha256:1234", &digests) {
+            Err(Error::Error(_)) => (),
+            _ => panic!("mismatched image accepted"),
+        }
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let env = vec![("A".to_string(), "1".to_string())];
+        assert_eq!(DockerMan::env_vars(&env).unwrap(), vec!["A=1".to_string()]);
+
+        let env = vec![("A=B".to_string(), "1".to_string())];
+        match DockerMan::env_vars(&env) {
+            Err(Error::Error(_)) => (),
+            _ => panic!("key with '=' accepted"),
+        }
+    }
+
+    #[test]
+    fn test_extra_hosts() {
+        let hosts = vec![
+            ("db".to_string(), "10.0.0.2".to_string()),
+            ("cache".to_string(), " ::1 ".to_string()),
+        ];
+        assert_eq!(
+            DockerMan::extra_hosts(&hosts).unwrap(),
+            vec!["db:10.0.0.2".to_string(), "cache:::1".to_string()]
+        );
+        assert!(DockerMan::extra_hosts(&[]).unwrap().is_empty());
+
+        for (host, ip) in &[("", "10.0.0.2"), ("a b", "10.0.0.2"), ("db", "10.0.0")] {
+            match DockerMan::extra_hosts(&[(host.to_string(), ip.to_string())]) {
+                Err(Error::IncorrectOptions(_)) => (),
+                _ => panic!("invalid extra host {:?} -> {:?} accepted", host, ip),
+            }
+        }
+    }
+
+    #[test]
+    fn test_network_mode() {
+        let network = |network_mode: Option<&str>| CreateOptions {
+            network_mode: network_mode.map(str::to_string),
+            ..CreateOptions::default()
+        };
+        assert!(DockerMan::check_network_mode(&network(None)).is_ok());
+        assert!(DockerMan::check_network_mode(&network(Some("none"))).is_ok());
+        assert!(DockerMan::check_network_mode(&network(Some(" "))).is_err());
+
+        match DockerMan::create_error(Some("jobs"), "network jobs not found".into()) {
+            Error::IncorrectOptions(message) => assert!(message.contains("\"jobs\"")),
+            _ => panic!("missing network not reported"),
+        }
+        match DockerMan::create_error(None, "no space left".into()) {
+            Error::IoError(_) => (),
+            _ => panic!("unexpected error kind"),
+        }
+    }
+
+    #[test]
+    fn test_pull_progress() {
+        let mut pull = PendingPull {
+            name: "test".into(),
+            tags: Vec::new(),
+            image_url: "alpine".into(),
+            layers: HashMap::new(),
+        };
+        pull.update(&json!({"status": "Downloading", "id": "a",
+            "progressDetail": {"current": 10, "total": 100}}));
+        pull.update(&json!({"status": "Downloading", "id": "b",
+            "progressDetail": {"current": 0, "total": 100}}));
+        assert_eq!(pull.percent(), 5);
+
+        pull.update(&json!({"status": "Pull complete", "id": "b", "progressDetail": {}}));
+        assert_eq!(pull.percent(), 55);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_exec_in_working_dir() {
+        // requires running docker daemon with `alpine` image available
+        System::run(|| {
+            let api = new_docker(None).unwrap();
+            let opts = DockerMan::container_config(
+                "alpine".into(),
+                async_docker::models::HostConfig::new(),
+                &CreateOptions {
+                    working_dir: Some("/tmp".into()),
+                    ..CreateOptions::default()
+                },
+            )
+            .unwrap();
+            let workspace = WorkspacesManager::new(&ConfigModule::new(), "docker-test")
+                .unwrap()
+                .workspace();
+
+            Arbiter::spawn(
+                api.containers()
+                    .create(&opts)
+                    .map_err(|e| panic!("create container: {}", e))
+                    .and_then(move |c| {
+                        let mut session = DockerSession {
+                            workspace,
+                            container: api.container(Cow::from(c.id().to_owned())),
+                            status: PeerSessionStatus::CREATED,
+                            children: HashMap::new(),
+                            env: BTreeMap::new(),
+                            stop_timeout: DEFAULT_STOP_TIMEOUT,
+                            shell: None,
+                            logs: Rc::new(RefCell::new(Vec::new())),
+                        };
+
+                        session
+                            .do_open()
+                            .and_then(move |_| {
+                                session
+                                    .do_exec("pwd".into(), Vec::new())
+                                    .and_then(move |output| {
+                                        assert_eq!(output.stdout.trim(), "/tmp");
+                                        session.destroy().map_err(|e| e.to_string())
+                                    })
+                            })
+                            .map_err(|e| panic!("error: {}", e))
+                    })
+                    .then(|_| Ok(System::current().stop())),
+            )
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_stop_completes_wait() {
+        // requires running docker daemon with `alpine` image available
+        System::run(|| {
+            let api = new_docker(None).unwrap();
+            let opts = DockerMan::container_config(
+                "alpine".into(),
+                async_docker::models::HostConfig::new(),
+                &CreateOptions::default(),
+            )
+            .unwrap();
+            let workspace = WorkspacesManager::new(&ConfigModule::new(), "docker-test")
+                .unwrap()
+                .workspace();
+
+            Arbiter::spawn(
+                api.containers()
+                    .create(&opts)
+                    .map_err(|e| panic!("create container: {}", e))
+                    .and_then(move |c| {
+                        let mut session = DockerSession {
+                            workspace,
+                            container: api.container(Cow::from(c.id().to_owned())),
+                            status: PeerSessionStatus::CREATED,
+                            children: HashMap::new(),
+                            env: BTreeMap::new(),
+                            stop_timeout: DEFAULT_STOP_TIMEOUT,
+                            shell: None,
+                            logs: Rc::new(RefCell::new(Vec::new())),
+                        };
+
+                        session
+                            .do_open()
+                            .and_then(move |_| {
+                                session
+                                    .do_start("sleep".into(), vec!["600".into()])
+                                    .and_then(move |child_id| {
+                                        let wait = session.do_wait();
+                                        session.do_stop(child_id).join(wait).and_then(move |_| {
+                                            session.remove_finished();
+                                            assert!(session.children.is_empty());
+                                            session.destroy().map_err(|e| e.to_string())
+                                        })
+                                    })
+                            })
+                            .map_err(|e| panic!("error: {}", e))
+                    })
+                    .then(|_| Ok(System::current().stop())),
+            )
+        });
+    }
+}
@@ -2,11 +2,13 @@
 //!
 
 use actix::prelude::*;
-use futures::{future, prelude::*};
+use futures::{future, prelude::*, sync::oneshot};
 use gu_actix::prelude::*;
+use gu_hardware::actor::{HardwareActor, HardwareQuery};
 use gu_model::envman::*;
 use gu_net::rpc::peer::PeerSessionInfo;
 use gu_net::rpc::{PublicMessage, RemotingContext, RemotingSystemService};
+use log::error;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value as JsonValue;
 use std::borrow::Cow;
@@ -19,6 +21,11 @@ struct EnvMan {
     session_update_map: BTreeMap<String, Recipient<SessionUpdate>>,
     get_sessions_map: BTreeMap<String, Recipient<GetSessions>>,
     destroy_session_map: BTreeMap<String, Recipient<DestroySession>>,
+    session_logs_map: BTreeMap<String, Recipient<GetSessionLogs>>,
+    set_metadata_map: BTreeMap<String, Recipient<SetSessionMetadata>>,
+    capacity_map: BTreeMap<String, Recipient<GetEnvCapacity>>,
+    /// cancel triggers of updates in progress, by session id
+    updates_in_progress: BTreeMap<String, Vec<oneshot::Sender<()>>>,
 }
 
 impl Actor for EnvMan {
@@ -29,11 +36,24 @@ impl Actor for EnvMan {
         ctx.bind::<SessionUpdate>(SessionUpdate::ID);
         ctx.bind::<GetSessions>(GetSessions::ID);
         ctx.bind::<DestroySession>(DestroySession::ID);
+        ctx.bind::<CancelUpdate>(CancelUpdate::ID);
+        ctx.bind::<GetSessionLogs>(GetSessionLogs::ID);
+        ctx.bind::<GetCapabilities>(GetCapabilities::ID);
+        ctx.bind::<SetSessionMetadata>(SetSessionMetadata::ID);
+        ctx.bind::<GetCapacity>(GetCapacity::ID);
+        ctx.bind::<GetResources>(GetResources::ID);
     }
 }
 
 impl RemotingSystemService for EnvMan {}
 
+/// Asks a single environment for its session capacity.
+pub struct GetEnvCapacity;
+
+impl Message for GetEnvCapacity {
+    type Result = SessionCapacity;
+}
+
 pub trait EnvManService {
     type CreateOptions: Serialize + DeserializeOwned + Default + Send;
 }
@@ -56,6 +76,7 @@ impl<T: EnvManService + 'static> CreateSender for CreateRecipient<T> {
                         tags: msg.tags,
                         note: msg.note,
                         options,
+                        dry_run: msg.dry_run,
                     })
                     .flatten_fut(),
             ),
@@ -86,11 +107,17 @@ where
     T: Handler<CreateSession<Options>>
         + Handler<SessionUpdate>
         + Handler<GetSessions>
-        + Handler<DestroySession>,
+        + Handler<DestroySession>
+        + Handler<GetSessionLogs>
+        + Handler<SetSessionMetadata>
+        + Handler<GetEnvCapacity>,
     T::Context: actix::dev::ToEnvelope<T, CreateSession<T::CreateOptions>>,
     T::Context: actix::dev::ToEnvelope<T, SessionUpdate>,
     T::Context: actix::dev::ToEnvelope<T, GetSessions>,
     T::Context: actix::dev::ToEnvelope<T, DestroySession>,
+    T::Context: actix::dev::ToEnvelope<T, GetSessionLogs>,
+    T::Context: actix::dev::ToEnvelope<T, SetSessionMetadata>,
+    T::Context: actix::dev::ToEnvelope<T, GetEnvCapacity>,
 {
     type Result = ();
 
@@ -109,7 +136,12 @@ where
         self.get_sessions_map
             .insert(env_type.clone(), msg.address.clone().recipient());
         self.destroy_session_map
-            .insert(env_type, msg.address.recipient());
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.session_logs_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.set_metadata_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.capacity_map.insert(env_type, msg.address.recipient());
     }
 }
 
@@ -125,11 +157,17 @@ impl Handler<CreateSession<JsonValue>> for EnvMan {
 
     fn handle(&mut self, msg: CreateSession<JsonValue>, _ctx: &mut Self::Context) -> Self::Result {
         let env_type = msg.env_type.clone();
+        // dry run ids must not resolve to any environment
+        let prefix = if msg.dry_run {
+            DRY_RUN_SESSION_PREFIX.to_string()
+        } else {
+            env_type.clone()
+        };
         if let Some(address) = self.create_map.get(&env_type) {
             return ActorResponse::r#async(
                 address
                     .send(msg)
-                    .and_then(move |session_id| Ok(format!("{}::{}", env_type, session_id)))
+                    .and_then(move |session_id| Ok(format!("{}::{}", prefix, session_id)))
                     .into_actor(self),
             );
         }
@@ -148,18 +186,119 @@ impl Handler<SessionUpdate> for EnvMan {
             }
         };
 
-        match self.session_update_map.get(prefix) {
-            Some(r) => ActorResponse::r#async(
-                r.send(SessionUpdate {
-                    session_id: session_id.into(),
-                    commands: msg.commands,
+        let r = match self.session_update_map.get(prefix) {
+            Some(r) => r,
+            None => return ActorResponse::reply(Err(Vec::new())),
+        };
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let updates = self
+            .updates_in_progress
+            .entry(msg.session_id.clone())
+            .or_insert_with(Vec::new);
+        updates.retain(|update| !update.is_canceled());
+        updates.push(cancel_tx);
+
+        ActorResponse::r#async(
+            r.send(SessionUpdate {
+                session_id: session_id.into(),
+                commands: msg.commands,
+                continue_on_error: msg.continue_on_error,
+            })
+            .map_err(|_e| Vec::new())
+            .flatten_fut()
+            .select2(cancel_rx)
+            .then(|res| match res {
+                Ok(future::Either::A((results, _))) => Ok(results),
+                Err(future::Either::A((e, _))) => Err(e),
+                Ok(future::Either::B(_)) | Err(future::Either::B(_)) => {
+                    Err(vec!["update cancelled".to_string()])
+                }
+            })
+            .into_actor(self),
+        )
+    }
+}
+
+impl Handler<CancelUpdate> for EnvMan {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: CancelUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        let _ = extract_prefix(&msg.session_id)?;
+
+        Ok(self
+            .updates_in_progress
+            .remove(&msg.session_id)
+            .map(|updates| {
+                updates
+                    .into_iter()
+                    .filter(|update| update.send(()).is_ok())
+                    .count()
+            })
+            .unwrap_or(0))
+    }
+}
+
+impl Handler<GetCapabilities> for EnvMan {
+    type Result = Result<Vec<String>, ()>;
+
+    fn handle(&mut self, _msg: GetCapabilities, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.create_map.keys().cloned().collect())
+    }
+}
+
+impl EnvMan {
+    fn capacity(&self) -> impl Future<Item = BTreeMap<String, SessionCapacity>, Error = ()> {
+        future::join_all(
+            self.capacity_map
+                .iter()
+                .map(|(k, v)| {
+                    let env_type = k.to_owned();
+
+                    v.send(GetEnvCapacity)
+                        .map_err(|_| ())
+                        .and_then(|capacity| Ok((env_type, capacity)))
+                })
+                .collect::<Vec<_>>(),
+        )
+        .and_then(|v| Ok(v.into_iter().collect()))
+    }
+}
+
+impl Handler<GetCapacity> for EnvMan {
+    type Result = ActorResponse<EnvMan, BTreeMap<String, SessionCapacity>, ()>;
+
+    fn handle(&mut self, _msg: GetCapacity, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(self.capacity().into_actor(self))
+    }
+}
+
+impl Handler<GetResources> for EnvMan {
+    type Result = ActorResponse<EnvMan, ResourceAvailability, ()>;
+
+    fn handle(&mut self, _msg: GetResources, _ctx: &mut Self::Context) -> Self::Result {
+        let hardware = HardwareActor::from_registry()
+            .send(HardwareQuery)
+            .map_err(|e| e.to_string())
+            .flatten_fut()
+            .map_err(|e| error!("cannot read host stats: {}", e));
+
+        ActorResponse::r#async(
+            hardware
+                .join(self.capacity())
+                .and_then(|(hardware, capacity)| {
+                    let free_sessions = capacity
+                        .values()
+                        .filter_map(SessionCapacity::available)
+                        .fold(None, |sum, available| Some(sum.unwrap_or(0) + available));
+                    Ok(ResourceAvailability {
+                        cpu_cores: hardware.num_cores(),
+                        free_memory_bytes: hardware.free_memory_bytes().unwrap_or(0),
+                        free_sessions,
+                    })
                 })
-                .map_err(|_e| Vec::new())
-                .flatten_fut()
                 .into_actor(self),
-            ),
-            None => ActorResponse::reply(Err(Vec::new())),
-        }
+        )
     }
 }
 
@@ -229,6 +368,61 @@ impl Handler<DestroySession> for EnvMan {
     }
 }
 
+impl Handler<GetSessionLogs> for EnvMan {
+    type Result = ActorResponse<EnvMan, Vec<LogLine>, Error>;
+
+    fn handle(&mut self, msg: GetSessionLogs, _ctx: &mut Self::Context) -> Self::Result {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+
+        match self.session_logs_map.get(prefix) {
+            Some(address) => ActorResponse::r#async(
+                address
+                    .send(GetSessionLogs {
+                        session_id: session_id.into(),
+                        offset: msg.offset,
+                    })
+                    .flatten_fut()
+                    .into_actor(self),
+            ),
+            None => ActorResponse::reply(Err(Error::UnknownEnv(prefix.into()))),
+        }
+    }
+}
+
+impl Handler<SetSessionMetadata> for EnvMan {
+    type Result = ActorResponse<EnvMan, PeerSessionInfo, Error>;
+
+    fn handle(&mut self, msg: SetSessionMetadata, _ctx: &mut Self::Context) -> Self::Result {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+
+        match self.set_metadata_map.get(prefix) {
+            Some(address) => {
+                let prefix = prefix.to_owned();
+                ActorResponse::r#async(
+                    address
+                        .send(SetSessionMetadata {
+                            session_id: session_id.into(),
+                            ..msg
+                        })
+                        .flatten_fut()
+                        .map(move |session| PeerSessionInfo {
+                            id: format!("{}::{}", prefix, session.id),
+                            ..session
+                        })
+                        .into_actor(self),
+                )
+            }
+            None => ActorResponse::reply(Err(Error::UnknownEnv(prefix.into()))),
+        }
+    }
+}
+
 pub fn register<A, IntoCowStr, Options>(env_type: IntoCowStr, address: Addr<A>)
 where
     IntoCowStr: Into<Cow<'static, str>>,
@@ -237,11 +431,17 @@ where
     A: Handler<CreateSession<Options>>
         + Handler<SessionUpdate>
         + Handler<GetSessions>
-        + Handler<DestroySession>,
+        + Handler<DestroySession>
+        + Handler<GetSessionLogs>
+        + Handler<SetSessionMetadata>
+        + Handler<GetEnvCapacity>,
     A::Context: actix::dev::ToEnvelope<A, CreateSession<A::CreateOptions>>,
     A::Context: actix::dev::ToEnvelope<A, SessionUpdate>,
     A::Context: actix::dev::ToEnvelope<A, GetSessions>,
     A::Context: actix::dev::ToEnvelope<A, DestroySession>,
+    A::Context: actix::dev::ToEnvelope<A, GetSessionLogs>,
+    A::Context: actix::dev::ToEnvelope<A, SetSessionMetadata>,
+    A::Context: actix::dev::ToEnvelope<A, GetEnvCapacity>,
 {
     EnvMan::from_registry().do_send(Register {
         env_type: env_type.into(),
@@ -4,7 +4,7 @@
 
 */
 use super::id::generate_new_id;
-use super::provision::{download, download_step, untgz, upload_step};
+use super::provision::{checked_download_step, download, download_step, untgz, upload_step};
 use super::{
     envman, status,
     sync_exec::{Exec, ExecResult, SyncExecManager},
@@ -15,6 +15,7 @@ use actix_web::client;
 use actix_web::error::ErrorInternalServerError;
 use futures::future;
 use futures::prelude::*;
+use futures::sync::oneshot;
 use gu_actix::prelude::*;
 use gu_base::files::read_async;
 use gu_model::envman::*;
@@ -23,16 +24,16 @@ use gu_net::rpc::{
     *,
 };
 use gu_persist::config::ConfigModule;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde_derive::*;
 
-use super::workspace::{Workspace, WorkspacesManager};
+use super::workspace::{Workspace, WorkspacesManager, DISK_USAGE_REFRESH_INTERVAL};
 use gu_hdman::image_manager;
 use std::collections::hash_map::{Entry, OccupiedEntry};
 use std::collections::HashSet;
 use std::path::Path;
 use std::sync::Arc;
-use std::{collections::HashMap, fs, path::PathBuf, process, result, time};
+use std::{collections::HashMap, fs, io, path::PathBuf, process, result, time};
 
 impl IntoDeployInfo for HdSessionInfo {
     fn convert(&self, id: &String) -> PeerSessionInfo {
@@ -43,6 +44,8 @@ impl IntoDeployInfo for HdSessionInfo {
             tags: self.workspace.tags(),
             note: self.note.clone(),
             processes: self.processes.keys().cloned().collect(),
+            disk_bytes: self.workspace.disk_usage(),
+            image_url: self.workspace.image_url().map(ToOwned::to_owned),
         }
     }
 }
@@ -89,6 +92,11 @@ impl Actor for HdMan {
         ctx.run_interval(time::Duration::from_secs(10), |act, _| {
             act.scan_for_processes()
         });
+        ctx.run_interval(DISK_USAGE_REFRESH_INTERVAL, |act, _| {
+            for session in act.deploys.values_mut() {
+                session.workspace.refresh_disk_usage()
+            }
+        });
     }
 }
 
@@ -164,6 +172,7 @@ impl HdMan {
             if some_finished & sess_info.processes.is_empty() {
                 sess_info.status = PeerSessionStatus::CONFIGURED;
             }
+            sess_info.notify_waiters();
         }
     }
 }
@@ -176,9 +185,19 @@ struct HdSessionInfo {
     dirty: bool,
     note: Option<String>,
     processes: HashMap<String, process::Child>,
+    /// `Command::Wait` callers, notified when no child process is left
+    waiters: Vec<oneshot::Sender<()>>,
 }
 
 impl HdSessionInfo {
+    fn notify_waiters(&mut self) {
+        if self.processes.is_empty() {
+            for waiter in self.waiters.drain(..) {
+                let _ = waiter.send(());
+            }
+        }
+    }
+
     fn insert_process(&mut self, child: process::Child) -> String {
         let id = generate_new_id(&self.processes);
         self.processes.insert(id.clone(), child);
@@ -219,10 +238,15 @@ impl Handler<CreateSession> for HdMan {
             Err(e) => return ActorResponse::reply(Err(Error::IncorrectOptions(format!("{}", e)))),
         };
 
+        if msg.dry_run {
+            return ActorResponse::reply(Ok(session_id));
+        }
+
         let cache_path = self.get_cache_path(&image_file_name);
 
         let mut workspace = self.workspaces_man.workspace();
         workspace.add_tags(msg.tags);
+        workspace.set_image_url(msg.image.url.clone());
         match workspace.create_dirs() {
             Ok(_) => (),
             Err(e) => return ActorResponse::reply(Err(e.into())),
@@ -235,6 +259,7 @@ impl Handler<CreateSession> for HdMan {
             dirty: false,
             note: msg.note,
             processes: HashMap::new(),
+            waiters: Vec::new(),
         };
 
         self.deploys.insert_deploy(session_id.clone(), session);
@@ -280,29 +305,58 @@ fn run_command(
     match command {
         Command::Open => Box::new(fut::ok("Open mock".to_string())),
         Command::Close => Box::new(fut::ok("Close mock".to_string())),
-        Command::Exec { executable, args } => {
+        Command::Exec {
+            executable,
+            args,
+            timeout_secs,
+            output_lines,
+        } => {
             let executable = session.get_session_exec_path(&executable);
             let session_id = session_id.clone();
             let session_dir = session.workspace.path().to_owned();
+            let log_id = session_id.clone();
+            let started = time::Instant::now();
 
             info!("executing sync: {} {:?}", executable, args);
+            // `None` marks expired timeout
+            let exec = fut::wrap_future(
+                SyncExecManager::from_registry()
+                    .send(Exec::Run {
+                        executable,
+                        args,
+                        cwd: session_dir.clone(),
+                    })
+                    .flatten_fut()
+                    .map_err(move |e| Some(e.to_string())),
+            );
+            let exec: Box<ActorFuture<Actor = HdMan, Item = _, Error = Option<String>>> =
+                match timeout_secs {
+                    Some(timeout_secs) => {
+                        Box::new(exec.timeout(time::Duration::from_secs(timeout_secs), None))
+                    }
+                    None => Box::new(exec),
+                };
             Box::new(
-                fut::wrap_future(
-                    SyncExecManager::from_registry()
-                        .send(Exec::Run {
-                            executable,
-                            args,
-                            cwd: session_dir.clone(),
-                        })
-                        .flatten_fut()
-                        .map_err(move |e| e.to_string()),
-                )
+                exec.map_err(move |e, _, _| {
+                    e.unwrap_or_else(|| {
+                        // sync processes cannot be killed, so it is left to finish on its own
+                        warn!("command in session {} timed out", log_id);
+                        format!("command timed out after {:?}", started.elapsed())
+                    })
+                })
                 .and_then(move |res, act: &mut HdMan, _ctx| {
                     info!("sync cmd result: {:?}", res);
-                    let result = if let ExecResult::Run(output) = res {
-                        String::from_utf8_lossy(&output.stdout).to_string()
-                    } else {
-                        "".to_string()
+                    let result = match res {
+                        ExecResult::Run(output) => gu_model::envman::ExecResult {
+                            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                            exit_code: output.status.code().unwrap_or(-1),
+                        },
+                        _ => gu_model::envman::ExecResult::default(),
+                    };
+                    let result = match result.to_json(output_lines) {
+                        Ok(result) => result,
+                        Err(e) => return fut::err(e),
                     };
 
                     match act.get_session_mut(&session_id) {
@@ -361,6 +415,7 @@ fn run_command(
                                     if session.processes.is_empty() {
                                         session.status = PeerSessionStatus::CONFIGURED;
                                     };
+                                    session.notify_waiters();
                                     fut::ok(output)
                                 }
                                 Err(e) => fut::err(e.to_string()),
@@ -369,14 +424,76 @@ fn run_command(
                 }),
             )
         }
-        Command::Wait => Box::new(fut::ok("Wait mock".to_string())),
+        Command::Signal { child_id, signal } => {
+            info!("sending signal {} to: {:?}", signal, &child_id);
+
+            Box::new(fut::result(
+                session
+                    .processes
+                    .get(&child_id)
+                    .ok_or(Error::NoSuchChild(child_id.clone()))
+                    .and_then(|child| send_signal(child, signal).map_err(From::from))
+                    .map(|_| format!("signal {} sent to {}", signal, child_id))
+                    .map_err(|e| e.to_string()),
+            ))
+        }
+        Command::Wait => match session.processes.is_empty() {
+            true => Box::new(fut::ok("no running processes".to_string())),
+            false => {
+                let (tx, rx) = oneshot::channel();
+                session.waiters.push(tx);
+                Box::new(fut::wrap_future(
+                    rx.map(|()| "all processes finished".to_string())
+                        .map_err(|_| "session destroyed while waiting".to_string()),
+                ))
+            }
+        },
+        Command::Inspect => {
+            let result = InspectResult {
+                state: match session.processes.is_empty() {
+                    true => "idle".into(),
+                    false => "running".into(),
+                },
+                processes: session
+                    .processes
+                    .iter()
+                    .map(|(id, child)| (id.clone(), child.id()))
+                    .collect(),
+                ..Default::default()
+            };
+            Box::new(fut::result(
+                serde_json::to_string(&result).map_err(|e| e.to_string()),
+            ))
+        }
+        Command::SetEnv(_) => Box::new(fut::err(
+            Error::IncorrectOptions("set env is not supported in hd environment".into())
+                .to_string(),
+        )),
+        Command::ExecWithInput { .. } => Box::new(fut::err(
+            Error::IncorrectOptions("exec with input is not supported in hd environment".into())
+                .to_string(),
+        )),
+        Command::Shell { .. } => Box::new(fut::err(
+            Error::IncorrectOptions("shell is not supported in hd environment".into()).to_string(),
+        )),
+        Command::WaitFor { .. } => Box::new(fut::err(
+            Error::IncorrectOptions("wait for exit code is not supported in hd environment".into())
+                .to_string(),
+        )),
+        Command::Restart { .. } => Box::new(fut::err(
+            Error::IncorrectOptions("restart is not supported in hd environment".into())
+                .to_string(),
+        )),
         Command::DownloadFile {
             uri,
             file_path,
             format,
+            sha256,
         } => {
             let path = session.workspace.path().join(file_path);
-            Box::new(fut::wrap_future(handle_download_file(uri, path, format)))
+            Box::new(fut::wrap_future(handle_download_file(
+                uri, path, format, sha256,
+            )))
         }
         Command::UploadFile {
             uri,
@@ -386,6 +503,73 @@ fn run_command(
             let path = session.workspace.path().join(file_path);
             Box::new(fut::wrap_future(handle_upload_file(uri, path, format)))
         }
+        Command::CopyFile {
+            from_session,
+            from_path,
+            to_path,
+        } => {
+            let to_path = match session.workspace.resolve(to_path) {
+                Ok(path) => path,
+                Err(e) => return Box::new(fut::err(Error::IoError(e.to_string()).to_string())),
+            };
+            let from_path = match hd_man.get_session_mut(from_session.trim_left_matches("hd::")) {
+                Ok(source) => match source.workspace.resolve(from_path) {
+                    Ok(path) => path,
+                    Err(e) => return Box::new(fut::err(Error::IoError(e.to_string()).to_string())),
+                },
+                Err(e) => return Box::new(fut::err(e.to_string())),
+            };
+
+            Box::new(fut::result(
+                fs::copy(&from_path, &to_path)
+                    .map(|_| format!("{:?} copied to {:?}", from_path, to_path))
+                    .map_err(|e| e.to_string()),
+            ))
+        }
+        Command::Mkdir { path } => {
+            let path = match session.workspace.resolve(path) {
+                Ok(path) => path,
+                Err(e) => return Box::new(fut::err(Error::IoError(e.to_string()).to_string())),
+            };
+            Box::new(fut::result(
+                fs::create_dir_all(&path)
+                    .map(|()| format!("{:?} created", path))
+                    .map_err(|e| Error::IoError(e.to_string()).to_string()),
+            ))
+        }
+        Command::ListDir { path } => {
+            let path = match session.workspace.resolve(path) {
+                Ok(path) => path,
+                Err(e) => return Box::new(fut::err(Error::IoError(e.to_string()).to_string())),
+            };
+            Box::new(fut::result(
+                list_dir(&path)
+                    .map_err(|e| Error::IoError(e.to_string()).to_string())
+                    .and_then(|entries| serde_json::to_string(&entries).map_err(|e| e.to_string())),
+            ))
+        }
+        Command::RemovePath { path, recursive } => {
+            let path = match session.workspace.resolve(path) {
+                Ok(ref path) if path == session.workspace.path() => {
+                    return Box::new(fut::err(
+                        Error::IoError("cannot remove the workspace itself".into()).to_string(),
+                    ));
+                }
+                Ok(path) => path,
+                Err(e) => return Box::new(fut::err(Error::IoError(e.to_string()).to_string())),
+            };
+            let result = match fs::symlink_metadata(&path) {
+                Ok(ref metadata) if metadata.is_dir() && recursive => fs::remove_dir_all(&path),
+                Ok(ref metadata) if metadata.is_dir() => fs::remove_dir(&path),
+                Ok(_) => fs::remove_file(&path),
+                Err(e) => Err(e),
+            };
+            Box::new(fut::result(
+                result
+                    .map(|()| format!("{:?} removed", path))
+                    .map_err(|e| Error::IoError(e.to_string()).to_string()),
+            ))
+        }
         Command::AddTags(tags) => Box::new({
             session.workspace.add_tags(tags);
             fut::ok(format!(
@@ -403,34 +587,83 @@ fn run_command(
     }
 }
 
+/// Direct children of the directory, sorted by name.
+fn list_dir(path: &Path) -> io::Result<Vec<FileEntry>> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            Ok(FileEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size: metadata.len(),
+                is_dir: metadata.is_dir(),
+                mode: file_mode(&metadata),
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+/// Runs commands in order; on failure the remaining ones are marked as skipped,
+/// unless `continue_on_error` is set. Fails when any of the commands failed.
 fn run_commands(
     hd_man: &mut HdMan,
     session_id: String,
     commands: Vec<Command>,
+    continue_on_error: bool,
 ) -> impl ActorFuture<Actor = HdMan, Item = Vec<String>, Error = Vec<String>> {
-    let f: Box<dyn ActorFuture<Actor = HdMan, Item = Vec<String>, Error = Vec<String>>> =
-        Box::new(future::ok(Vec::new()).into_actor(hd_man));
-
-    commands.into_iter().fold(f, |acc, command| {
-        let session_id = session_id.clone();
-        Box::new(acc.and_then(|mut vec, act, _ctx| {
-            run_command(act, session_id, command).then(move |i, _, _| match i {
-                Ok(a) => {
-                    vec.push(a);
-                    fut::ok(vec)
-                }
-                Err(a) => {
-                    vec.push(a);
-                    fut::err(vec)
-                }
-            })
-        }))
-    })
+    let f: Box<dyn ActorFuture<Actor = HdMan, Item = (Vec<String>, bool), Error = Vec<String>>> =
+        Box::new(future::ok((Vec::new(), false)).into_actor(hd_man));
+
+    commands
+        .into_iter()
+        .enumerate()
+        .fold(f, |acc, (idx, command)| {
+            let session_id = session_id.clone();
+            Box::new(acc.and_then(move |(mut vec, failed), act, _ctx| {
+                let run: Box<ActorFuture<Actor = HdMan, Item = String, Error = String>> =
+                    match failed && !continue_on_error {
+                        true => Box::new(fut::ok(SKIPPED_COMMAND.to_string())),
+                        false => run_command(act, session_id, command),
+                    };
+                run.then(move |i, _, _| match i {
+                    Ok(a) => {
+                        vec.push(a);
+                        fut::ok((vec, failed))
+                    }
+                    Err(a) => {
+                        vec.push(failed_command(idx, &a));
+                        fut::ok((vec, true))
+                    }
+                })
+            }))
+        })
+        .and_then(|(vec, failed), _, _| match failed {
+            true => fut::err(vec),
+            false => fut::ok(vec),
+        })
 }
 
 impl Handler<SessionUpdate> for HdMan {
     /// ok: succeeded cmds output
-    /// err: all succeeded cmds output till first failure, plus failed cmd err msg
+    /// err: cmds output, with failed cmds err msgs and skipped cmds marked
     type Result = ActorResponse<HdMan, Vec<String>, Vec<String>>;
 
     fn handle(&mut self, msg: SessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
@@ -441,18 +674,48 @@ impl Handler<SessionUpdate> for HdMan {
         }
         let session_id = msg.session_id.clone();
 
-        ActorResponse::r#async(run_commands(self, session_id, msg.commands))
+        ActorResponse::r#async(run_commands(
+            self,
+            session_id,
+            msg.commands,
+            msg.continue_on_error,
+        ))
     }
 }
 
+#[cfg(unix)]
+fn send_signal(child: &process::Child, signal: i32) -> io::Result<()> {
+    match unsafe { libc::kill(child.id() as libc::pid_t, signal) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn send_signal(_child: &process::Child, _signal: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "signals are not supported on this platform",
+    ))
+}
+
 fn handle_download_file(
     url: String,
     file_path: PathBuf,
     format: ResourceFormat,
+    sha256: Option<String>,
 ) -> impl Future<Item = String, Error = String> {
-    download_step(url.as_ref(), file_path, format)
-        .and_then(move |_| Ok(format!("{:?} file downloaded", url)))
-        .map_err(|e| e.to_string())
+    match sha256 {
+        Some(sha256) => future::Either::A(checked_download_step(
+            url.as_ref(),
+            file_path,
+            format,
+            &sha256,
+        )),
+        None => future::Either::B(download_step(url.as_ref(), file_path, format)),
+    }
+    .and_then(move |_| Ok(format!("{:?} file downloaded", url)))
+    .map_err(|e| e.to_string())
 }
 
 fn handle_upload_file(
@@ -494,6 +757,41 @@ impl Handler<DestroySession> for HdMan {
     }
 }
 
+impl Handler<SetSessionMetadata> for HdMan {
+    type Result = Result<PeerSessionInfo, Error>;
+
+    fn handle(&mut self, msg: SetSessionMetadata, _ctx: &mut Self::Context) -> Self::Result {
+        let session = self.get_session_mut(&msg.session_id)?;
+        if let Some(name) = msg.name {
+            session.workspace.set_name(name);
+        }
+        if let Some(tags) = msg.tags {
+            session.workspace.set_tags(tags);
+        }
+        Ok(session.convert(&msg.session_id))
+    }
+}
+
+impl Handler<envman::GetEnvCapacity> for HdMan {
+    type Result = MessageResult<envman::GetEnvCapacity>;
+
+    fn handle(&mut self, _msg: envman::GetEnvCapacity, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(SessionCapacity {
+            active_sessions: self.deploys.count(),
+            max_sessions: None,
+        })
+    }
+}
+
+impl Handler<GetSessionLogs> for HdMan {
+    type Result = Result<Vec<LogLine>, Error>;
+
+    fn handle(&mut self, msg: GetSessionLogs, _ctx: &mut Self::Context) -> Self::Result {
+        let _ = self.deploys.deploy_mut(&msg.session_id)?;
+        Err(Error::Error("hd sessions do not keep logs".into()))
+    }
+}
+
 impl Handler<status::GetEnvStatus> for HdMan {
     type Result = MessageResult<status::GetEnvStatus>;
 
@@ -5,81 +5,208 @@ use actix_web::HttpMessage;
 use futures::{future, prelude::*};
 use gu_actix::{async_result, async_try, prelude::*};
 use gu_base::files::read_async;
+pub use gu_base::files::{gunzip_stream, gzip_stream};
 use gu_base::files::{untgz_async, write_async};
 use gu_model::envman::ResourceFormat;
 use log::{debug, error, info};
 use std::{
-    fs,
+    fs, io,
     path::{Path, PathBuf},
     time,
 };
 
+/// Number of fetches made by `checked_download` before giving up.
+pub const DOWNLOAD_ATTEMPTS: usize = 3;
+
 pub fn download_step(
     url: &str,
     output_path: PathBuf,
     format: ResourceFormat,
 ) -> impl Future<Item = (), Error = String> {
     use actix_web::client;
-    use tar_async::decode::full;
 
     let client_request = async_try!(client::ClientRequest::get(url)
         .finish()
         .map_err(|e| format!("{}", e)));
 
+    future::Either::A(
+        client_request
+            .send()
+            .map_err(|e| format!("send download request: {}", e))
+            .and_then(move |resp| {
+                save_step(
+                    resp.payload().map_err(|e| e.to_string()),
+                    output_path,
+                    format,
+                )
+            }),
+    )
+}
+
+/// Like `download_step`, but nothing is stored until the content matches
+/// the given SHA-256 checksum (see `checked_download`).
+pub fn checked_download_step(
+    url: &str,
+    output_path: PathBuf,
+    format: ResourceFormat,
+    sha256: &str,
+) -> impl Future<Item = (), Error = String> {
+    checked_download(url, sha256)
+        .and_then(move |tmp| save_step(tmp_file_stream(tmp), output_path, format))
+}
+
+fn save_step<S>(
+    stream: S,
+    output_path: PathBuf,
+    format: ResourceFormat,
+) -> impl Future<Item = (), Error = String>
+where
+    S: Stream<Item = bytes::Bytes, Error = String>,
+{
     let dir_name = match format {
         ResourceFormat::Raw => output_path.parent().unwrap(),
-        ResourceFormat::Tar => output_path.as_ref(),
+        ResourceFormat::Tar | ResourceFormat::TarGz | ResourceFormat::Zip => output_path.as_ref(),
     };
 
     if !dir_name.exists() {
         async_try!(fs::create_dir_all(dir_name).map_err(|e| format!("creare dir {}", e)))
     }
 
+    future::Either::A(match format {
+        ResourceFormat::Raw => future::Either::A(
+            write_async(stream, output_path)
+                .map_err(|_| "writing downloaded file failed".to_string()),
+        ),
+        ResourceFormat::Tar => {
+            future::Either::B(future::Either::A(untar_to_dir(stream, output_path)))
+        }
+        ResourceFormat::TarGz => future::Either::B(future::Either::B(future::Either::A(
+            untar_to_dir(gunzip_stream(stream), output_path),
+        ))),
+        ResourceFormat::Zip => future::Either::B(future::Either::B(future::Either::B(
+            untar_to_dir(zip_to_tar_stream(stream), output_path),
+        ))),
+    })
+}
+
+/// Downloads `url` into a temporary file and checks its SHA-256 checksum
+/// (hex, optionally prefixed with `sha256:`).
+///
+/// The fetch is repeated on transport errors and checksum mismatches, up to
+/// `DOWNLOAD_ATTEMPTS` times.
+pub fn checked_download(
+    url: &str,
+    sha256: &str,
+) -> impl Future<Item = tempfile::NamedTempFile, Error = String> {
+    let url = url.to_owned();
+    let expected = sha256.trim_left_matches("sha256:").to_lowercase();
+
+    future::loop_fn(1, move |attempt| {
+        let url = url.clone();
+        checked_download_once(&url, expected.clone()).then(move |res| match res {
+            Ok(tmp) => Ok(future::Loop::Break(tmp)),
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                error!("download of {} failed (attempt {}): {}", url, attempt, e);
+                Ok(future::Loop::Continue(attempt + 1))
+            }
+            Err(e) => Err(format!(
+                "download of {} failed after {} attempts: {}",
+                url, attempt, e
+            )),
+        })
+    })
+}
+
+fn checked_download_once(
+    url: &str,
+    expected: String,
+) -> impl Future<Item = tempfile::NamedTempFile, Error = String> {
+    let stream = download_stream(url);
+
+    future::result(tempfile::NamedTempFile::new().map_err(|e| format!("download tmp file: {}", e)))
+        .and_then(|tmp| {
+            let path = tmp.path().to_owned();
+            write_async(stream, path.clone())
+                .and_then(move |()| sha256_hex(path))
+                .and_then(move |actual| match actual == expected {
+                    true => Ok(tmp),
+                    false => Err(format!(
+                        "checksum mismatch: expected sha256:{}, got sha256:{}",
+                        expected, actual
+                    )),
+                })
+        })
+}
+
+fn sha256_hex(path: PathBuf) -> impl Future<Item = String, Error = String> {
+    let digest = async_try!(gu_model::hash::digest("sha256", 256).map_err(|e| e.to_string()));
+
     future::Either::A(
-        client_request
-            .send()
-            .map_err(|e| format!("send download request: {}", e))
-            .and_then(move |resp| {
-                match format {
-                    ResourceFormat::Raw => future::Either::A(
-                        write_async(resp.payload(), output_path)
-                            .map_err(|_| "writing downloaded file failed".to_string()),
-                    ),
-                    ResourceFormat::Tar => future::Either::B(
-                        full::decode_tar(resp.payload())
-                            .map_err(|e| format!("tar: {}", e))
-                            .for_each(move |entry| {
-                                let entry_type = entry.header().entry_type().clone();
-                                let path: PathBuf = async_try!(entry
-                                    .header()
-                                    .path()
-                                    .map_err(|e| format!("payload err: {}", e)))
-                                .to_owned();
-                                eprintln!("tar-path:{}", path.display());
-
-                                if entry_type.is_dir() {
-                                    // is directory
-                                    use std::fs;
-                                    let dir_name = output_path.join(path);
-                                    if !dir_name.exists() {
-                                        let _ = async_try!(fs::create_dir_all(dir_name)
-                                            .map_err(|e| format!("io: {}", e)));
-                                    }
-                                    future::Either::B(future::ok(()))
-                                } else if entry_type.is_file() {
-                                    let out_file = output_path.join(path);
-                                    async_result!(write_async(entry, out_file))
-                                } else {
-                                    // if entry.header().path() { }
-                                    future::Either::B(future::ok(()))
-                                }
-                            }),
-                    ),
-                }
+        read_async(path)
+            .fold(digest, |mut digest, chunk| {
+                digest.input(chunk.as_ref());
+                Ok::<_, String>(digest)
+            })
+            .map(|digest| {
+                digest
+                    .result()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect()
             }),
     )
 }
 
+/// Streams the temporary file, which is removed once the stream is dropped.
+fn tmp_file_stream(
+    tmp: tempfile::NamedTempFile,
+) -> impl Stream<Item = bytes::Bytes, Error = String> {
+    let path = tmp.path().to_owned();
+    read_async(path).then(move |chunk| {
+        let _ = &tmp;
+        chunk
+    })
+}
+
+fn untar_to_dir<TarStream>(
+    stream: TarStream,
+    output_path: PathBuf,
+) -> impl Future<Item = (), Error = String>
+where
+    TarStream: Stream<Item = bytes::Bytes, Error = String>,
+{
+    use tar_async::decode::full;
+
+    full::decode_tar(stream)
+        .map_err(|e| format!("tar: {}", e))
+        .for_each(move |entry| {
+            let entry_type = entry.header().entry_type().clone();
+            let path: PathBuf = async_try!(entry
+                .header()
+                .path()
+                .map_err(|e| format!("payload err: {}", e)))
+            .to_owned();
+            eprintln!("tar-path:{}", path.display());
+
+            if entry_type.is_dir() {
+                // is directory
+                use std::fs;
+                let dir_name = output_path.join(path);
+                if !dir_name.exists() {
+                    let _ =
+                        async_try!(fs::create_dir_all(dir_name).map_err(|e| format!("io: {}", e)));
+                }
+                future::Either::B(future::ok(()))
+            } else if entry_type.is_file() {
+                let out_file = output_path.join(path);
+                async_result!(write_async(entry, out_file))
+            } else {
+                // if entry.header().path() { }
+                future::Either::B(future::ok(()))
+            }
+        })
+}
+
 pub fn upload_step(
     url: &str,
     input_path: PathBuf,
@@ -93,7 +220,9 @@ pub fn upload_step(
     );
     let source_stream: Box<dyn Stream<Item = bytes::Bytes, Error = String>> = match format {
         ResourceFormat::Tar => Box::new(stream_tar(input_path)),
+        ResourceFormat::TarGz => Box::new(gzip_stream(stream_tar(input_path))),
         ResourceFormat::Raw => Box::new(stream_raw(input_path)),
+        ResourceFormat::Zip => Box::new(tar_to_zip_stream(stream_tar(input_path))),
     };
     let url_desc = url.to_owned();
 
@@ -152,6 +281,145 @@ where
         })
 }
 
+/// Repacks zip archive stream into tar stream.
+///
+/// Zip keeps its index at the end of the archive, so the input is spooled
+/// into a temporary file (not memory) before its entries are streamed out.
+pub fn zip_to_tar_stream<ZipStream>(
+    stream: ZipStream,
+) -> impl Stream<Item = bytes::Bytes, Error = String>
+where
+    ZipStream: Stream<Item = bytes::Bytes, Error = String>,
+{
+    use gu_actix::pipe;
+    use std::{io, thread};
+
+    future::result(tempfile::NamedTempFile::new().map_err(|e| format!("zip tmp file: {}", e)))
+        .and_then(|tmp| {
+            let path = tmp.path().to_owned();
+            write_async(stream, path).and_then(move |()| Ok(tmp))
+        })
+        .and_then(|tmp| {
+            let file = tmp.reopen().map_err(|e| format!("zip tmp file: {}", e))?;
+            let (mut tx, rx) = pipe::sync_to_async(5);
+
+            thread::spawn(move || {
+                if let Err(e) = zip_to_tar(file, &mut tx) {
+                    let _ = tx.send(Err(io::Error::new(io::ErrorKind::Other, e)));
+                }
+                drop(tmp);
+            });
+
+            Ok(rx.map_err(|e| e.to_string()))
+        })
+        .flatten_stream()
+}
+
+fn zip_to_tar<W: io::Write>(input: fs::File, output: W) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(input).map_err(|e| format!("zip: {}", e))?;
+    let mut builder = tar::Builder::new(output);
+
+    for idx in 0..archive.len() {
+        let mut entry = archive.by_index(idx).map_err(|e| format!("zip: {}", e))?;
+        let path = entry.sanitized_name();
+        let is_dir = entry.name().ends_with('/');
+        let mut header = tar::Header::new_gnu();
+
+        if is_dir {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(entry.size());
+        }
+        header.set_mode(
+            entry
+                .unix_mode()
+                .unwrap_or(if is_dir { 0o755 } else { 0o644 }),
+        );
+        builder
+            .append_data(&mut header, path, &mut entry)
+            .map_err(|e| format!("tar: {}", e))?;
+    }
+
+    builder.finish().map_err(|e| format!("tar: {}", e))
+}
+
+/// Repacks tar archive stream into zip stream.
+///
+/// Zip writer needs to seek back to entry headers, so the archive is built
+/// in a temporary file and streamed out from there.
+pub fn tar_to_zip_stream<TarStream>(
+    stream: TarStream,
+) -> impl Stream<Item = bytes::Bytes, Error = String>
+where
+    TarStream: Stream<Item = bytes::Bytes, Error = String>,
+{
+    use futures::sync::oneshot;
+    use gu_actix::pipe::{self, WriteError};
+    use std::{io, thread};
+
+    future::result(tempfile::NamedTempFile::new().map_err(|e| format!("zip tmp file: {}", e)))
+        .and_then(|tmp| {
+            let file = tmp.reopen().map_err(|e| format!("zip tmp file: {}", e))?;
+            Ok((tmp, file))
+        })
+        .and_then(|(tmp, file)| {
+            let (tx, rx) = pipe::async_to_sync(5);
+            let (result_tx, result_rx) = oneshot::channel();
+
+            thread::spawn(move || {
+                let _ = result_tx.send(tar_to_zip(rx, file));
+            });
+
+            let feed = stream
+                .map_err(|e| WriteError::Other(io::Error::new(io::ErrorKind::Other, e)))
+                .forward(tx)
+                .map_err(|_| "tar stream broken".to_string());
+            let zip = result_rx
+                .map_err(|_| "zip writer failed".to_string())
+                .flatten();
+
+            zip.join(feed).and_then(move |_| {
+                let path = tmp.path().to_owned();
+                Ok(read_async(path).then(move |chunk| {
+                    let _ = &tmp;
+                    chunk
+                }))
+            })
+        })
+        .flatten_stream()
+}
+
+fn tar_to_zip<R: io::Read>(input: R, output: fs::File) -> Result<(), String> {
+    use zip::{write::FileOptions, ZipWriter};
+
+    let mut archive = tar::Archive::new(input);
+    let mut zip = ZipWriter::new(output);
+
+    for entry in archive.entries().map_err(|e| format!("tar: {}", e))? {
+        let mut entry = entry.map_err(|e| format!("tar: {}", e))?;
+        let path = entry
+            .path()
+            .map_err(|e| format!("tar: {}", e))?
+            .to_string_lossy()
+            .into_owned();
+        let entry_type = entry.header().entry_type();
+
+        if entry_type.is_dir() {
+            zip.add_directory(path, FileOptions::default())
+                .map_err(|e| format!("zip: {}", e))?;
+        } else if entry_type.is_file() {
+            zip.start_file(path, FileOptions::default())
+                .map_err(|e| format!("zip: {}", e))?;
+            io::copy(&mut entry, &mut zip).map_err(|e| format!("zip: {}", e))?;
+        }
+    }
+
+    zip.finish().map_err(|e| format!("zip: {}", e))?;
+    Ok(())
+}
+
 // TODO: support redirect
 // TODO: support https
 pub fn download(
@@ -205,21 +473,23 @@ fn response_to_tarred_stream<P>(
 where
     P: AsRef<Path>,
 {
-    let header = content_length(&resp).and_then(|length| {
-        let mut header = tar::Header::new_ustar();
-        header.set_size(length);
-        header
-            .set_path(path)
-            .map_err(|_| "Incorrect filepath - cannot be set as filepath in tar".to_string())?;
-        header.set_cksum();
-
-        let header: &[u8] = header.as_bytes();
-        Ok(bytes::Bytes::from(header))
-    });
+    let header = content_length(&resp).and_then(|length| tar_header(length, path));
 
     futures::stream::once(header).chain(resp.payload().map_err(|e| e.to_string()))
 }
 
+fn tar_header<P: AsRef<Path>>(length: u64, path: P) -> Result<bytes::Bytes, String> {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(length);
+    header
+        .set_path(path)
+        .map_err(|_| "Incorrect filepath - cannot be set as filepath in tar".to_string())?;
+    header.set_cksum();
+
+    let header: &[u8] = header.as_bytes();
+    Ok(bytes::Bytes::from(header))
+}
+
 fn response_to_stream(
     resp: ClientResponse,
 ) -> impl Stream<Item = bytes::Bytes, Error = String> + 'static {
@@ -263,6 +533,39 @@ where
     })
 }
 
+/// Like `download_stream`, but the content is fetched and checked first (see `checked_download`).
+pub fn checked_download_stream(
+    url: &str,
+    sha256: &str,
+) -> impl Stream<Item = bytes::Bytes, Error = String> + 'static {
+    checked_download(url, sha256)
+        .map(tmp_file_stream)
+        .flatten_stream()
+}
+
+/// Like `tarred_download_stream`, but the content is fetched and checked first
+/// (see `checked_download`).
+pub fn checked_tarred_download_stream<P>(
+    url: &str,
+    sha256: &str,
+    filename: P,
+) -> impl Stream<Item = bytes::Bytes, Error = String> + 'static
+where
+    P: AsRef<Path> + 'static,
+{
+    checked_download(url, sha256)
+        .and_then(move |tmp| {
+            let length = tmp
+                .as_file()
+                .metadata()
+                .map_err(|e| format!("download tmp file: {}", e))?
+                .len();
+            let header = tar_header(length, filename)?;
+            Ok(futures::stream::once(Ok(header)).chain(tmp_file_stream(tmp)))
+        })
+        .flatten_stream()
+}
+
 pub fn untgz<P: AsRef<Path> + ToOwned>(
     input_path: P,
     output_path: P,
@@ -275,3 +578,21 @@ pub fn untgz<P: AsRef<Path> + ToOwned>(
 
     untgz_async(input_path, output_path)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gzip_round_trip() {
+        let data: Vec<bytes::Bytes> = (0..100)
+            .map(|i| bytes::Bytes::from(format!("line {}\n", i)))
+            .collect();
+        let expected: Vec<u8> = data.iter().flat_map(|chunk| chunk.to_vec()).collect();
+
+        let compressed = gzip_stream(futures::stream::iter_ok(data));
+        let decompressed = gunzip_stream(compressed).concat2().wait().unwrap();
+
+        assert_eq!(decompressed.as_ref(), expected.as_slice());
+    }
+}
@@ -4,16 +4,28 @@ use super::provision::untgz;
 use gu_actix::{async_result, async_try, prelude::*};
 use gu_model::dockerman::VolumeDef;
 use gu_persist::config::ConfigModule;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde_json::Value;
 use std::collections::{BTreeSet, HashSet};
 use std::fs;
 use std::fs::DirBuilder;
 use std::io;
 use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
+/// how often sizes of session workspaces are recomputed
+pub const DISK_USAGE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What happens on startup to workspaces left by previous runs of the provider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retention {
+    KeepAll,
+    /// removes workspaces not modified for the given time
+    RemoveOlderThan(Duration),
+}
+
 pub struct WorkspacesManager {
     namespace: &'static str,
     path: PathBuf,
@@ -21,20 +33,73 @@ pub struct WorkspacesManager {
 
 impl WorkspacesManager {
     pub fn new(config: &ConfigModule, name: &'static str) -> Option<WorkspacesManager> {
-        let session_dir = config.work_dir().to_path_buf().join("sessions").join(name);
+        Self::with_root(Self::default_root(config), name, Retention::KeepAll)
+    }
+
+    /// Keeps workspaces in `root`/`name`, applying `retention` to the ones already there.
+    pub fn with_root<P: AsRef<Path>>(
+        root: P,
+        name: &'static str,
+        retention: Retention,
+    ) -> Option<WorkspacesManager> {
+        let session_dir = root.as_ref().join(name);
 
-        fs::create_dir_all(&session_dir)
-            .map_err(|e| error!("Cannot create HdMan dir: {:?}", e))
+        let manager = fs::create_dir_all(&session_dir)
+            .map_err(|e| error!("Cannot create {} workspaces dir: {:?}", name, e))
             .map(|_| WorkspacesManager {
                 namespace: name,
                 path: session_dir,
             })
-            .ok()
+            .ok()?;
+
+        if let Retention::RemoveOlderThan(max_age) = retention {
+            match manager.reclaim(max_age) {
+                Ok(removed) => info!("removed {} stale {} workspaces", removed, name),
+                Err(e) => warn!("cannot remove stale {} workspaces: {}", name, e),
+            }
+        }
+        Some(manager)
+    }
+
+    /// Directory keeping workspaces of this kind.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn default_root(config: &ConfigModule) -> PathBuf {
+        config.work_dir().join("sessions")
     }
 
     pub fn workspace(&self) -> Workspace {
         Workspace::new(self.namespace, self.path.join(Uuid::new_v4().to_string()))
     }
+
+    /// Removes workspaces not modified for `max_age`, e.g. left by sessions of
+    /// a crashed provider. Workspaces of live sessions are not recognized, so
+    /// it is meant to run before any session is created.
+    pub fn reclaim(&self, max_age: Duration) -> io::Result<usize> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+            if !metadata.is_dir() || age < max_age {
+                continue;
+            }
+            debug!(
+                "removing workspace {:?}, unused for {:?}",
+                entry.path(),
+                age
+            );
+            match fs::remove_dir_all(entry.path()) {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("cannot remove workspace {:?}: {}", entry.path(), e),
+            }
+        }
+        Ok(removed)
+    }
 }
 
 type Set<K> = BTreeSet<K>;
@@ -42,40 +107,73 @@ type Set<K> = BTreeSet<K>;
 #[derive(Clone)]
 pub struct Workspace {
     name: &'static str,
+    /// name given by the requestor; `name` is used when not set
+    label: Option<String>,
+    /// url of the image the session was created from
+    image_url: Option<String>,
     path: PathBuf,
     metadata: Value,
     tags: Set<String>,
     volumes: HashSet<VolumeDef>,
+    disk_usage: Option<u64>,
 }
 
 impl Workspace {
     pub(self) fn new(name: &'static str, path: PathBuf) -> Self {
         Self {
             name,
+            label: None,
+            image_url: None,
             path,
             metadata: Value::Null,
             tags: Set::new(),
             volumes: HashSet::new(),
+            disk_usage: None,
         }
     }
 
-    pub fn name(&self) -> &'static str {
-        &self.name
+    pub fn name(&self) -> &str {
+        self.label.as_ref().map(String::as_str).unwrap_or(self.name)
+    }
+
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.label = Some(name.into());
+    }
+
+    pub fn image_url(&self) -> Option<&str> {
+        self.image_url.as_ref().map(String::as_str)
+    }
+
+    pub fn set_image_url<S: Into<String>>(&mut self, image_url: S) {
+        self.image_url = Some(image_url.into());
     }
 
     pub fn tags(&self) -> Vec<String> {
         Vec::from_iter(self.tags.iter().cloned())
     }
 
-    pub fn add_tags<T: IntoIterator<Item = String>>(&mut self, tags: T) {
+    pub fn add_tags<T: IntoIterator>(&mut self, tags: T)
+    where
+        T::Item: Into<String>,
+    {
         for tag in tags {
-            self.tags.insert(tag);
+            self.tags.insert(tag.into());
         }
     }
 
-    pub fn remove_tags<T: IntoIterator<Item = String>>(&mut self, tags: T) {
+    pub fn set_tags<T: IntoIterator>(&mut self, tags: T)
+    where
+        T::Item: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+    }
+
+    pub fn remove_tags<T: IntoIterator>(&mut self, tags: T)
+    where
+        T::Item: Into<String>,
+    {
         for tag in tags {
-            self.tags.remove(&tag);
+            self.tags.remove(&tag.into());
         }
     }
 
@@ -123,13 +221,74 @@ impl Workspace {
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
+
+    /// Joins `path` onto the workspace directory. Fails for paths leading outside
+    /// of it: absolute ones, ones with `..` and ones going through a symlink.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        let path = path.as_ref();
+        let outside = || {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("path outside of the workspace: {:?}", path),
+            )
+        };
+
+        let relative = path.components().all(|c| match c {
+            Component::Normal(_) | Component::CurDir => true,
+            _ => false,
+        });
+        if !relative {
+            return Err(outside());
+        }
+
+        let resolved = self.path.join(path);
+        // symlinks are followed up to the deepest existing part of the path
+        if let Some(existing) = resolved.ancestors().find(|p| p.exists()) {
+            if !existing
+                .canonicalize()?
+                .starts_with(self.path.canonicalize()?)
+            {
+                return Err(outside());
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// size of the workspace in bytes, as of the last `refresh_disk_usage`
+    pub fn disk_usage(&self) -> Option<u64> {
+        self.disk_usage
+    }
+
+    pub fn refresh_disk_usage(&mut self) {
+        match dir_size(&self.path) {
+            Ok(size) => self.disk_usage = Some(size),
+            Err(e) => debug!("cannot compute size of {:?}: {}", self.path, e),
+        }
+    }
+}
+
+/// sums sizes of files in the directory tree; symlinks are not followed
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::workspace::Workspace;
+    use crate::workspace::{Retention, Workspace, WorkspacesManager};
     use gu_model::dockerman::VolumeDef;
+    use std::fs;
     use std::path::PathBuf;
+    use std::time::Duration;
 
     #[test]
     fn create_dirs() {
@@ -151,6 +310,69 @@ mod tests {
         assert!(&PathBuf::from(path).join("test2").exists());
     }
 
+    #[test]
+    fn disk_usage() {
+        let path = "/tmp/gu-unlimited/tests-disk-usage";
+        let mut work = Workspace::new("work", path.into());
+        work.add_volume(VolumeDef::BindRw {
+            src: "sub".to_string(),
+            target: "".to_string(),
+        });
+        work.create_dirs().unwrap();
+        fs::write(PathBuf::from(path).join("a"), [0u8; 10]).unwrap();
+        fs::write(PathBuf::from(path).join("sub").join("b"), [0u8; 5]).unwrap();
+
+        assert_eq!(work.disk_usage(), None);
+        work.refresh_disk_usage();
+        assert_eq!(work.disk_usage(), Some(15));
+    }
+
+    #[test]
+    fn reclaim() {
+        let root = "/tmp/gu-unlimited/tests-reclaim";
+        let _ = fs::remove_dir_all(root);
+        let manager = WorkspacesManager::with_root(root, "work", Retention::KeepAll).unwrap();
+        let work = manager.workspace();
+        work.create_dirs().unwrap();
+
+        let hour = Duration::from_secs(3600);
+        WorkspacesManager::with_root(root, "work", Retention::RemoveOlderThan(hour)).unwrap();
+        assert!(work.path().exists());
+
+        WorkspacesManager::with_root(
+            root,
+            "work",
+            Retention::RemoveOlderThan(Duration::from_secs(0)),
+        )
+        .unwrap();
+        assert!(!work.path().exists());
+        assert!(PathBuf::from(root).join("work").exists());
+    }
+
+    #[test]
+    fn resolve() {
+        let path = "/tmp/gu-unlimited/tests-resolve";
+        let _ = fs::remove_dir_all(path);
+        let work = Workspace::new("work", path.into());
+        work.create_dirs().unwrap();
+        fs::create_dir(PathBuf::from(path).join("sub")).unwrap();
+
+        assert_eq!(
+            work.resolve("sub/new").unwrap(),
+            PathBuf::from(path).join("sub/new")
+        );
+        assert!(work.resolve("/").is_err());
+        assert!(work.resolve("/etc/passwd").is_err());
+        assert!(work.resolve("sub/../..").is_err());
+        assert!(work.resolve("../tests").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", PathBuf::from(path).join("etc")).unwrap();
+            assert!(work.resolve("etc/passwd").is_err());
+        }
+    }
+
     #[test]
     fn tags() {
         let path = "/tmp/gu-unlimited/tests";